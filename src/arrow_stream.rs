@@ -0,0 +1,117 @@
+//! Exports record batches through the Arrow C stream interface, see
+//! <https://arrow.apache.org/docs/format/CStreamInterface.html>.
+//!
+//! arrow 42 only ships the array and schema halves of the C data interface
+//! in `arrow-data` and `arrow-schema`, the stream itself lives here.
+
+use std::ffi::{c_char, c_int, c_void, CString};
+use std::ptr;
+
+use arrow_array::{Array, RecordBatch, StructArray};
+use arrow_data::ffi::FFI_ArrowArray;
+use arrow_schema::ffi::FFI_ArrowSchema;
+use arrow_schema::SchemaRef;
+
+const EIO: c_int = 5;
+
+/// ABI compatible `struct ArrowArrayStream`.
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct FFI_ArrowArrayStream {
+    get_schema: Option<unsafe extern "C" fn(*mut Self, *mut FFI_ArrowSchema) -> c_int>,
+    get_next: Option<unsafe extern "C" fn(*mut Self, *mut FFI_ArrowArray) -> c_int>,
+    get_last_error: Option<unsafe extern "C" fn(*mut Self) -> *const c_char>,
+    release: Option<unsafe extern "C" fn(*mut Self)>,
+    private_data: *mut c_void,
+}
+
+// The private data is only touched through the callbacks, which the C stream
+// interface does not allow to be called concurrently.
+unsafe impl Send for FFI_ArrowArrayStream {}
+
+struct StreamPrivateData {
+    schema: SchemaRef,
+    batches: std::vec::IntoIter<RecordBatch>,
+    last_error: Option<CString>,
+}
+
+impl FFI_ArrowArrayStream {
+    /// A stream yielding `batches`, which all need to have `schema`.
+    pub fn new(schema: SchemaRef, batches: Vec<RecordBatch>) -> Self {
+        let private_data = Box::new(StreamPrivateData {
+            schema,
+            batches: batches.into_iter(),
+            last_error: None,
+        });
+        Self {
+            get_schema: Some(get_schema),
+            get_next: Some(get_next),
+            get_last_error: Some(get_last_error),
+            release: Some(release_stream),
+            private_data: Box::into_raw(private_data) as *mut c_void,
+        }
+    }
+}
+
+impl Drop for FFI_ArrowArrayStream {
+    fn drop(&mut self) {
+        // A consumer that moved the stream out has set `release` to null
+        if let Some(release) = self.release {
+            unsafe { release(self) };
+        }
+    }
+}
+
+unsafe fn private_data<'a>(stream: *mut FFI_ArrowArrayStream) -> &'a mut StreamPrivateData {
+    &mut *((*stream).private_data as *mut StreamPrivateData)
+}
+
+unsafe extern "C" fn get_schema(
+    stream: *mut FFI_ArrowArrayStream,
+    out: *mut FFI_ArrowSchema,
+) -> c_int {
+    let data = private_data(stream);
+    match FFI_ArrowSchema::try_from(data.schema.as_ref()) {
+        Ok(schema) => {
+            ptr::write(out, schema);
+            0
+        }
+        Err(e) => {
+            data.last_error = CString::new(e.to_string()).ok();
+            EIO
+        }
+    }
+}
+
+unsafe extern "C" fn get_next(
+    stream: *mut FFI_ArrowArrayStream,
+    out: *mut FFI_ArrowArray,
+) -> c_int {
+    let data = private_data(stream);
+    // A released (empty) array marks the end of the stream
+    let array = match data.batches.next() {
+        Some(batch) => FFI_ArrowArray::new(&StructArray::from(batch).to_data()),
+        None => FFI_ArrowArray::empty(),
+    };
+    ptr::write(out, array);
+    0
+}
+
+unsafe extern "C" fn get_last_error(stream: *mut FFI_ArrowArrayStream) -> *const c_char {
+    match &private_data(stream).last_error {
+        Some(e) => e.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+unsafe extern "C" fn release_stream(stream: *mut FFI_ArrowArrayStream) {
+    if stream.is_null() {
+        return;
+    }
+    let stream = &mut *stream;
+    drop(Box::from_raw(stream.private_data as *mut StreamPrivateData));
+    stream.get_schema = None;
+    stream.get_next = None;
+    stream.get_last_error = None;
+    stream.release = None;
+}
@@ -0,0 +1,5 @@
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    ExitCode::from(timsrust_pyo3::convert::run(std::env::args_os()))
+}
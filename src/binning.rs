@@ -0,0 +1,271 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyByteArray, PyDict, PyString};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::timsrust_readers::PySpectrumReader;
+use crate::timsrust_structs::PySpectrum;
+
+/// Fixed width m/z bins from `min_mz` (inclusive) to `max_mz` (exclusive).
+/// Frame grids use the same bins for 1/K0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BinningParams {
+    pub bin_width: f64,
+    pub min_mz: f64,
+    pub max_mz: f64,
+}
+
+impl BinningParams {
+    pub fn new(bin_width: f64, min_mz: f64, max_mz: f64) -> PyResult<Self> {
+        if bin_width.is_nan() || bin_width <= 0.0 {
+            return Err(PyValueError::new_err("bin_width must be positive"));
+        }
+        if max_mz.is_nan() || min_mz.is_nan() || max_mz <= min_mz {
+            return Err(PyValueError::new_err("max_mz must be above min_mz"));
+        }
+        Ok(BinningParams {
+            bin_width,
+            min_mz,
+            max_mz,
+        })
+    }
+
+    pub fn num_bins(&self) -> usize {
+        ((self.max_mz - self.min_mz) / self.bin_width).ceil() as usize
+    }
+
+    pub fn bin_index(&self, value: f64) -> Option<usize> {
+        if value < self.min_mz || value >= self.max_mz {
+            return None;
+        }
+        Some((((value - self.min_mz) / self.bin_width) as usize).min(self.num_bins() - 1))
+    }
+
+    pub fn bin_centers(&self) -> Vec<f64> {
+        (0..self.num_bins())
+            .map(|i| self.min_mz + (i as f64 + 0.5) * self.bin_width)
+            .collect()
+    }
+}
+
+/// A numpy array of `dtype` from the little endian bytes of its `values`.
+pub fn numpy_array<'py, const N: usize>(
+    py: Python<'py>,
+    values: impl ExactSizeIterator<Item = [u8; N]>,
+    dtype: &str,
+) -> PyResult<Bound<'py, PyAny>> {
+    let buffer = PyByteArray::new_with(py, values.len() * N, |buffer| {
+        for (chunk, value) in buffer.chunks_exact_mut(N).zip(values) {
+            chunk.copy_from_slice(&value);
+        }
+        Ok(())
+    })?;
+    frombuffer(py, buffer, dtype)
+}
+
+/// A float64 numpy array of `len` zeros, except for the (position, value)
+/// pairs of `values`.
+pub fn dense_numpy_array<'py>(
+    py: Python<'py>,
+    len: usize,
+    values: impl Iterator<Item = (usize, f64)>,
+) -> PyResult<Bound<'py, PyAny>> {
+    // New bytearrays are zeroed
+    let buffer = PyByteArray::new_with(py, len * 8, |buffer| {
+        for (position, value) in values {
+            buffer[position * 8..(position + 1) * 8].copy_from_slice(&value.to_le_bytes());
+        }
+        Ok(())
+    })?;
+    frombuffer(py, buffer, "<f8")
+}
+
+/// The array shares the writable buffer of the bytearray, so nothing is copied.
+fn frombuffer<'py>(
+    py: Python<'py>,
+    buffer: Bound<'py, PyByteArray>,
+    dtype: &str,
+) -> PyResult<Bound<'py, PyAny>> {
+    py.import("numpy")?
+        .getattr("frombuffer")?
+        .call1((buffer, dtype))
+}
+
+/// Non-empty bins of a spectrum as (bin, summed intensity), by bin.
+fn bin_spectrum(spectrum: &PySpectrum, params: &BinningParams) -> Vec<(u32, f64)> {
+    let mut bins: Vec<(u32, f64)> = spectrum
+        .mz_values
+        .iter()
+        .zip(spectrum.intensities.iter())
+        .filter_map(|(mz, intensity)| Some((params.bin_index(*mz)? as u32, *intensity)))
+        .collect();
+    bins.sort_by_key(|x| x.0);
+    bins.dedup_by(|next, kept| {
+        let same_bin = next.0 == kept.0;
+        if same_bin {
+            kept.1 += next.1;
+        }
+        same_bin
+    });
+    bins
+}
+
+/// Binned spectra as a sparse matrix in CSR layout, one row per spectrum and
+/// one column per m/z bin. Intensities of peaks in the same bin are summed.
+#[pyclass(name = "BinnedSpectra")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PyBinnedSpectra {
+    /// Index of the spectrum of every row.
+    #[pyo3(get)]
+    pub spectrum_indices: Vec<usize>,
+    /// Row `i` is stored in `indices[indptr[i]..indptr[i + 1]]` and `data`.
+    #[pyo3(get)]
+    pub indptr: Vec<u64>,
+    #[pyo3(get)]
+    pub indices: Vec<u32>,
+    #[pyo3(get)]
+    pub data: Vec<f64>,
+    #[pyo3(get)]
+    pub bin_width: f64,
+    #[pyo3(get)]
+    pub min_mz: f64,
+    #[pyo3(get)]
+    pub max_mz: f64,
+    #[pyo3(get)]
+    pub num_bins: usize,
+}
+
+impl PyBinnedSpectra {
+    pub fn from_spectra(spectra: &[PySpectrum], params: &BinningParams) -> Self {
+        let rows: Vec<Vec<(u32, f64)>> = spectra
+            .par_iter()
+            .map(|x| bin_spectrum(x, params))
+            .collect();
+        let mut indptr = vec![0];
+        let mut indices = vec![];
+        let mut data = vec![];
+        for row in rows {
+            for (bin, intensity) in row {
+                indices.push(bin);
+                data.push(intensity);
+            }
+            indptr.push(indices.len() as u64);
+        }
+        PyBinnedSpectra {
+            spectrum_indices: spectra.iter().map(|x| x.index).collect(),
+            indptr,
+            indices,
+            data,
+            bin_width: params.bin_width,
+            min_mz: params.min_mz,
+            max_mz: params.max_mz,
+            num_bins: params.num_bins(),
+        }
+    }
+}
+
+#[pymethods]
+impl PyBinnedSpectra {
+    pub fn __len__(&self) -> usize {
+        self.spectrum_indices.len()
+    }
+
+    pub fn __repr__(slf: &Bound<'_, Self>) -> PyResult<String> {
+        let class_name: Bound<'_, PyString> = slf.get_type().qualname()?;
+        let x = slf.borrow();
+        Ok(format!(
+            "{}(num_spectra={}, num_bins={}, bin_width={}, min_mz={}, max_mz={}, nnz={})",
+            class_name,
+            x.spectrum_indices.len(),
+            x.num_bins,
+            x.bin_width,
+            x.min_mz,
+            x.max_mz,
+            x.data.len(),
+        ))
+    }
+
+    #[getter]
+    pub fn shape(&self) -> (usize, usize) {
+        (self.spectrum_indices.len(), self.num_bins)
+    }
+
+    /// Center m/z of every bin.
+    pub fn bin_centers(&self) -> Vec<f64> {
+        BinningParams {
+            bin_width: self.bin_width,
+            min_mz: self.min_mz,
+            max_mz: self.max_mz,
+        }
+        .bin_centers()
+    }
+
+    /// A `scipy.sparse.csr_matrix` of float64 intensities.
+    pub fn to_scipy<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let sparse = py.import("scipy.sparse")?;
+        let data = numpy_array(py, self.data.iter().map(|x| x.to_le_bytes()), "<f8")?;
+        let indices = numpy_array(
+            py,
+            self.indices.iter().map(|x| (*x as i32).to_le_bytes()),
+            "<i4",
+        )?;
+        let indptr = numpy_array(
+            py,
+            self.indptr.iter().map(|x| (*x as i64).to_le_bytes()),
+            "<i8",
+        )?;
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("shape", self.shape())?;
+        sparse
+            .getattr("csr_matrix")?
+            .call(((data, indices, indptr),), Some(&kwargs))
+    }
+
+    /// A dense float64 numpy array of shape (num_spectra, num_bins).
+    pub fn to_numpy<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let values = self
+            .indptr
+            .windows(2)
+            .enumerate()
+            .flat_map(|(row, bounds)| {
+                (bounds[0] as usize..bounds[1] as usize)
+                    .map(move |i| (row * self.num_bins + self.indices[i] as usize, self.data[i]))
+            });
+        dense_numpy_array(py, self.spectrum_indices.len() * self.num_bins, values)?
+            .call_method1("reshape", (self.shape(),))
+    }
+}
+
+/// Binned spectra of a spectrum reader, `batch_size` spectra at a time.
+#[pyclass(name = "BinnedSpectraIterator")]
+pub struct PyBinnedSpectraIterator {
+    pub reader: Py<PySpectrumReader>,
+    pub params: BinningParams,
+    pub batch_size: usize,
+    pub i: usize,
+}
+
+#[pymethods]
+impl PyBinnedSpectraIterator {
+    pub fn __len__(&self, py: Python<'_>) -> usize {
+        self.reader.borrow(py).len().div_ceil(self.batch_size)
+    }
+
+    pub fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    pub fn __next__(
+        mut slf: PyRefMut<'_, Self>,
+        py: Python<'_>,
+    ) -> PyResult<Option<PyBinnedSpectra>> {
+        let start = slf.i;
+        let end = (start + slf.batch_size).min(slf.reader.borrow(py).len());
+        if start >= end {
+            return Ok(None);
+        }
+        slf.i = end;
+        let spectra = slf.reader.borrow(py).read_spectra(start..end)?;
+        Ok(Some(PyBinnedSpectra::from_spectra(&spectra, &slf.params)))
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt::Display;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+const ELEMENTARY_CHARGE: f64 = 1.602_176_634e-19;
+const BOLTZMANN: f64 = 1.380_649e-23;
+/// Gas number density at 273.15 K and 101.325 kPa, in m^-3.
+const LOSCHMIDT: f64 = 2.686_780_111e25;
+const DALTON: f64 = 1.660_539_066_60e-27;
+
+/// Drift gas of the CCS calculation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[pyclass(eq, eq_int, name = "DriftGas")]
+pub enum PyDriftGas {
+    #[pyo3(name = "N2")]
+    N2,
+    #[pyo3(name = "He")]
+    He,
+    #[pyo3(name = "Ar")]
+    Ar,
+    #[pyo3(name = "CO2")]
+    CO2,
+}
+
+impl Display for PyDriftGas {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                PyDriftGas::N2 => "N2",
+                PyDriftGas::He => "He",
+                PyDriftGas::Ar => "Ar",
+                PyDriftGas::CO2 => "CO2",
+            }
+        )
+    }
+}
+
+#[pymethods]
+impl PyDriftGas {
+    /// Monoisotopic mass in Da.
+    #[getter]
+    pub fn mass(&self) -> f64 {
+        match self {
+            PyDriftGas::N2 => 28.006_148,
+            PyDriftGas::He => 4.002_603,
+            PyDriftGas::Ar => 39.962_383,
+            PyDriftGas::CO2 => 43.989_829,
+        }
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!("DriftGas.{}", self)
+    }
+}
+
+/// Mason-Schamp factor such that ccs = factor * 1/K0, with the ccs in Å²
+/// and 1/K0 in V·s/cm². The ion mass is taken as m/z times charge.
+fn mason_schamp_factor(mz: f64, charge: usize, gas: PyDriftGas, temperature: f64) -> f64 {
+    let ion_mass = mz * charge as f64;
+    let reduced_mass = ion_mass * gas.mass() / (ion_mass + gas.mass()) * DALTON;
+    let ccs_m2 = 3.0 * ELEMENTARY_CHARGE * charge as f64 / (16.0 * LOSCHMIDT)
+        * (2.0 * std::f64::consts::PI / (reduced_mass * BOLTZMANN * temperature)).sqrt();
+    // 1/K0 from V·s/cm² to V·s/m² and the ccs from m² to Å²
+    ccs_m2 * 1e4 * 1e20
+}
+
+/// Collision cross section in Å² of an ion with reduced mobility `im` (1/K0).
+pub fn im_to_ccs(im: f64, mz: f64, charge: usize, gas: PyDriftGas, temperature: f64) -> f64 {
+    im * mason_schamp_factor(mz, charge, gas, temperature)
+}
+
+/// Reduced mobility (1/K0) of an ion with collision cross section `ccs` in Å².
+pub fn ccs_to_im(ccs: f64, mz: f64, charge: usize, gas: PyDriftGas, temperature: f64) -> f64 {
+    ccs / mason_schamp_factor(mz, charge, gas, temperature)
+}
+
+/// Applies `f` elementwise over equally long `values`, `mzs` and `charges`.
+pub fn map_ccs(
+    values: &[f64],
+    mzs: &[f64],
+    charges: &[usize],
+    f: impl Fn(f64, f64, usize) -> f64,
+) -> PyResult<Vec<f64>> {
+    if values.len() != mzs.len() || values.len() != charges.len() {
+        return Err(PyValueError::new_err(format!(
+            "Lengths differ: {} values, {} mzs and {} charges",
+            values.len(),
+            mzs.len(),
+            charges.len()
+        )));
+    }
+    if charges.contains(&0) {
+        return Err(PyValueError::new_err("Charges must be positive"));
+    }
+    Ok(values
+        .iter()
+        .zip(mzs)
+        .zip(charges)
+        .map(|((value, mz), charge)| f(*value, *mz, *charge))
+        .collect())
+}
+
+/// Collision cross sections in Å² (Mason-Schamp) of reduced mobilities in
+/// V·s/cm², given the m/z and charge of each ion.
+#[pyfunction(name = "im_to_ccs")]
+#[pyo3(signature = (ims, mzs, charges, gas=PyDriftGas::N2, temperature=305.0))]
+pub fn py_im_to_ccs(
+    ims: Vec<f64>,
+    mzs: Vec<f64>,
+    charges: Vec<usize>,
+    gas: PyDriftGas,
+    temperature: f64,
+) -> PyResult<Vec<f64>> {
+    map_ccs(&ims, &mzs, &charges, |im, mz, charge| {
+        im_to_ccs(im, mz, charge, gas, temperature)
+    })
+}
+
+/// Reduced mobilities in V·s/cm² of collision cross sections in Å², the
+/// inverse of `im_to_ccs`.
+#[pyfunction(name = "ccs_to_im")]
+#[pyo3(signature = (ccs, mzs, charges, gas=PyDriftGas::N2, temperature=305.0))]
+pub fn py_ccs_to_im(
+    ccs: Vec<f64>,
+    mzs: Vec<f64>,
+    charges: Vec<usize>,
+    gas: PyDriftGas,
+    temperature: f64,
+) -> PyResult<Vec<f64>> {
+    map_ccs(&ccs, &mzs, &charges, |ccs, mz, charge| {
+        ccs_to_im(ccs, mz, charge, gas, temperature)
+    })
+}
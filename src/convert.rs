@@ -0,0 +1,685 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use clap::{Parser, ValueEnum};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde_json::{json, Value};
+use timsrust::readers::{
+    FrameReader, FrameWindowSplittingConfiguration, QuadWindowExpansionStrategy,
+    SpectrumProcessingParams, SpectrumReaderConfig,
+};
+
+use crate::dataframes::{frames_table, spectra_table, Column, ColumnTable};
+use crate::timsrust_enums::PyMSLevel;
+use crate::timsrust_readers::{PySpectrumReader, SpectrumReadError};
+use crate::timsrust_structs::{PyFrame, PySpectrum};
+
+/// Spectra (or frames) are read in parallel and written in chunks of this size.
+const CHUNK_SIZE: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    Mgf,
+    #[value(name = "mzml")]
+    MzML,
+    Parquet,
+    Json,
+}
+
+impl OutputFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "mgf" => Some(OutputFormat::Mgf),
+            "mzml" => Some(OutputFormat::MzML),
+            "parquet" => Some(OutputFormat::Parquet),
+            "json" => Some(OutputFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Mgf => "mgf",
+            OutputFormat::MzML => "mzML",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Json => "json",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum QuadExpansion {
+    None,
+    Even,
+    UniformScan,
+    UniformMobility,
+}
+
+/// Failure classes of a conversion, each with its own exit code.
+#[derive(Debug)]
+pub enum ConvertError {
+    InvalidArguments(String),
+    Open(String),
+    Read(String),
+    Write(String),
+}
+
+impl ConvertError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ConvertError::InvalidArguments(_) => 2,
+            ConvertError::Open(_) => 3,
+            ConvertError::Read(_) => 4,
+            ConvertError::Write(_) => 5,
+        }
+    }
+
+    fn from_open(e: SpectrumReadError) -> Self {
+        match e {
+            SpectrumReadError::InvalidConfig(_) => ConvertError::InvalidArguments(e.to_string()),
+            _ => ConvertError::Open(e.to_string()),
+        }
+    }
+}
+
+impl Display for ConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConvertError::InvalidArguments(e) => write!(f, "Invalid arguments: {}", e),
+            ConvertError::Open(e) => write!(f, "Could not open input: {}", e),
+            ConvertError::Read(e) => write!(f, "Could not read input: {}", e),
+            ConvertError::Write(e) => write!(f, "Could not write output: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for ConvertError {
+    fn from(e: std::io::Error) -> Self {
+        ConvertError::Write(e.to_string())
+    }
+}
+
+impl From<ParquetError> for ConvertError {
+    fn from(e: ParquetError) -> Self {
+        ConvertError::Write(e.to_string())
+    }
+}
+
+impl From<arrow_schema::ArrowError> for ConvertError {
+    fn from(e: arrow_schema::ArrowError) -> Self {
+        ConvertError::Write(e.to_string())
+    }
+}
+
+/// Convert a bruker .d folder or .ms2 minitdf folder to MGF, mzML, Parquet or JSON.
+///
+/// Exit codes: 0 success, 2 invalid arguments, 3 input could not be opened,
+/// 4 input could not be read, 5 output could not be written.
+#[derive(Debug, Parser)]
+#[command(name = "timsrust-convert", version)]
+pub struct Cli {
+    /// Input .d or .ms2 folder
+    pub input: PathBuf,
+    /// Output file, defaults to the input with the extension of the format
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Output format, inferred from the output extension when not given
+    #[arg(short, long, value_enum)]
+    pub format: Option<OutputFormat>,
+    /// Write raw frames instead of spectra (parquet and json only)
+    #[arg(long)]
+    pub frames: bool,
+    #[arg(long, default_value_t = 1)]
+    pub smoothing_window: u32,
+    #[arg(long, default_value_t = 1)]
+    pub centroiding_window: u32,
+    #[arg(long)]
+    pub calibrate: bool,
+    #[arg(long, default_value_t = 0.1)]
+    pub calibration_tolerance: f64,
+    /// How quadrupole windows are split into spectra
+    #[arg(long, value_enum, default_value_t = QuadExpansion::Even)]
+    pub quad_expansion: QuadExpansion,
+    /// Number of spectra per window for `--quad-expansion even`
+    #[arg(long, default_value_t = 1)]
+    pub even_splits: usize,
+    /// Span and step in scans for `--quad-expansion uniform-scan`
+    #[arg(long, num_args = 2, value_names = ["SPAN", "STEP"])]
+    pub scan_span_step: Option<Vec<usize>>,
+    /// Span and step in 1/K0 for `--quad-expansion uniform-mobility`
+    #[arg(long, num_args = 2, value_names = ["SPAN", "STEP"])]
+    pub mobility_span_step: Option<Vec<f64>>,
+    /// Add the ion mobility of every peak (.d only, not with --calibrate)
+    #[arg(long)]
+    pub with_mobility: bool,
+    /// Interleave MS1 spectra with the MS2 spectra (.d only)
+    #[arg(long)]
+    pub include_ms1: bool,
+    /// Split MS1 spectra in 1/K0 slices of this span and step
+    #[arg(long, num_args = 2, value_names = ["SPAN", "STEP"])]
+    pub ms1_mobility_span_step: Option<Vec<f64>>,
+    /// Do not report progress on stderr
+    #[arg(short, long)]
+    pub quiet: bool,
+}
+
+impl Cli {
+    fn output_and_format(&self) -> Result<(PathBuf, OutputFormat), ConvertError> {
+        let format = match (&self.format, &self.output) {
+            (Some(format), _) => *format,
+            (None, Some(output)) => OutputFormat::from_path(output).ok_or_else(|| {
+                ConvertError::InvalidArguments(
+                    "Could not infer the format from the output, use --format".to_string(),
+                )
+            })?,
+            (None, None) => {
+                return Err(ConvertError::InvalidArguments(
+                    "Either --output or --format is required".to_string(),
+                ))
+            }
+        };
+        let output = match &self.output {
+            Some(output) => output.clone(),
+            None => self.input.with_extension(format.extension()),
+        };
+        Ok((output, format))
+    }
+
+    fn spectrum_reader_config(&self) -> Result<SpectrumReaderConfig, ConvertError> {
+        let strategy = match self.quad_expansion {
+            QuadExpansion::None => QuadWindowExpansionStrategy::None,
+            QuadExpansion::Even => QuadWindowExpansionStrategy::Even(self.even_splits),
+            QuadExpansion::UniformScan => match self.scan_span_step.as_deref() {
+                Some([span, step]) => QuadWindowExpansionStrategy::UniformScan((*span, *step)),
+                _ => {
+                    return Err(ConvertError::InvalidArguments(
+                        "--quad-expansion uniform-scan requires --scan-span-step".to_string(),
+                    ))
+                }
+            },
+            QuadExpansion::UniformMobility => match self.mobility_span_step.as_deref() {
+                Some([span, step]) => {
+                    QuadWindowExpansionStrategy::UniformMobility((*span, *step), None)
+                }
+                _ => {
+                    return Err(ConvertError::InvalidArguments(
+                        "--quad-expansion uniform-mobility requires --mobility-span-step"
+                            .to_string(),
+                    ))
+                }
+            },
+        };
+        Ok(SpectrumReaderConfig {
+            spectrum_processing_params: SpectrumProcessingParams {
+                smoothing_window: self.smoothing_window,
+                centroiding_window: self.centroiding_window,
+                calibration_tolerance: self.calibration_tolerance,
+                calibrate: self.calibrate,
+            },
+            frame_splitting_params: FrameWindowSplittingConfiguration::Quadrupole(strategy),
+        })
+    }
+}
+
+/// Parses the arguments, runs the conversion and returns the exit code.
+pub fn run<I, T>(args: I) -> u8
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let cli = match Cli::try_parse_from(args) {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            return e.exit_code() as u8;
+        }
+    };
+    match convert(&cli) {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("timsrust-convert: {}", e);
+            e.exit_code()
+        }
+    }
+}
+
+/// Runs a conversion, returning the number of spectra or frames written.
+pub fn convert(cli: &Cli) -> Result<usize, ConvertError> {
+    let (output, format) = cli.output_and_format()?;
+    let progress = Progress::new(cli.quiet, if cli.frames { "frames" } else { "spectra" });
+    if cli.frames {
+        return convert_frames(&cli.input, &output, format, progress);
+    }
+    if cli.include_ms1 && format == OutputFormat::Mgf {
+        return Err(ConvertError::InvalidArguments(
+            "MGF only holds MS2 spectra, --include-ms1 is not supported".to_string(),
+        ));
+    }
+    let ms1_mobility_span_step = match cli.ms1_mobility_span_step.as_deref() {
+        Some([span, step]) => Some((*span, *step)),
+        _ => None,
+    };
+    let reader = PySpectrumReader::from_config(
+        &cli.input.to_string_lossy(),
+        cli.spectrum_reader_config()?,
+        cli.with_mobility,
+        cli.include_ms1,
+        ms1_mobility_span_step,
+    )
+    .map_err(ConvertError::from_open)?;
+    let mut writer = SpectrumWriter::create(&output, format, reader.len())?;
+    let mut progress = progress;
+    for start in (0..reader.len()).step_by(CHUNK_SIZE) {
+        let end = (start + CHUNK_SIZE).min(reader.len());
+        let spectra = reader
+            .read_spectra(start..end)
+            .map_err(|e| ConvertError::Read(e.to_string()))?;
+        writer.write(&spectra)?;
+        progress.update(end, reader.len());
+    }
+    writer.finish()?;
+    Ok(reader.len())
+}
+
+fn convert_frames(
+    input: &Path,
+    output: &Path,
+    format: OutputFormat,
+    mut progress: Progress,
+) -> Result<usize, ConvertError> {
+    let reader = FrameReader::new(input).map_err(|e| ConvertError::Open(e.to_string()))?;
+    let mut writer = match format {
+        OutputFormat::Parquet | OutputFormat::Json => {
+            TableWriter::create(output, format, frames_table(&[]))?
+        }
+        _ => {
+            return Err(ConvertError::InvalidArguments(
+                "Frames can only be written as parquet or json".to_string(),
+            ))
+        }
+    };
+    for start in (0..reader.len()).step_by(CHUNK_SIZE) {
+        let end = (start + CHUNK_SIZE).min(reader.len());
+        let frames = (start..end)
+            .into_par_iter()
+            .map(|index| reader.get(index).map(PyFrame::from))
+            .collect::<Result<Vec<PyFrame>, _>>()
+            .map_err(|e| ConvertError::Read(e.to_string()))?;
+        writer.write(frames_table(&frames.iter().collect::<Vec<_>>()))?;
+        progress.update(end, reader.len());
+    }
+    writer.finish()?;
+    Ok(reader.len())
+}
+
+/// Progress report on stderr, at most once every 10 percent.
+struct Progress {
+    quiet: bool,
+    unit: &'static str,
+    last_decile: usize,
+}
+
+impl Progress {
+    fn new(quiet: bool, unit: &'static str) -> Self {
+        Progress {
+            quiet,
+            unit,
+            last_decile: 0,
+        }
+    }
+
+    fn update(&mut self, done: usize, total: usize) {
+        let decile = done * 10 / total.max(1);
+        if self.quiet || decile == self.last_decile {
+            return;
+        }
+        self.last_decile = decile;
+        eprintln!(
+            "timsrust-convert: {}/{} {} ({}%)",
+            done,
+            total,
+            self.unit,
+            decile * 10
+        );
+    }
+}
+
+enum SpectrumWriter {
+    Mgf(BufWriter<File>),
+    MzML(BufWriter<File>),
+    Table(TableWriter),
+}
+
+impl SpectrumWriter {
+    fn create(path: &Path, format: OutputFormat, num_spectra: usize) -> Result<Self, ConvertError> {
+        Ok(match format {
+            OutputFormat::Mgf => SpectrumWriter::Mgf(BufWriter::new(File::create(path)?)),
+            OutputFormat::MzML => {
+                let mut writer = BufWriter::new(File::create(path)?);
+                write_mzml_header(&mut writer, num_spectra)?;
+                SpectrumWriter::MzML(writer)
+            }
+            OutputFormat::Parquet | OutputFormat::Json => {
+                SpectrumWriter::Table(TableWriter::create(path, format, spectra_table(&[]))?)
+            }
+        })
+    }
+
+    fn write(&mut self, spectra: &[PySpectrum]) -> Result<(), ConvertError> {
+        match self {
+            SpectrumWriter::Mgf(writer) => {
+                for spectrum in spectra {
+                    write_mgf_spectrum(writer, spectrum)?;
+                }
+            }
+            SpectrumWriter::MzML(writer) => {
+                for spectrum in spectra {
+                    write_mzml_spectrum(writer, spectrum)?;
+                }
+            }
+            SpectrumWriter::Table(writer) => {
+                writer.write(spectra_table(&spectra.iter().collect::<Vec<_>>()))?
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), ConvertError> {
+        match self {
+            SpectrumWriter::Mgf(mut writer) => writer.flush()?,
+            SpectrumWriter::MzML(mut writer) => {
+                writeln!(writer, "    </spectrumList>\n  </run>\n</mzML>")?;
+                writer.flush()?
+            }
+            SpectrumWriter::Table(writer) => writer.finish()?,
+        }
+        Ok(())
+    }
+}
+
+fn write_mgf_spectrum(writer: &mut impl Write, spectrum: &PySpectrum) -> std::io::Result<()> {
+    writeln!(writer, "BEGIN IONS")?;
+    writeln!(writer, "TITLE=index={}", spectrum.index)?;
+    match &spectrum.precursor {
+        Some(precursor) => {
+            match precursor.intensity {
+                Some(intensity) => writeln!(writer, "PEPMASS={} {}", precursor.mz, intensity)?,
+                None => writeln!(writer, "PEPMASS={}", precursor.mz)?,
+            }
+            if let Some(charge) = precursor.charge {
+                writeln!(writer, "CHARGE={}+", charge)?;
+            }
+            writeln!(writer, "ION_MOBILITY={}", precursor.im)?;
+        }
+        None => writeln!(writer, "PEPMASS={}", spectrum.isolation_mz)?,
+    }
+    writeln!(writer, "RTINSECONDS={}", spectrum.rt)?;
+    writeln!(writer, "SCANS={}", spectrum.index)?;
+    for (mz, intensity) in spectrum.mz_values.iter().zip(spectrum.intensities.iter()) {
+        writeln!(writer, "{} {}", mz, intensity)?;
+    }
+    writeln!(writer, "END IONS\n")
+}
+
+fn cv_param(name: &str, accession: &str, value: impl Display) -> String {
+    format!(
+        "<cvParam cvRef=\"MS\" accession=\"{}\" name=\"{}\" value=\"{}\"/>",
+        accession, name, value
+    )
+}
+
+fn write_mzml_header(writer: &mut impl Write, num_spectra: usize) -> std::io::Result<()> {
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"utf-8\"?>")?;
+    writeln!(
+        writer,
+        "<mzML xmlns=\"http://psi.hupo.org/ms/mzml\" version=\"1.1.0\">"
+    )?;
+    writeln!(
+        writer,
+        "  <cvList count=\"2\">\n    <cv id=\"MS\" fullName=\"Proteomics Standards Initiative Mass Spectrometry Ontology\" URI=\"https://raw.githubusercontent.com/HUPO-PSI/psi-ms-CV/master/psi-ms.obo\"/>\n    <cv id=\"UO\" fullName=\"Unit Ontology\" URI=\"http://ontologies.berkeleybop.org/uo.obo\"/>\n  </cvList>"
+    )?;
+    writeln!(
+        writer,
+        "  <fileDescription>\n    <fileContent>\n      {}\n    </fileContent>\n  </fileDescription>",
+        cv_param("MSn spectrum", "MS:1000580", "")
+    )?;
+    writeln!(
+        writer,
+        "  <softwareList count=\"1\">\n    <software id=\"timsrust_pyo3\" version=\"{}\">\n      {}\n    </software>\n  </softwareList>",
+        env!("CARGO_PKG_VERSION"),
+        cv_param("custom unreleased software tool", "MS:1000799", "timsrust_pyo3")
+    )?;
+    writeln!(
+        writer,
+        "  <instrumentConfigurationList count=\"1\">\n    <instrumentConfiguration id=\"IC1\">\n      {}\n    </instrumentConfiguration>\n  </instrumentConfigurationList>",
+        cv_param("Bruker Daltonics timsTOF series", "MS:1003123", "")
+    )?;
+    writeln!(
+        writer,
+        "  <dataProcessingList count=\"1\">\n    <dataProcessing id=\"DP1\">\n      <processingMethod order=\"1\" softwareRef=\"timsrust_pyo3\">\n        {}\n      </processingMethod>\n    </dataProcessing>\n  </dataProcessingList>",
+        cv_param("Conversion to mzML", "MS:1000544", "")
+    )?;
+    writeln!(
+        writer,
+        "  <run id=\"run\" defaultInstrumentConfigurationRef=\"IC1\">\n    <spectrumList count=\"{}\" defaultDataProcessingRef=\"DP1\">",
+        num_spectra
+    )
+}
+
+fn write_mzml_binary_array(
+    writer: &mut impl Write,
+    values: &[f64],
+    array_param: &str,
+) -> std::io::Result<()> {
+    let bytes: Vec<u8> = values.iter().flat_map(|x| x.to_le_bytes()).collect();
+    let encoded = BASE64.encode(bytes);
+    writeln!(
+        writer,
+        "          <binaryDataArray encodedLength=\"{}\">\n            {}\n            {}\n            {}\n            <binary>{}</binary>\n          </binaryDataArray>",
+        encoded.len(),
+        cv_param("64-bit float", "MS:1000523", ""),
+        cv_param("no compression", "MS:1000576", ""),
+        array_param,
+        encoded
+    )
+}
+
+fn write_mzml_spectrum(writer: &mut impl Write, spectrum: &PySpectrum) -> std::io::Result<()> {
+    let ms_level = match spectrum.ms_level {
+        PyMSLevel::MS1 => 1,
+        _ => 2,
+    };
+    writeln!(
+        writer,
+        "      <spectrum index=\"{0}\" id=\"index={0}\" defaultArrayLength=\"{1}\">",
+        spectrum.index,
+        spectrum.mz_values.len()
+    )?;
+    writeln!(
+        writer,
+        "        {}",
+        cv_param("ms level", "MS:1000511", ms_level)
+    )?;
+    writeln!(
+        writer,
+        "        {}",
+        cv_param("centroid spectrum", "MS:1000127", "")
+    )?;
+    writeln!(
+        writer,
+        "        <scanList count=\"1\">\n          {}\n          <scan>\n            <cvParam cvRef=\"MS\" accession=\"MS:1000016\" name=\"scan start time\" value=\"{}\" unitCvRef=\"UO\" unitAccession=\"UO:0000010\" unitName=\"second\"/>",
+        cv_param("no combination", "MS:1000795", ""),
+        spectrum.rt
+    )?;
+    if !spectrum.lower_im.is_nan() {
+        writeln!(
+            writer,
+            "            <cvParam cvRef=\"MS\" accession=\"MS:1002815\" name=\"inverse reduced ion mobility\" value=\"{}\" unitCvRef=\"MS\" unitAccession=\"MS:1002814\" unitName=\"volt-second per square centimeter\"/>",
+            (spectrum.lower_im + spectrum.upper_im) / 2.0
+        )?;
+    }
+    writeln!(writer, "          </scan>\n        </scanList>")?;
+    if let Some(precursor) = &spectrum.precursor {
+        let half_width = spectrum.isolation_width / 2.0;
+        writeln!(
+            writer,
+            "        <precursorList count=\"1\">\n          <precursor>\n            <isolationWindow>\n              {}\n              {}\n              {}\n            </isolationWindow>",
+            cv_param("isolation window target m/z", "MS:1000827", spectrum.isolation_mz),
+            cv_param("isolation window lower offset", "MS:1000828", half_width),
+            cv_param("isolation window upper offset", "MS:1000829", half_width),
+        )?;
+        writeln!(
+            writer,
+            "            <selectedIonList count=\"1\">\n              <selectedIon>\n                {}",
+            cv_param("selected ion m/z", "MS:1000744", precursor.mz)
+        )?;
+        if let Some(charge) = precursor.charge {
+            writeln!(
+                writer,
+                "                {}",
+                cv_param("charge state", "MS:1000041", charge)
+            )?;
+        }
+        if let Some(intensity) = precursor.intensity {
+            writeln!(
+                writer,
+                "                {}",
+                cv_param("peak intensity", "MS:1000042", intensity)
+            )?;
+        }
+        writeln!(
+            writer,
+            "                <cvParam cvRef=\"MS\" accession=\"MS:1002815\" name=\"inverse reduced ion mobility\" value=\"{}\" unitCvRef=\"MS\" unitAccession=\"MS:1002814\" unitName=\"volt-second per square centimeter\"/>\n              </selectedIon>\n            </selectedIonList>",
+            precursor.im
+        )?;
+        writeln!(
+            writer,
+            "            <activation>\n              {}\n              <cvParam cvRef=\"MS\" accession=\"MS:1000045\" name=\"collision energy\" value=\"{}\" unitCvRef=\"UO\" unitAccession=\"UO:0000266\" unitName=\"electronvolt\"/>\n            </activation>\n          </precursor>\n        </precursorList>",
+            cv_param("collision-induced dissociation", "MS:1000133", ""),
+            spectrum.collision_energy
+        )?;
+    }
+    writeln!(writer, "        <binaryDataArrayList count=\"2\">")?;
+    write_mzml_binary_array(
+        writer,
+        &spectrum.mz_values,
+        "<cvParam cvRef=\"MS\" accession=\"MS:1000514\" name=\"m/z array\" value=\"\" unitCvRef=\"MS\" unitAccession=\"MS:1000040\" unitName=\"m/z\"/>",
+    )?;
+    write_mzml_binary_array(
+        writer,
+        &spectrum.intensities,
+        "<cvParam cvRef=\"MS\" accession=\"MS:1000515\" name=\"intensity array\" value=\"\" unitCvRef=\"MS\" unitAccession=\"MS:1000131\" unitName=\"number of detector counts\"/>",
+    )?;
+    writeln!(writer, "        </binaryDataArrayList>\n      </spectrum>")
+}
+
+/// Writes column tables as parquet row groups or as a json array of records.
+enum TableWriter {
+    Parquet(Box<ArrowWriter<File>>),
+    Json(BufWriter<File>, usize),
+}
+
+impl TableWriter {
+    /// `empty` only provides the schema of the tables that will be written.
+    fn create(path: &Path, format: OutputFormat, empty: ColumnTable) -> Result<Self, ConvertError> {
+        let file = File::create(path)?;
+        match format {
+            OutputFormat::Parquet => {
+                let properties = WriterProperties::builder()
+                    .set_compression(Compression::ZSTD(ZstdLevel::default()))
+                    .build();
+                let schema = empty.into_record_batch()?.schema();
+                Ok(TableWriter::Parquet(Box::new(ArrowWriter::try_new(
+                    file,
+                    schema,
+                    Some(properties),
+                )?)))
+            }
+            _ => {
+                let mut writer = BufWriter::new(file);
+                write!(writer, "[")?;
+                Ok(TableWriter::Json(writer, 0))
+            }
+        }
+    }
+
+    fn write(&mut self, table: ColumnTable) -> Result<(), ConvertError> {
+        match self {
+            TableWriter::Parquet(writer) => writer.write(&table.into_record_batch()?)?,
+            TableWriter::Json(writer, rows_written) => {
+                let num_rows = table.columns.first().map_or(0, |(_, x)| column_len(x));
+                for row in 0..num_rows {
+                    let record: serde_json::Map<String, Value> = table
+                        .columns
+                        .iter()
+                        .map(|(name, column)| (name.clone(), json_value(column, row)))
+                        .collect();
+                    if *rows_written > 0 {
+                        write!(writer, ",")?;
+                    }
+                    write!(writer, "\n{}", Value::Object(record))?;
+                    *rows_written += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), ConvertError> {
+        match self {
+            TableWriter::Parquet(writer) => {
+                writer.close()?;
+            }
+            TableWriter::Json(mut writer, _) => {
+                writeln!(writer, "\n]")?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn column_len(column: &Column) -> usize {
+    match column {
+        Column::F64(x) => x.len(),
+        Column::U64(x) => x.len(),
+        Column::U8(x) => x.len(),
+        Column::Str(x) => x.len(),
+        Column::OptionStr(x) => x.len(),
+        Column::OptionF64(x) => x.len(),
+        Column::OptionU64(x) => x.len(),
+        Column::ListF64(x) => x.len(),
+        Column::ListU32(x) => x.len(),
+        Column::ListU64(x) => x.len(),
+        Column::OptionListF64(x) => x.len(),
+        Column::OptionI64(x) => x.len(),
+        Column::OptionBytes(x) => x.len(),
+    }
+}
+
+fn json_value(column: &Column, row: usize) -> Value {
+    match column {
+        Column::F64(x) => json!(x[row]),
+        Column::U64(x) => json!(x[row]),
+        Column::U8(x) => json!(x[row]),
+        Column::Str(x) => json!(x[row]),
+        Column::OptionStr(x) => json!(x[row]),
+        Column::OptionF64(x) => json!(x[row]),
+        Column::OptionU64(x) => json!(x[row]),
+        Column::ListF64(x) => json!(x[row]),
+        Column::ListU32(x) => json!(x[row]),
+        Column::ListU64(x) => json!(x[row]),
+        Column::OptionListF64(x) => json!(x[row]),
+        Column::OptionI64(x) => json!(x[row]),
+        Column::OptionBytes(x) => json!(x[row].as_ref().map(|y| BASE64.encode(y))),
+    }
+}
@@ -0,0 +1,143 @@
+use pyo3::exceptions::PyIOError;
+use pyo3::prelude::*;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::tdf_sql::{ReadableSqlTable, SqlDiaFrame, SqlFrameInfo, TdfSqlReader};
+use crate::timsrust_readers::PyFrameReader;
+use crate::timsrust_structs::PyFrame;
+
+/// Frames (0-based positions) that make up one acquisition cycle.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CycleLayout {
+    pub frames: Vec<usize>,
+    pub window_groups: Vec<usize>,
+}
+
+/// Groups frames in cycles, each starting at an MS1 frame and holding all
+/// MS2 frames until the next MS1 frame.
+///
+/// Frames acquired before the first MS1 frame form a cycle on their own.
+/// `window_groups` has the diaPASEF window group of every frame (0 when it
+/// is not a diaPASEF MS2 frame).
+pub fn group_cycles(frames: &[SqlFrameInfo], window_groups: &[usize]) -> Vec<CycleLayout> {
+    let mut cycles: Vec<CycleLayout> = vec![];
+    for (position, frame) in frames.iter().enumerate() {
+        if frame.msms_type == 0 || cycles.is_empty() {
+            cycles.push(CycleLayout::default());
+        }
+        if let Some(cycle) = cycles.last_mut() {
+            cycle.frames.push(position);
+            let window_group = window_groups.get(position).copied().unwrap_or(0);
+            if window_group != 0 && !cycle.window_groups.contains(&window_group) {
+                cycle.window_groups.push(window_group);
+            }
+        }
+    }
+    cycles
+}
+
+pub fn read_cycle_layouts(tdf_sql_reader: &TdfSqlReader) -> rusqlite::Result<Vec<CycleLayout>> {
+    let frames = SqlFrameInfo::from_sql_reader(tdf_sql_reader)?;
+    let mut window_groups = vec![0; frames.len()];
+    if tdf_sql_reader.has_table("DiaFrameMsMsInfo")? {
+        for dia_frame in SqlDiaFrame::from_sql_reader(tdf_sql_reader)? {
+            if let Some(x) = window_groups.get_mut(dia_frame.frame - 1) {
+                *x = dia_frame.window_group;
+            }
+        }
+    }
+    Ok(group_cycles(&frames, &window_groups))
+}
+
+#[pyclass(name = "Cycle")]
+pub struct PyCycle {
+    #[pyo3(get)]
+    pub index: usize,
+    /// Retention time of the first frame of the cycle (the MS1 frame).
+    #[pyo3(get)]
+    pub rt: f64,
+    /// Retention time of the last frame of the cycle.
+    #[pyo3(get)]
+    pub rt_end: f64,
+    #[pyo3(get)]
+    pub frames: Vec<PyFrame>,
+    #[pyo3(get)]
+    pub window_groups: Vec<usize>,
+}
+
+#[pymethods]
+impl PyCycle {
+    #[getter]
+    fn frame_indices(&self) -> Vec<usize> {
+        self.frames.iter().map(|x| x.index).collect()
+    }
+
+    pub fn __len__(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "Cycle(index={}, rt={}, rt_end={}, num_frames={}, window_groups={:?})",
+            self.index,
+            self.rt,
+            self.rt_end,
+            self.frames.len(),
+            self.window_groups,
+        )
+    }
+}
+
+#[pyclass(name = "CycleIterator")]
+pub struct PyCycleIterator {
+    pub reader: Py<PyFrameReader>,
+    pub layouts: Vec<CycleLayout>,
+    pub i: usize,
+}
+
+impl PyCycleIterator {
+    pub fn new(reader: &Bound<'_, PyFrameReader>) -> PyResult<Self> {
+        let tdf_sql_reader = TdfSqlReader::open(reader.borrow().reader.get_path())
+            .map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let layouts =
+            read_cycle_layouts(&tdf_sql_reader).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        Ok(PyCycleIterator {
+            reader: reader.clone().unbind(),
+            layouts,
+            i: 0,
+        })
+    }
+}
+
+#[pymethods]
+impl PyCycleIterator {
+    pub fn __len__(&self) -> usize {
+        self.layouts.len()
+    }
+
+    pub fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    pub fn __next__(mut slf: PyRefMut<'_, Self>, py: Python<'_>) -> PyResult<Option<PyCycle>> {
+        let index = slf.i;
+        let Some(layout) = slf.layouts.get(index).cloned() else {
+            return Ok(None);
+        };
+        slf.i += 1;
+        let reader_ref = slf.reader.borrow(py);
+        let reader: &PyFrameReader = &reader_ref;
+        let frames = layout
+            .frames
+            .par_iter()
+            .map(|x| reader.read_frame(*x))
+            .collect::<PyResult<Vec<PyFrame>>>()?;
+        Ok(Some(PyCycle {
+            index,
+            rt: frames.first().map(|x| x.rt).unwrap_or(f64::NAN),
+            rt_end: frames.last().map(|x| x.rt).unwrap_or(f64::NAN),
+            frames,
+            window_groups: layout.window_groups,
+        }))
+    }
+}
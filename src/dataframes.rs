@@ -0,0 +1,544 @@
+use std::ffi::CString;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{
+    ArrowPrimitiveType, Float64Type, Int64Type, UInt32Type, UInt64Type, UInt8Type,
+};
+use arrow_array::{
+    Array, ArrayRef, BinaryArray, Float64Array, Int64Array, ListArray, RecordBatch, StringArray,
+    UInt64Array, UInt8Array,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use pyo3::exceptions::{PyIOError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyCapsule, PyDict, PyList};
+
+use crate::arrow_stream::FFI_ArrowArrayStream;
+use crate::features::PyFeature;
+use crate::tdf_sql::{ReadableSqlTable, SqlFrameMetadata, TdfSqlReader};
+use crate::timsrust_enums::PyMSLevel;
+use crate::timsrust_structs::{PyFrame, PyPrecursor, PySpectrum};
+
+/// A typed column, kept in rust until it is handed to a dataframe library.
+pub enum Column {
+    F64(Vec<f64>),
+    U64(Vec<u64>),
+    U8(Vec<u8>),
+    Str(Vec<String>),
+    OptionStr(Vec<Option<String>>),
+    OptionF64(Vec<Option<f64>>),
+    OptionU64(Vec<Option<u64>>),
+    ListF64(Vec<Vec<f64>>),
+    ListU32(Vec<Vec<u32>>),
+    ListU64(Vec<Vec<u64>>),
+    OptionListF64(Vec<Option<Vec<f64>>>),
+    OptionI64(Vec<Option<i64>>),
+    OptionBytes(Vec<Option<Vec<u8>>>),
+}
+
+impl Column {
+    pub fn into_arrow(self) -> ArrayRef {
+        match self {
+            Column::F64(x) => Arc::new(Float64Array::from(x)),
+            Column::U64(x) => Arc::new(UInt64Array::from(x)),
+            Column::U8(x) => Arc::new(UInt8Array::from(x)),
+            Column::Str(x) => Arc::new(StringArray::from(x)),
+            Column::OptionStr(x) => Arc::new(StringArray::from(x)),
+            Column::OptionF64(x) => Arc::new(Float64Array::from(x)),
+            Column::OptionU64(x) => Arc::new(UInt64Array::from(x)),
+            Column::ListF64(x) => list_array::<Float64Type>(x.into_iter().map(Some)),
+            Column::ListU32(x) => list_array::<UInt32Type>(x.into_iter().map(Some)),
+            Column::ListU64(x) => list_array::<UInt64Type>(x.into_iter().map(Some)),
+            Column::OptionListF64(x) => list_array::<Float64Type>(x),
+            Column::OptionI64(x) => Arc::new(Int64Array::from(x)),
+            Column::OptionBytes(x) => Arc::new(BinaryArray::from_iter(x)),
+        }
+    }
+}
+
+/// Lists are appended to one values buffer, each freed once it is copied.
+fn list_array<T: ArrowPrimitiveType>(
+    lists: impl IntoIterator<Item = Option<Vec<T::Native>>>,
+) -> ArrayRef {
+    Arc::new(ListArray::from_iter_primitive::<T, _, _>(
+        lists
+            .into_iter()
+            .map(|x| x.map(|y| y.into_iter().map(Some))),
+    ))
+}
+
+/// Columns in insertion order.
+#[derive(Default)]
+pub struct ColumnTable {
+    pub columns: Vec<(String, Column)>,
+}
+
+impl ColumnTable {
+    pub fn push(&mut self, name: impl Into<String>, column: Column) {
+        self.columns.push((name.into(), column));
+    }
+
+    pub fn into_dict<'py>(self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        self.into_arrow()?.to_pydict(py)
+    }
+
+    /// All fields are nullable, so tables built from chunks share one schema.
+    /// Each column moves into its Arrow buffers without a copy.
+    pub fn into_record_batch(self) -> Result<RecordBatch, ArrowError> {
+        let (fields, arrays): (Vec<Field>, Vec<ArrayRef>) = self
+            .columns
+            .into_iter()
+            .map(|(name, column)| {
+                let array = column.into_arrow();
+                (Field::new(name, array.data_type().clone(), true), array)
+            })
+            .unzip();
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)
+    }
+
+    pub fn into_arrow(self) -> PyResult<PyArrowTable> {
+        let batch = self
+            .into_record_batch()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PyArrowTable { batch })
+    }
+
+    pub fn into_polars(self, py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+        PyArrowTable::to_polars(&Bound::new(py, self.into_arrow()?)?)
+    }
+
+    pub fn into_pandas(self, py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+        PyArrowTable::to_pandas(&Bound::new(py, self.into_arrow()?)?)
+    }
+}
+
+/// Columns as an Arrow record batch. Implements the Arrow PyCapsule
+/// interface (`__arrow_c_stream__`), so polars, pyarrow, duckdb and other
+/// Arrow-aware libraries import it without a copy.
+#[pyclass(name = "ArrowTable")]
+pub struct PyArrowTable {
+    batch: RecordBatch,
+}
+
+#[pymethods]
+impl PyArrowTable {
+    #[getter]
+    pub fn num_rows(&self) -> usize {
+        self.batch.num_rows()
+    }
+
+    #[getter]
+    pub fn column_names(&self) -> Vec<String> {
+        let schema = self.batch.schema();
+        schema.fields().iter().map(|x| x.name().clone()).collect()
+    }
+
+    pub fn __len__(&self) -> usize {
+        self.num_rows()
+    }
+
+    /// The columns as a dict of lists, with None for missing values.
+    pub fn to_pydict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        let schema = self.batch.schema();
+        for (field, array) in schema.fields().iter().zip(self.batch.columns()) {
+            dict.set_item(field.name(), array_to_list(py, array.as_ref())?)?;
+        }
+        Ok(dict)
+    }
+
+    /// Exports the table as a single batch `ArrowArrayStream` capsule.
+    #[pyo3(signature = (requested_schema=None))]
+    pub fn __arrow_c_stream__<'py>(
+        &self,
+        py: Python<'py>,
+        requested_schema: Option<Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, PyCapsule>> {
+        // Honouring a requested schema is optional, consumers cast if needed
+        let _ = requested_schema;
+        let stream = FFI_ArrowArrayStream::new(self.batch.schema(), vec![self.batch.clone()]);
+        let name = CString::new("arrow_array_stream").unwrap();
+        PyCapsule::new(py, stream, Some(name))
+    }
+
+    /// The table as a polars DataFrame.
+    pub fn to_polars<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        slf.py()
+            .import("polars")?
+            .call_method1("from_arrow", (slf,))
+    }
+
+    /// The table as a pandas DataFrame backed by `pandas.ArrowDtype` columns.
+    pub fn to_pandas<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        let py = slf.py();
+        let pandas = py.import("pandas")?;
+        let table = py.import("pyarrow")?.call_method1("table", (slf,))?;
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("types_mapper", pandas.getattr("ArrowDtype")?)?;
+        table.call_method("to_pandas", (), Some(&kwargs))
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "ArrowTable(num_rows={}, num_columns={})",
+            self.batch.num_rows(),
+            self.batch.num_columns()
+        )
+    }
+}
+
+fn array_to_list<'py>(py: Python<'py>, array: &dyn Array) -> PyResult<Bound<'py, PyList>> {
+    fn primitive<'py, T: ArrowPrimitiveType>(
+        py: Python<'py>,
+        array: &dyn Array,
+    ) -> PyResult<Bound<'py, PyList>>
+    where
+        T::Native: IntoPyObject<'py>,
+    {
+        PyList::new(py, array.as_primitive::<T>().iter().collect::<Vec<_>>())
+    }
+    match array.data_type() {
+        DataType::Float64 => primitive::<Float64Type>(py, array),
+        DataType::Int64 => primitive::<Int64Type>(py, array),
+        DataType::UInt64 => primitive::<UInt64Type>(py, array),
+        DataType::UInt32 => primitive::<UInt32Type>(py, array),
+        DataType::UInt8 => primitive::<UInt8Type>(py, array),
+        DataType::Utf8 => PyList::new(py, array.as_string::<i32>().iter().collect::<Vec<_>>()),
+        DataType::Binary => PyList::new(py, array.as_binary::<i32>().iter().collect::<Vec<_>>()),
+        DataType::List(_) => {
+            let lists = array.as_list::<i32>();
+            let values = (0..lists.len())
+                .map(|i| {
+                    lists
+                        .is_valid(i)
+                        .then(|| array_to_list(py, lists.value(i).as_ref()))
+                        .transpose()
+                })
+                .collect::<PyResult<Vec<_>>>()?;
+            PyList::new(py, values)
+        }
+        x => Err(PyTypeError::new_err(format!(
+            "Unsupported column type {}",
+            x
+        ))),
+    }
+}
+
+pub fn frames_table(frames: &[&PyFrame]) -> ColumnTable {
+    let mut table = ColumnTable::default();
+    table.push(
+        "index",
+        Column::U64(frames.iter().map(|x| x.index as u64).collect()),
+    );
+    table.push("rt", Column::F64(frames.iter().map(|x| x.rt).collect()));
+    table.push(
+        "ms_level",
+        Column::Str(frames.iter().map(|x| x.ms_level.to_string()).collect()),
+    );
+    table.push(
+        "acquisition_type",
+        Column::Str(
+            frames
+                .iter()
+                .map(|x| x.acquisition_type.to_string())
+                .collect(),
+        ),
+    );
+    table.push(
+        "window_group",
+        Column::U8(frames.iter().map(|x| x.window_group).collect()),
+    );
+    table.push(
+        "intensity_correction_factor",
+        Column::F64(
+            frames
+                .iter()
+                .map(|x| x.intensity_correction_factor)
+                .collect(),
+        ),
+    );
+    table.push(
+        "scan_offsets",
+        Column::ListU64(
+            frames
+                .iter()
+                .map(|x| x.scan_offsets.iter().map(|y| *y as u64).collect())
+                .collect(),
+        ),
+    );
+    table.push(
+        "tof_indices",
+        Column::ListU32(frames.iter().map(|x| x.tof_indices.clone()).collect()),
+    );
+    table.push(
+        "intensities",
+        Column::ListU32(frames.iter().map(|x| x.intensities.clone()).collect()),
+    );
+    table
+}
+
+pub fn frame_metadata_table(frames: &[SqlFrameMetadata]) -> ColumnTable {
+    let mut table = ColumnTable::default();
+    let optional_u64 =
+        |f: fn(&SqlFrameMetadata) -> Option<u64>| Column::OptionU64(frames.iter().map(f).collect());
+    let optional_f64 =
+        |f: fn(&SqlFrameMetadata) -> Option<f64>| Column::OptionF64(frames.iter().map(f).collect());
+    table.push(
+        "index",
+        Column::U64(frames.iter().map(|x| x.id as u64).collect()),
+    );
+    table.push("rt", Column::F64(frames.iter().map(|x| x.rt).collect()));
+    table.push(
+        "ms_level",
+        Column::Str(
+            frames
+                .iter()
+                .map(|x| PyMSLevel::from_msms_type(x.msms_type).to_string())
+                .collect(),
+        ),
+    );
+    table.push(
+        "msms_type",
+        Column::U8(frames.iter().map(|x| x.msms_type).collect()),
+    );
+    table.push(
+        "polarity",
+        Column::OptionStr(frames.iter().map(|x| x.polarity.clone()).collect()),
+    );
+    table.push("scan_mode", optional_u64(|x| x.scan_mode.map(u64::from)));
+    table.push("tims_id", optional_u64(|x| x.tims_id));
+    table.push("max_intensity", optional_u64(|x| x.max_intensity));
+    table.push("summed_intensities", optional_u64(|x| x.summed_intensities));
+    table.push("num_scans", optional_u64(|x| x.num_scans.map(|y| y as u64)));
+    table.push("num_peaks", optional_u64(|x| x.num_peaks.map(|y| y as u64)));
+    table.push("accumulation_time", optional_f64(|x| x.accumulation_time));
+    table.push("ramp_time", optional_f64(|x| x.ramp_time));
+    table.push(
+        "tims_calibration",
+        optional_u64(|x| x.tims_calibration.map(|y| y as u64)),
+    );
+    table.push(
+        "mz_calibration",
+        optional_u64(|x| x.mz_calibration.map(|y| y as u64)),
+    );
+    table.push(
+        "property_group",
+        optional_u64(|x| x.property_group.map(|y| y as u64)),
+    );
+    table.push("t1", optional_f64(|x| x.t1));
+    table.push("t2", optional_f64(|x| x.t2));
+    table.push("pressure", optional_f64(|x| x.pressure));
+    table
+}
+
+/// Frames table of an `analysis.tdf` (or its `.d` folder), read without
+/// touching the frame binary data.
+pub fn read_frame_metadata(path: impl AsRef<Path>) -> PyResult<ColumnTable> {
+    let frames = TdfSqlReader::open(path)
+        .and_then(|x| SqlFrameMetadata::from_sql_reader(&x))
+        .map_err(|e| PyIOError::new_err(e.to_string()))?;
+    Ok(frame_metadata_table(&frames))
+}
+
+pub fn precursors_table(precursors: &[Option<&PyPrecursor>]) -> ColumnTable {
+    let mut table = ColumnTable::default();
+    let column = |f: fn(&PyPrecursor) -> f64| precursors.iter().map(|x| x.map(f)).collect();
+    table.push("precursor_mz", Column::OptionF64(column(|x| x.mz)));
+    table.push("precursor_rt", Column::OptionF64(column(|x| x.rt)));
+    table.push("precursor_im", Column::OptionF64(column(|x| x.im)));
+    table.push(
+        "precursor_charge",
+        Column::OptionU64(
+            precursors
+                .iter()
+                .map(|x| x.and_then(|y| y.charge).map(|y| y as u64))
+                .collect(),
+        ),
+    );
+    table.push(
+        "precursor_intensity",
+        Column::OptionF64(
+            precursors
+                .iter()
+                .map(|x| x.and_then(|y| y.intensity))
+                .collect(),
+        ),
+    );
+    table.push(
+        "precursor_index",
+        Column::OptionU64(
+            precursors
+                .iter()
+                .map(|x| x.map(|y| y.index as u64))
+                .collect(),
+        ),
+    );
+    table.push(
+        "precursor_frame_index",
+        Column::OptionU64(
+            precursors
+                .iter()
+                .map(|x| x.map(|y| y.frame_index as u64))
+                .collect(),
+        ),
+    );
+    table
+}
+
+pub fn spectra_table(spectra: &[&PySpectrum]) -> ColumnTable {
+    let mut table = ColumnTable::default();
+    table.push(
+        "index",
+        Column::U64(spectra.iter().map(|x| x.index as u64).collect()),
+    );
+    table.push(
+        "ms_level",
+        Column::Str(spectra.iter().map(|x| x.ms_level.to_string()).collect()),
+    );
+    table.push("rt", Column::F64(spectra.iter().map(|x| x.rt).collect()));
+    table.push(
+        "lower_im",
+        Column::F64(spectra.iter().map(|x| x.lower_im).collect()),
+    );
+    table.push(
+        "upper_im",
+        Column::F64(spectra.iter().map(|x| x.upper_im).collect()),
+    );
+    table.push(
+        "collision_energy",
+        Column::F64(spectra.iter().map(|x| x.collision_energy).collect()),
+    );
+    table.push(
+        "isolation_mz",
+        Column::F64(spectra.iter().map(|x| x.isolation_mz).collect()),
+    );
+    table.push(
+        "isolation_width",
+        Column::F64(spectra.iter().map(|x| x.isolation_width).collect()),
+    );
+    let precursors: Vec<Option<&PyPrecursor>> =
+        spectra.iter().map(|x| x.precursor.as_ref()).collect();
+    table.columns.extend(precursors_table(&precursors).columns);
+    table.push(
+        "frame_indices",
+        Column::ListU64(
+            spectra
+                .iter()
+                .map(|x| x.frame_indices.iter().map(|y| *y as u64).collect())
+                .collect(),
+        ),
+    );
+    table.push(
+        "mz_values",
+        Column::ListF64(spectra.iter().map(|x| x.mz_values.clone()).collect()),
+    );
+    table.push(
+        "intensities",
+        Column::ListF64(spectra.iter().map(|x| x.intensities.clone()).collect()),
+    );
+    table.push(
+        "mobility_values",
+        Column::OptionListF64(spectra.iter().map(|x| x.mobility_values.clone()).collect()),
+    );
+    table
+}
+
+pub fn features_table(features: &[&PyFeature]) -> ColumnTable {
+    let mut table = ColumnTable::default();
+    let column = |f: fn(&PyFeature) -> f64| Column::F64(features.iter().map(|x| f(x)).collect());
+    let list = |f: fn(&PyFeature) -> &Vec<f64>| {
+        Column::ListF64(features.iter().map(|x| f(x).clone()).collect())
+    };
+    let trace = |f: fn(&PyFeature) -> &Option<Vec<f64>>| {
+        Column::OptionListF64(features.iter().map(|x| f(x).clone()).collect())
+    };
+    table.push("mz", column(|x| x.mz));
+    table.push(
+        "charge",
+        Column::OptionU64(
+            features
+                .iter()
+                .map(|x| x.charge.map(|y| y as u64))
+                .collect(),
+        ),
+    );
+    table.push("rt", column(|x| x.rt));
+    table.push("rt_start", column(|x| x.rt_start));
+    table.push("rt_end", column(|x| x.rt_end));
+    table.push("rt_width", column(|x| x.rt_width));
+    table.push("im", column(|x| x.im));
+    table.push("im_width", column(|x| x.im_width));
+    table.push("intensity", column(|x| x.intensity));
+    table.push("apex_intensity", column(|x| x.apex_intensity));
+    table.push(
+        "num_frames",
+        Column::U64(features.iter().map(|x| x.num_frames as u64).collect()),
+    );
+    table.push(
+        "apex_frame_index",
+        Column::U64(features.iter().map(|x| x.apex_frame_index as u64).collect()),
+    );
+    table.push("isotope_mzs", list(|x| &x.isotope_mzs));
+    table.push("isotope_intensities", list(|x| &x.isotope_intensities));
+    table.push("xic_rt", trace(|x| &x.xic_rt));
+    table.push("xic_intensities", trace(|x| &x.xic_intensities));
+    table.push("mobilogram_im", trace(|x| &x.mobilogram_im));
+    table.push(
+        "mobilogram_intensities",
+        trace(|x| &x.mobilogram_intensities),
+    );
+    table
+}
+
+/// Builds the column table for a homogeneous list of frames, spectra,
+/// precursors or features.
+pub fn table_from_list(items: &Bound<'_, PyList>) -> PyResult<ColumnTable> {
+    if let Ok(frames) = items.extract::<Vec<PyRef<PyFrame>>>() {
+        let frames: Vec<&PyFrame> = frames.iter().map(|x| &**x).collect();
+        return Ok(frames_table(&frames));
+    }
+    if let Ok(spectra) = items.extract::<Vec<PyRef<PySpectrum>>>() {
+        let spectra: Vec<&PySpectrum> = spectra.iter().map(|x| &**x).collect();
+        return Ok(spectra_table(&spectra));
+    }
+    if let Ok(precursors) = items.extract::<Vec<PyRef<PyPrecursor>>>() {
+        let precursors: Vec<Option<&PyPrecursor>> = precursors.iter().map(|x| Some(&**x)).collect();
+        return Ok(precursors_table(&precursors));
+    }
+    if let Ok(features) = items.extract::<Vec<PyRef<PyFeature>>>() {
+        let features: Vec<&PyFeature> = features.iter().map(|x| &**x).collect();
+        return Ok(features_table(&features));
+    }
+    Err(PyTypeError::new_err(
+        "Expected a list of only Frame, Spectrum, Precursor or Feature objects",
+    ))
+}
+
+/// Columns of a list of frames, spectra, precursors or features as a dict of lists.
+#[pyfunction]
+pub fn to_columns<'py>(
+    py: Python<'py>,
+    items: &Bound<'py, PyList>,
+) -> PyResult<Bound<'py, PyDict>> {
+    table_from_list(items)?.into_dict(py)
+}
+
+/// Converts a list of frames, spectra, precursors or features to an Arrow table.
+#[pyfunction]
+pub fn to_arrow(items: &Bound<'_, PyList>) -> PyResult<PyArrowTable> {
+    table_from_list(items)?.into_arrow()
+}
+
+/// Converts a list of frames, spectra, precursors or features to a polars DataFrame.
+#[pyfunction]
+pub fn to_polars<'py>(py: Python<'py>, items: &Bound<'py, PyList>) -> PyResult<Bound<'py, PyAny>> {
+    table_from_list(items)?.into_polars(py)
+}
+
+/// Converts a list of frames, spectra, precursors or features to a pandas DataFrame.
+#[pyfunction]
+pub fn to_pandas<'py>(py: Python<'py>, items: &Bound<'py, PyList>) -> PyResult<Bound<'py, PyAny>> {
+    table_from_list(items)?.into_pandas(py)
+}
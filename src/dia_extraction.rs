@@ -0,0 +1,341 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyString;
+use rayon::iter::ParallelIterator;
+use timsrust::converters::{ConvertableDomain, Scan2ImConverter, Tof2MzConverter};
+use timsrust::readers::{FrameReader, FrameReaderError};
+use timsrust::{AcquisitionType, MSLevel};
+
+use crate::timsrust_converters::scan_range;
+use crate::timsrust_enums::PyMSLevel;
+use crate::timsrust_structs::PyFrame;
+
+/// A targeted diaPASEF precursor with its fragments (a transition group).
+///
+/// Traces are extracted within `rt_tolerance` seconds of `rt`, within
+/// `im_tolerance` of `im` (1/K0) and within `mz_tolerance_ppm` of the
+/// precursor and fragment m/z values.
+#[pyclass(name = "DiaTarget")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PyDiaTarget {
+    #[pyo3(get)]
+    pub precursor_mz: f64,
+    #[pyo3(get)]
+    pub fragment_mzs: Vec<f64>,
+    #[pyo3(get)]
+    pub rt: f64,
+    #[pyo3(get)]
+    pub im: f64,
+    #[pyo3(get)]
+    pub rt_tolerance: f64,
+    #[pyo3(get)]
+    pub im_tolerance: f64,
+    #[pyo3(get)]
+    pub mz_tolerance_ppm: f64,
+    #[pyo3(get)]
+    pub name: Option<String>,
+}
+
+#[pymethods]
+impl PyDiaTarget {
+    #[new]
+    #[pyo3(signature = (precursor_mz, fragment_mzs, rt, im, rt_tolerance=60.0, im_tolerance=0.05, mz_tolerance_ppm=20.0, name=None))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        precursor_mz: f64,
+        fragment_mzs: Vec<f64>,
+        rt: f64,
+        im: f64,
+        rt_tolerance: f64,
+        im_tolerance: f64,
+        mz_tolerance_ppm: f64,
+        name: Option<String>,
+    ) -> PyResult<Self> {
+        for (value, label) in [
+            (rt_tolerance, "rt_tolerance"),
+            (im_tolerance, "im_tolerance"),
+            (mz_tolerance_ppm, "mz_tolerance_ppm"),
+        ] {
+            if value.is_nan() || value < 0.0 {
+                return Err(PyValueError::new_err(format!(
+                    "{} must not be negative",
+                    label
+                )));
+            }
+        }
+        Ok(PyDiaTarget {
+            precursor_mz,
+            fragment_mzs,
+            rt,
+            im,
+            rt_tolerance,
+            im_tolerance,
+            mz_tolerance_ppm,
+            name,
+        })
+    }
+
+    pub fn __repr__(slf: &Bound<'_, Self>) -> PyResult<String> {
+        let class_name: Bound<'_, PyString> = slf.get_type().qualname()?;
+        let x = slf.borrow();
+        Ok(format!(
+            "{}(precursor_mz={}, fragment_mzs={:?}, rt={}, im={}, name={:?})",
+            class_name, x.precursor_mz, x.fragment_mzs, x.rt, x.im, x.name,
+        ))
+    }
+}
+
+/// Extraction windows of a target in raw units: frames by rt, scans
+/// (`start..end`) and tof indices (inclusive bounds).
+#[derive(Clone, Debug, PartialEq)]
+struct TargetWindows {
+    rt: (f64, f64),
+    scans: (usize, usize),
+    precursor_mz: f64,
+    precursor_tofs: (f64, f64),
+    fragment_tofs: Vec<(f64, f64)>,
+}
+
+impl TargetWindows {
+    fn new(
+        target: &PyDiaTarget,
+        mz_converter: &Tof2MzConverter,
+        im_converter: &Scan2ImConverter,
+    ) -> Self {
+        let tof_window = |mz: f64| {
+            let tolerance = mz * target.mz_tolerance_ppm / 1e6;
+            (
+                mz_converter.invert(mz - tolerance),
+                mz_converter.invert(mz + tolerance),
+            )
+        };
+        TargetWindows {
+            rt: (
+                target.rt - target.rt_tolerance,
+                target.rt + target.rt_tolerance,
+            ),
+            // Clamped to the scans of each frame in `sum_intensity`
+            scans: scan_range(
+                im_converter,
+                target.im - target.im_tolerance,
+                target.im + target.im_tolerance,
+                usize::MAX,
+            ),
+            precursor_mz: target.precursor_mz,
+            precursor_tofs: tof_window(target.precursor_mz),
+            fragment_tofs: target.fragment_mzs.iter().map(|x| tof_window(*x)).collect(),
+        }
+    }
+}
+
+/// Summed intensity within `tofs` of the scans `start..end` of a frame.
+/// Tof indices are sorted within every scan.
+fn sum_intensity(frame: &PyFrame, scans: (usize, usize), tofs: (f64, f64)) -> f64 {
+    let scan_end = scans.1.min(frame.scan_offsets.len().saturating_sub(1));
+    let mut intensity = 0.0;
+    for scan in scans.0..scan_end {
+        let (start, end) = (frame.scan_offsets[scan], frame.scan_offsets[scan + 1]);
+        let scan_tofs = &frame.tof_indices[start..end];
+        let lower = scan_tofs.partition_point(|x| (*x as f64) < tofs.0);
+        let upper = scan_tofs.partition_point(|x| (*x as f64) <= tofs.1);
+        intensity += frame.intensities[start + lower..start + upper]
+            .iter()
+            .map(|x| *x as f64)
+            .sum::<f64>();
+    }
+    intensity
+}
+
+/// Scan ranges of the quadrupole windows of a diaPASEF frame that isolated
+/// the precursor, limited to the scans of the target.
+fn isolated_scans(frame: &PyFrame, windows: &TargetWindows) -> Vec<(usize, usize)> {
+    let quadrupole = &frame.quadrupole_settings;
+    (0..quadrupole.isolation_mz.len())
+        .filter(|i| {
+            (quadrupole.isolation_mz[*i] - windows.precursor_mz).abs()
+                <= quadrupole.isolation_width[*i] / 2.0
+        })
+        .map(|i| {
+            (
+                quadrupole.scan_starts[i].max(windows.scans.0),
+                quadrupole.scan_ends[i].min(windows.scans.1),
+            )
+        })
+        .filter(|(start, end)| start < end)
+        .collect()
+}
+
+/// Targets sorted by the start of their rt window, to find the targets of a
+/// frame without checking all of them.
+struct RtIndex {
+    order: Vec<usize>,
+    starts: Vec<f64>,
+    max_span: f64,
+}
+
+impl RtIndex {
+    fn new(windows: &[TargetWindows]) -> Self {
+        let mut order: Vec<usize> = (0..windows.len()).collect();
+        order.sort_by(|a, b| windows[*a].rt.0.total_cmp(&windows[*b].rt.0));
+        RtIndex {
+            starts: order.iter().map(|x| windows[*x].rt.0).collect(),
+            max_span: windows.iter().map(|x| x.rt.1 - x.rt.0).fold(0.0, f64::max),
+            order,
+        }
+    }
+
+    fn targets<'a>(
+        &'a self,
+        windows: &'a [TargetWindows],
+        rt: f64,
+    ) -> impl Iterator<Item = usize> + 'a {
+        let start = self.starts.partition_point(|x| *x < rt - self.max_span);
+        let end = self.starts.partition_point(|x| *x <= rt);
+        self.order[start..end]
+            .iter()
+            .copied()
+            .filter(move |x| windows[*x].rt.1 >= rt)
+    }
+}
+
+/// Intensities a frame contributes to each target: the precursor for MS1
+/// frames, the fragments for diaPASEF frames that isolated the target.
+fn extract_frame(
+    frame: &PyFrame,
+    windows: &[TargetWindows],
+    rt_index: &RtIndex,
+) -> Vec<(usize, Vec<f64>)> {
+    rt_index
+        .targets(windows, frame.rt)
+        .filter_map(|target| {
+            let window = &windows[target];
+            if frame.ms_level == PyMSLevel::MS1 {
+                let intensity = sum_intensity(frame, window.scans, window.precursor_tofs);
+                return Some((target, vec![intensity]));
+            }
+            let scans = isolated_scans(frame, window);
+            if scans.is_empty() {
+                return None;
+            }
+            let intensities = window
+                .fragment_tofs
+                .iter()
+                .map(|tofs| scans.iter().map(|x| sum_intensity(frame, *x, *tofs)).sum())
+                .collect();
+            Some((target, intensities))
+        })
+        .collect()
+}
+
+/// Fragment ion chromatograms of all `targets` from the diaPASEF frames
+/// that isolated their precursor, and precursor chromatograms from the MS1
+/// frames, reading every frame within the rt windows once.
+pub fn extract_dia_targets(
+    frame_reader: &FrameReader,
+    mz_converter: &Tof2MzConverter,
+    im_converter: &Scan2ImConverter,
+    targets: &[PyDiaTarget],
+) -> Result<Vec<PyDiaTraces>, FrameReaderError> {
+    let windows: Vec<TargetWindows> = targets
+        .iter()
+        .map(|x| TargetWindows::new(x, mz_converter, im_converter))
+        .collect();
+    let rt_index = RtIndex::new(&windows);
+    let points = frame_reader
+        .parallel_filter(|x| {
+            let used_level = x.ms_level == MSLevel::MS1
+                || (x.ms_level == MSLevel::MS2 && x.acquisition_type == AcquisitionType::DIAPASEF);
+            used_level && rt_index.targets(&windows, x.rt).next().is_some()
+        })
+        .map(|x| {
+            let frame = PyFrame::from(x?);
+            let contributions = extract_frame(&frame, &windows, &rt_index);
+            Ok((frame.index, frame.rt, frame.ms_level, contributions))
+        })
+        .collect::<Result<Vec<_>, FrameReaderError>>()?;
+    let mut traces: Vec<PyDiaTraces> = targets.iter().map(PyDiaTraces::new).collect();
+    for (frame_index, rt, ms_level, contributions) in points {
+        for (target, intensities) in contributions {
+            let trace = &mut traces[target];
+            if ms_level == PyMSLevel::MS1 {
+                trace.ms1_frame_indices.push(frame_index);
+                trace.ms1_rt.push(rt);
+                trace.precursor_intensities.push(intensities[0]);
+                continue;
+            }
+            trace.frame_indices.push(frame_index);
+            trace.rt.push(rt);
+            for (fragment, intensity) in trace.fragment_intensities.iter_mut().zip(intensities) {
+                fragment.push(intensity);
+            }
+        }
+    }
+    Ok(traces)
+}
+
+/// Extracted ion chromatograms of a diaPASEF target.
+///
+/// `fragment_intensities` holds one trace per fragment m/z, aligned with
+/// `rt`, with one point per MS2 frame that isolated the precursor.
+/// `precursor_intensities` is aligned with `ms1_rt`, with one point per MS1
+/// frame. Intensities are summed raw intensities.
+#[pyclass(name = "DiaTraces")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PyDiaTraces {
+    #[pyo3(get)]
+    pub target: PyDiaTarget,
+    #[pyo3(get)]
+    pub frame_indices: Vec<usize>,
+    #[pyo3(get)]
+    pub rt: Vec<f64>,
+    #[pyo3(get)]
+    pub fragment_intensities: Vec<Vec<f64>>,
+    #[pyo3(get)]
+    pub ms1_frame_indices: Vec<usize>,
+    #[pyo3(get)]
+    pub ms1_rt: Vec<f64>,
+    #[pyo3(get)]
+    pub precursor_intensities: Vec<f64>,
+}
+
+impl PyDiaTraces {
+    fn new(target: &PyDiaTarget) -> Self {
+        PyDiaTraces {
+            target: target.clone(),
+            frame_indices: vec![],
+            rt: vec![],
+            fragment_intensities: vec![vec![]; target.fragment_mzs.len()],
+            ms1_frame_indices: vec![],
+            ms1_rt: vec![],
+            precursor_intensities: vec![],
+        }
+    }
+}
+
+#[pymethods]
+impl PyDiaTraces {
+    pub fn __len__(&self) -> usize {
+        self.rt.len()
+    }
+
+    pub fn __repr__(slf: &Bound<'_, Self>) -> PyResult<String> {
+        let class_name: Bound<'_, PyString> = slf.get_type().qualname()?;
+        let x = slf.borrow();
+        Ok(format!(
+            "{}(precursor_mz={}, name={:?}, num_fragments={}, num_points={}, num_ms1_points={})",
+            class_name,
+            x.target.precursor_mz,
+            x.target.name,
+            x.fragment_intensities.len(),
+            x.rt.len(),
+            x.ms1_rt.len(),
+        ))
+    }
+
+    /// Summed fragment intensity of every point.
+    pub fn total_fragment_intensities(&self) -> Vec<f64> {
+        (0..self.rt.len())
+            .map(|i| self.fragment_intensities.iter().map(|x| x[i]).sum())
+            .collect()
+    }
+}
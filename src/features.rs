@@ -0,0 +1,378 @@
+use std::collections::BTreeMap;
+
+use pyo3::prelude::*;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use timsrust::converters::{ConvertableDomain, Scan2ImConverter, Tof2MzConverter};
+use timsrust::readers::{FrameReader, FrameReaderError};
+
+use crate::isotopes::isotope_envelope;
+use crate::peak_picking::{PeakPickingParams, PyPickedPeaks};
+use crate::timsrust_converters::scan_range;
+
+/// Settings of the MS1 feature finder.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FeatureFinderParams {
+    pub peak_picking: PeakPickingParams,
+    /// Maximum m/z distance of peaks in one trace, and between isotopes.
+    pub mz_tolerance_ppm: f64,
+    /// Maximum 1/K0 distance of peaks in one trace, and between isotopes.
+    pub im_tolerance: f64,
+    /// Number of MS1 frames a trace may miss before it is closed.
+    pub max_gap: usize,
+    /// Traces with fewer peaks are dropped.
+    pub min_frames: usize,
+    pub max_charge: usize,
+    /// Features with fewer isotopes (including the monoisotopic one) are dropped.
+    pub min_isotopes: usize,
+    pub with_traces: bool,
+}
+
+impl Default for FeatureFinderParams {
+    fn default() -> Self {
+        FeatureFinderParams {
+            peak_picking: PeakPickingParams::default(),
+            mz_tolerance_ppm: 10.0,
+            im_tolerance: 0.02,
+            max_gap: 1,
+            min_frames: 3,
+            max_charge: 4,
+            min_isotopes: 2,
+            with_traces: false,
+        }
+    }
+}
+
+/// Centroided peaks of consecutive MS1 frames linked into one mass trace.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Hill {
+    /// Positions in the list of MS1 frames.
+    positions: Vec<usize>,
+    mzs: Vec<f64>,
+    ims: Vec<f64>,
+    im_widths: Vec<f64>,
+    intensities: Vec<f64>,
+}
+
+impl Hill {
+    fn weighted_mean(&self, values: &[f64]) -> f64 {
+        let total: f64 = self.intensities.iter().sum();
+        values
+            .iter()
+            .zip(self.intensities.iter())
+            .map(|(x, y)| x * y)
+            .sum::<f64>()
+            / total
+    }
+
+    fn mz(&self) -> f64 {
+        self.weighted_mean(&self.mzs)
+    }
+
+    fn im(&self) -> f64 {
+        self.weighted_mean(&self.ims)
+    }
+
+    fn intensity(&self) -> f64 {
+        self.intensities.iter().sum()
+    }
+
+    fn first(&self) -> usize {
+        self.positions[0]
+    }
+
+    fn last(&self) -> usize {
+        self.positions[self.positions.len() - 1]
+    }
+
+    fn apex(&self) -> usize {
+        let apex = (0..self.intensities.len())
+            .max_by(|a, b| self.intensities[*a].total_cmp(&self.intensities[*b]))
+            .unwrap_or_default();
+        self.positions[apex]
+    }
+}
+
+/// Links the peaks of consecutive MS1 frames into hills, each peak joining
+/// the open hill closest in m/z within the tolerances.
+fn build_hills(frames: &[PyPickedPeaks], params: &FeatureFinderParams) -> Vec<Hill> {
+    let mut finished = vec![];
+    let mut open: Vec<Hill> = vec![];
+    for (position, peaks) in frames.iter().enumerate() {
+        let (closed, still_open): (Vec<Hill>, Vec<Hill>) = open
+            .into_iter()
+            .partition(|x| position - x.last() > params.max_gap + 1);
+        finished.extend(closed);
+        open = still_open;
+        open.sort_by(|a, b| a.mzs[a.mzs.len() - 1].total_cmp(&b.mzs[b.mzs.len() - 1]));
+        let last_mzs: Vec<f64> = open.iter().map(|x| x.mzs[x.mzs.len() - 1]).collect();
+        let mut new_hills = vec![];
+        // Peaks are sorted by intensity, so the most intense peak claims a hill first
+        for i in 0..peaks.mz_values.len() {
+            let mz = peaks.mz_values[i];
+            let im = peaks.mobility_values[i];
+            let tolerance = mz * params.mz_tolerance_ppm / 1e6;
+            let start = last_mzs.partition_point(|x| *x < mz - tolerance);
+            let end = last_mzs.partition_point(|x| *x <= mz + tolerance);
+            let hill = (start..end)
+                .filter(|j| {
+                    let hill = &open[*j];
+                    hill.last() != position
+                        && (hill.ims[hill.ims.len() - 1] - im).abs() <= params.im_tolerance
+                })
+                .min_by(|a, b| {
+                    (last_mzs[*a] - mz)
+                        .abs()
+                        .total_cmp(&(last_mzs[*b] - mz).abs())
+                });
+            let hill = match hill {
+                Some(j) => &mut open[j],
+                None => {
+                    new_hills.push(Hill::default());
+                    new_hills.last_mut().unwrap()
+                }
+            };
+            hill.positions.push(position);
+            hill.mzs.push(mz);
+            hill.ims.push(im);
+            hill.im_widths.push(peaks.mobility_widths[i]);
+            hill.intensities.push(peaks.summed_intensities[i]);
+        }
+        open.extend(new_hills);
+    }
+    finished.extend(open);
+    finished.retain(|x| x.positions.len() >= params.min_frames);
+    finished
+}
+
+/// A 4D (m/z, rt, 1/K0, intensity) MS1 feature: the isotope envelope of an
+/// ion traced over consecutive frames.
+///
+/// `mz` and `im` are those of the monoisotopic trace, the rt values and
+/// intensities those of the summed envelope. `rt_width` is the width at half
+/// of the apex intensity. The traces are only set when requested.
+#[pyclass(name = "Feature")]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PyFeature {
+    #[pyo3(get)]
+    pub mz: f64,
+    #[pyo3(get)]
+    pub charge: Option<usize>,
+    #[pyo3(get)]
+    pub rt: f64,
+    #[pyo3(get)]
+    pub rt_start: f64,
+    #[pyo3(get)]
+    pub rt_end: f64,
+    #[pyo3(get)]
+    pub rt_width: f64,
+    #[pyo3(get)]
+    pub im: f64,
+    #[pyo3(get)]
+    pub im_width: f64,
+    #[pyo3(get)]
+    pub intensity: f64,
+    #[pyo3(get)]
+    pub apex_intensity: f64,
+    #[pyo3(get)]
+    pub isotope_mzs: Vec<f64>,
+    #[pyo3(get)]
+    pub isotope_intensities: Vec<f64>,
+    #[pyo3(get)]
+    pub num_frames: usize,
+    #[pyo3(get)]
+    pub apex_frame_index: usize,
+    #[pyo3(get)]
+    pub xic_rt: Option<Vec<f64>>,
+    #[pyo3(get)]
+    pub xic_intensities: Option<Vec<f64>>,
+    #[pyo3(get)]
+    pub mobilogram_im: Option<Vec<f64>>,
+    #[pyo3(get)]
+    pub mobilogram_intensities: Option<Vec<f64>>,
+}
+
+#[pymethods]
+impl PyFeature {
+    pub fn __repr__(&self) -> String {
+        format!(
+            "Feature(mz={}, charge={}, rt={}, im={}, intensity={}, num_isotopes={})",
+            self.mz,
+            self.charge.map_or("None".to_string(), |x| x.to_string()),
+            self.rt,
+            self.im,
+            self.intensity,
+            self.isotope_mzs.len(),
+        )
+    }
+}
+
+fn build_feature(
+    hills: &[&Hill],
+    charge: Option<usize>,
+    frames: &[PyPickedPeaks],
+    with_traces: bool,
+) -> PyFeature {
+    let mono = hills[0];
+    let first = hills.iter().map(|x| x.first()).min().unwrap_or_default();
+    let last = hills.iter().map(|x| x.last()).max().unwrap_or_default();
+    let mut xic = vec![0.0; last - first + 1];
+    for hill in hills {
+        for (position, intensity) in hill.positions.iter().zip(hill.intensities.iter()) {
+            xic[position - first] += intensity;
+        }
+    }
+    let apex = (0..xic.len())
+        .max_by(|a, b| xic[*a].total_cmp(&xic[*b]))
+        .unwrap_or_default();
+    let apex_intensity = xic[apex];
+    let half_start = xic.iter().position(|x| *x >= apex_intensity / 2.0);
+    let half_end = xic.iter().rposition(|x| *x >= apex_intensity / 2.0);
+    let rt_width = match (half_start, half_end) {
+        (Some(start), Some(end)) => frames[first + end].rt - frames[first + start].rt,
+        _ => 0.0,
+    };
+    let xic_rt: Vec<f64> = (first..=last).map(|x| frames[x].rt).collect();
+    PyFeature {
+        mz: mono.mz(),
+        charge,
+        rt: frames[first + apex].rt,
+        rt_start: frames[first].rt,
+        rt_end: frames[last].rt,
+        rt_width,
+        im: mono.im(),
+        im_width: mono.weighted_mean(&mono.im_widths),
+        intensity: hills.iter().map(|x| x.intensity()).sum(),
+        apex_intensity,
+        isotope_mzs: hills.iter().map(|x| x.mz()).collect(),
+        isotope_intensities: hills.iter().map(|x| x.intensity()).collect(),
+        num_frames: mono.positions.len(),
+        apex_frame_index: frames[first + apex].frame_index,
+        xic_rt: with_traces.then_some(xic_rt),
+        xic_intensities: with_traces.then_some(xic),
+        mobilogram_im: None,
+        mobilogram_intensities: None,
+    }
+}
+
+/// Detects features in the picked peaks of consecutive MS1 frames: peaks
+/// are linked across frames into hills, and co-eluting hills with matching
+/// mobility are grouped into isotope envelopes, most intense hill first.
+/// Features are sorted by m/z.
+pub fn find_features(frames: &[PyPickedPeaks], params: &FeatureFinderParams) -> Vec<PyFeature> {
+    let mut hills = build_hills(frames, params);
+    hills.sort_by(|a, b| a.mz().total_cmp(&b.mz()));
+    let mzs: Vec<f64> = hills.iter().map(|x| x.mz()).collect();
+    let ims: Vec<f64> = hills.iter().map(|x| x.im()).collect();
+    let intensities: Vec<f64> = hills.iter().map(|x| x.intensity()).collect();
+    let mut order: Vec<usize> = (0..hills.len()).collect();
+    order.sort_by(|a, b| intensities[*b].total_cmp(&intensities[*a]));
+    let mut assigned = vec![false; hills.len()];
+    let mut features = vec![];
+    for seed in order {
+        if assigned[seed] {
+            continue;
+        }
+        let seed_hill = &hills[seed];
+        let envelope = isotope_envelope(
+            &mzs,
+            &intensities,
+            seed,
+            params.max_charge,
+            params.mz_tolerance_ppm,
+            0.0,
+            |i| {
+                let apex = hills[i].apex();
+                !assigned[i]
+                    && (ims[i] - ims[seed]).abs() <= params.im_tolerance
+                    && (seed_hill.first()..=seed_hill.last()).contains(&apex)
+            },
+        );
+        let (charge, members) = match envelope {
+            Some(x) => (Some(x.charge), x.peaks),
+            None => (None, vec![seed]),
+        };
+        if members.len() < params.min_isotopes {
+            continue;
+        }
+        for member in members.iter() {
+            assigned[*member] = true;
+        }
+        let members: Vec<&Hill> = members.iter().map(|x| &hills[*x]).collect();
+        features.push(build_feature(&members, charge, frames, params.with_traces));
+    }
+    features.sort_by(|a, b| a.mz.total_cmp(&b.mz));
+    features
+}
+
+/// Sets the mobilogram of each feature: the summed intensity per scan of
+/// all isotopes in its apex frame, over twice its 1/K0 width and tolerance.
+pub fn add_mobilograms(
+    features: &mut [PyFeature],
+    frame_reader: &FrameReader,
+    mz_converter: &Tof2MzConverter,
+    im_converter: &Scan2ImConverter,
+    params: &FeatureFinderParams,
+) -> Result<(), FrameReaderError> {
+    let mut by_frame: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (i, feature) in features.iter().enumerate() {
+        by_frame
+            .entry(feature.apex_frame_index)
+            .or_default()
+            .push(i);
+    }
+    let mobilograms = by_frame
+        .into_iter()
+        .collect::<Vec<(usize, Vec<usize>)>>()
+        .into_par_iter()
+        .map(|(frame_index, members)| {
+            let frame = frame_reader.get(frame_index - 1)?;
+            let num_scans = frame.scan_offsets.len().saturating_sub(1);
+            Ok(members
+                .into_iter()
+                .map(|i| {
+                    let feature = &features[i];
+                    let span = 2.0 * (feature.im_width + params.im_tolerance);
+                    let (scan_start, scan_end) = scan_range(
+                        im_converter,
+                        feature.im - span,
+                        feature.im + span,
+                        num_scans,
+                    );
+                    let tof_windows: Vec<(f64, f64)> = feature
+                        .isotope_mzs
+                        .iter()
+                        .map(|mz| {
+                            let tolerance = mz * params.mz_tolerance_ppm / 1e6;
+                            (
+                                mz_converter.invert(mz - tolerance),
+                                mz_converter.invert(mz + tolerance),
+                            )
+                        })
+                        .collect();
+                    let mut mobilities = vec![];
+                    let mut intensities = vec![];
+                    for scan in scan_start..scan_end {
+                        let mut intensity = 0.0;
+                        for peak in frame.scan_offsets[scan]..frame.scan_offsets[scan + 1] {
+                            let tof = frame.tof_indices[peak] as f64;
+                            if tof_windows
+                                .iter()
+                                .any(|(lower, upper)| (*lower..=*upper).contains(&tof))
+                            {
+                                intensity += frame.intensities[peak] as f64;
+                            }
+                        }
+                        mobilities.push(im_converter.convert(scan as f64));
+                        intensities.push(intensity);
+                    }
+                    (i, mobilities, intensities)
+                })
+                .collect::<Vec<(usize, Vec<f64>, Vec<f64>)>>())
+        })
+        .collect::<Result<Vec<Vec<(usize, Vec<f64>, Vec<f64>)>>, FrameReaderError>>()?;
+    for (i, mobilities, intensities) in mobilograms.into_iter().flatten() {
+        features[i].mobilogram_im = Some(mobilities);
+        features[i].mobilogram_intensities = Some(intensities);
+    }
+    Ok(())
+}
@@ -0,0 +1,371 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::similarity::Tolerance;
+use crate::spectrum_processing::PROTON_MASS;
+use crate::timsrust_structs::PySpectrum;
+
+const H2O_MASS: f64 = 18.010_564_684;
+const NH3_MASS: f64 = 17.026_549_101;
+const CO_MASS: f64 = 27.994_914_620;
+
+/// Monoisotopic residue mass of an amino acid.
+fn residue_mass(residue: char) -> Option<f64> {
+    match residue {
+        'G' => Some(57.021_463_72),
+        'A' => Some(71.037_113_79),
+        'S' => Some(87.032_028_41),
+        'P' => Some(97.052_763_85),
+        'V' => Some(99.068_413_92),
+        'T' => Some(101.047_678_5),
+        'C' => Some(103.009_184_5),
+        'L' | 'I' => Some(113.084_064),
+        'N' => Some(114.042_927_4),
+        'D' => Some(115.026_943_1),
+        'Q' => Some(128.058_577_5),
+        'K' => Some(128.094_963),
+        'E' => Some(129.042_593_1),
+        'M' => Some(131.040_484_6),
+        'H' => Some(137.058_911_9),
+        'F' => Some(147.068_413_9),
+        'R' => Some(156.101_111),
+        'Y' => Some(163.063_328_5),
+        'W' => Some(186.079_313),
+        _ => None,
+    }
+}
+
+/// Mass shift of a modification given by name, UniMod accession or as a
+/// signed mass.
+fn modification_mass(name: &str) -> Option<f64> {
+    if name.starts_with(['+', '-']) {
+        return name.parse().ok();
+    }
+    match name.to_lowercase().as_str() {
+        "oxidation" | "ox" | "unimod:35" => Some(15.994_914_62),
+        "carbamidomethyl" | "cam" | "unimod:4" => Some(57.021_463_72),
+        "phospho" | "ph" | "unimod:21" => Some(79.966_330_93),
+        "acetyl" | "ac" | "unimod:1" => Some(42.010_564_68),
+        "deamidated" | "de" | "unimod:7" => Some(0.984_015_58),
+        "methyl" | "unimod:34" => Some(14.015_650_06),
+        "tmt6plex" | "unimod:737" => Some(229.162_932_1),
+        _ => None,
+    }
+}
+
+/// A peptide as residue masses, including their modifications.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Peptide {
+    pub residues: Vec<char>,
+    pub masses: Vec<f64>,
+}
+
+impl Peptide {
+    /// Parses sequences such as `PEPM[Oxidation]C[UNIMOD:4]K`,
+    /// `[Acetyl]-PEPTIDE`, `PEPM(ox)K` or `PEPM[+15.995]K`. A modification
+    /// before the first residue is an N-terminal one and is added to it.
+    pub fn parse(sequence: &str) -> PyResult<Self> {
+        let invalid = |reason: &str| {
+            PyValueError::new_err(format!("Invalid peptide '{}': {}", sequence, reason))
+        };
+        let mut residues = vec![];
+        let mut masses: Vec<f64> = vec![];
+        let mut n_term = 0.0;
+        let mut chars = sequence.trim_matches('_').chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '[' | '(' => {
+                    let close = if c == '[' { ']' } else { ')' };
+                    let name: String = chars.by_ref().take_while(|x| *x != close).collect();
+                    let mass = modification_mass(&name)
+                        .ok_or_else(|| invalid(&format!("unknown modification '{}'", name)))?;
+                    match masses.last_mut() {
+                        Some(x) => *x += mass,
+                        None => n_term += mass,
+                    }
+                }
+                '-' if masses.is_empty() => {}
+                c => {
+                    let mass = residue_mass(c)
+                        .ok_or_else(|| invalid(&format!("unknown residue '{}'", c)))?;
+                    residues.push(c);
+                    masses.push(mass);
+                }
+            }
+        }
+        match masses.first_mut() {
+            Some(x) => *x += n_term,
+            None => return Err(invalid("no residues")),
+        }
+        Ok(Peptide { residues, masses })
+    }
+
+    /// Monoisotopic mass of the uncharged peptide.
+    pub fn mass(&self) -> f64 {
+        self.masses.iter().sum::<f64>() + H2O_MASS
+    }
+}
+
+/// A theoretical fragment ion.
+#[pyclass(name = "FragmentIon")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PyFragmentIon {
+    /// One of 'a', 'b', 'y' or 'internal'.
+    #[pyo3(get)]
+    pub ion_type: String,
+    /// Number of residues from the terminus, or first residue (0-based) of
+    /// an internal fragment.
+    #[pyo3(get)]
+    pub position: usize,
+    /// Number of residues.
+    #[pyo3(get)]
+    pub length: usize,
+    #[pyo3(get)]
+    pub charge: usize,
+    /// 'H2O' or 'NH3'.
+    #[pyo3(get)]
+    pub neutral_loss: Option<String>,
+    #[pyo3(get)]
+    pub mz: f64,
+    /// Such as `b3`, `y5-H2O^2` or `int-PEP`.
+    #[pyo3(get)]
+    pub label: String,
+}
+
+#[pymethods]
+impl PyFragmentIon {
+    pub fn __repr__(&self) -> String {
+        format!("FragmentIon(label={}, mz={})", self.label, self.mz)
+    }
+}
+
+/// Fragment ion types to generate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FragmentParams {
+    pub a_ions: bool,
+    pub internal: bool,
+    pub neutral_losses: bool,
+    pub max_charge: usize,
+}
+
+fn fragment(
+    ion_type: &str,
+    position: usize,
+    length: usize,
+    name: String,
+    mass: f64,
+    charge: usize,
+    neutral_loss: Option<(&str, f64)>,
+) -> PyFragmentIon {
+    let mut label = name;
+    if let Some((loss, _)) = neutral_loss {
+        label = format!("{}-{}", label, loss);
+    }
+    if charge > 1 {
+        label = format!("{}^{}", label, charge);
+    }
+    let mass = mass - neutral_loss.map_or(0.0, |x| x.1);
+    PyFragmentIon {
+        ion_type: ion_type.to_string(),
+        position,
+        length,
+        charge,
+        neutral_loss: neutral_loss.map(|x| x.0.to_string()),
+        mz: mass / charge as f64 + PROTON_MASS,
+        label,
+    }
+}
+
+/// Theoretical fragments of `peptide`, sorted by m/z.
+pub fn fragment_ions(peptide: &Peptide, params: &FragmentParams) -> Vec<PyFragmentIon> {
+    let n = peptide.masses.len();
+    let losses: Vec<Option<(&str, f64)>> = if params.neutral_losses {
+        vec![None, Some(("H2O", H2O_MASS)), Some(("NH3", NH3_MASS))]
+    } else {
+        vec![None]
+    };
+    let mut ions = vec![];
+    for charge in 1..=params.max_charge.max(1) {
+        for length in 1..n {
+            let prefix: f64 = peptide.masses[..length].iter().sum();
+            let suffix: f64 = peptide.masses[n - length..].iter().sum::<f64>() + H2O_MASS;
+            for loss in losses.iter() {
+                let b = format!("b{}", length);
+                ions.push(fragment("b", length, length, b, prefix, charge, *loss));
+                let y = format!("y{}", length);
+                ions.push(fragment("y", length, length, y, suffix, charge, *loss));
+            }
+            if params.a_ions {
+                let a = format!("a{}", length);
+                ions.push(fragment(
+                    "a",
+                    length,
+                    length,
+                    a,
+                    prefix - CO_MASS,
+                    charge,
+                    None,
+                ));
+            }
+        }
+        if params.internal {
+            // Internal fragments keep neither terminal residue
+            for start in 1..n.saturating_sub(2) {
+                for end in start + 2..n {
+                    let name: String = peptide.residues[start..end].iter().collect();
+                    let mass = peptide.masses[start..end].iter().sum();
+                    let label = format!("int-{}", name);
+                    ions.push(fragment(
+                        "internal",
+                        start,
+                        end - start,
+                        label,
+                        mass,
+                        charge,
+                        None,
+                    ));
+                }
+            }
+        }
+    }
+    ions.sort_by(|a, b| a.mz.total_cmp(&b.mz));
+    ions
+}
+
+/// Fragment annotation of a spectrum for one peptide. Per peak lists are
+/// aligned with the peaks of the spectrum and are None for peaks without a
+/// matching fragment.
+#[pyclass(name = "FragmentAnnotation")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PyFragmentAnnotation {
+    #[pyo3(get)]
+    pub peptide: String,
+    #[pyo3(get)]
+    pub charge: usize,
+    #[pyo3(get)]
+    pub precursor_mz: f64,
+    #[pyo3(get)]
+    pub labels: Vec<Option<String>>,
+    #[pyo3(get)]
+    pub fragment_mzs: Vec<Option<f64>>,
+    #[pyo3(get)]
+    pub mass_errors_ppm: Vec<Option<f64>>,
+    #[pyo3(get)]
+    pub num_fragments: usize,
+    #[pyo3(get)]
+    pub num_matched_peaks: usize,
+    /// Distinct fragments explaining at least one peak.
+    #[pyo3(get)]
+    pub num_matched_fragments: usize,
+    #[pyo3(get)]
+    pub matched_intensity_fraction: f64,
+    /// Fraction of the backbone cleavages explained by a b or y ion.
+    #[pyo3(get)]
+    pub sequence_coverage: f64,
+}
+
+#[pymethods]
+impl PyFragmentAnnotation {
+    pub fn __repr__(&self) -> String {
+        format!(
+            "FragmentAnnotation(peptide={}, charge={}, num_matched_peaks={}, num_matched_fragments={}, matched_intensity_fraction={}, sequence_coverage={})",
+            self.peptide,
+            self.charge,
+            self.num_matched_peaks,
+            self.num_matched_fragments,
+            self.matched_intensity_fraction,
+            self.sequence_coverage,
+        )
+    }
+}
+
+/// Annotates every peak with the closest fragment within `tolerance`.
+pub fn annotate(
+    spectrum: &PySpectrum,
+    sequence: &str,
+    peptide: &Peptide,
+    charge: usize,
+    params: &FragmentParams,
+    tolerance: &Tolerance,
+) -> PyFragmentAnnotation {
+    let ions = fragment_ions(peptide, params);
+    let ion_mzs: Vec<f64> = ions.iter().map(|x| x.mz).collect();
+    let mut matched = vec![false; ions.len()];
+    let num_cleavages = peptide.masses.len().saturating_sub(1);
+    let mut cleavages = vec![false; num_cleavages];
+    let mut annotation = PyFragmentAnnotation {
+        peptide: sequence.to_string(),
+        charge,
+        precursor_mz: peptide.mass() / charge as f64 + PROTON_MASS,
+        labels: vec![],
+        fragment_mzs: vec![],
+        mass_errors_ppm: vec![],
+        num_fragments: ions.len(),
+        num_matched_peaks: 0,
+        num_matched_fragments: 0,
+        matched_intensity_fraction: 0.0,
+        sequence_coverage: 0.0,
+    };
+    let mut matched_intensity = 0.0;
+    for (mz, intensity) in spectrum.mz_values.iter().zip(spectrum.intensities.iter()) {
+        let width = tolerance.width(*mz);
+        let start = ion_mzs.partition_point(|x| *x < mz - width);
+        let end = ion_mzs.partition_point(|x| *x <= mz + width);
+        let closest = (start..end).min_by(|a, b| {
+            (ion_mzs[*a] - mz)
+                .abs()
+                .total_cmp(&(ion_mzs[*b] - mz).abs())
+        });
+        let Some(i) = closest else {
+            annotation.labels.push(None);
+            annotation.fragment_mzs.push(None);
+            annotation.mass_errors_ppm.push(None);
+            continue;
+        };
+        let ion = &ions[i];
+        matched[i] = true;
+        match ion.ion_type.as_str() {
+            "b" => cleavages[ion.length - 1] = true,
+            "y" => cleavages[num_cleavages - ion.length] = true,
+            _ => {}
+        }
+        matched_intensity += intensity;
+        annotation.num_matched_peaks += 1;
+        annotation.labels.push(Some(ion.label.clone()));
+        annotation.fragment_mzs.push(Some(ion.mz));
+        annotation
+            .mass_errors_ppm
+            .push(Some((mz - ion.mz) / ion.mz * 1e6));
+    }
+    let total_intensity: f64 = spectrum.intensities.iter().sum();
+    if total_intensity > 0.0 {
+        annotation.matched_intensity_fraction = matched_intensity / total_intensity;
+    }
+    annotation.num_matched_fragments = matched.iter().filter(|x| **x).count();
+    if num_cleavages > 0 {
+        annotation.sequence_coverage =
+            cleavages.iter().filter(|x| **x).count() as f64 / num_cleavages as f64;
+    }
+    annotation
+}
+
+/// Theoretical fragments of `peptide`, sorted by m/z: b and y ions, plus a
+/// ions, internal fragments and water and ammonia losses when enabled, up
+/// to charge `max_fragment_charge`.
+#[pyfunction]
+#[pyo3(name = "fragment_ions", signature = (peptide, max_fragment_charge=1, a_ions=false, internal=false, neutral_losses=false))]
+pub fn py_fragment_ions(
+    peptide: &str,
+    max_fragment_charge: usize,
+    a_ions: bool,
+    internal: bool,
+    neutral_losses: bool,
+) -> PyResult<Vec<PyFragmentIon>> {
+    let params = FragmentParams {
+        a_ions,
+        internal,
+        neutral_losses,
+        max_charge: max_fragment_charge,
+    };
+    Ok(fragment_ions(&Peptide::parse(peptide)?, &params))
+}
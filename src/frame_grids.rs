@@ -0,0 +1,206 @@
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyString};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use timsrust::converters::{ConvertableDomain, Scan2ImConverter, Tof2MzConverter};
+
+use crate::binning::{dense_numpy_array, numpy_array, BinningParams};
+use crate::timsrust_structs::PyFrame;
+
+/// Layout of a frame grid: one column per m/z bin, and one row per scan or,
+/// with `im_bins`, per 1/K0 bin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GridParams {
+    pub mz_bins: BinningParams,
+    pub im_bins: Option<BinningParams>,
+    /// Number of rows when binning by scan.
+    pub num_scans: usize,
+}
+
+impl GridParams {
+    /// Bins of `bin_width` m/z over `mz_range`, and of `im_bin_width` 1/K0
+    /// over `im_range` when given.
+    pub fn new(
+        bin_width: f64,
+        mz_range: (f64, f64),
+        im_bin_width: Option<f64>,
+        im_range: (f64, f64),
+        num_scans: usize,
+    ) -> PyResult<Self> {
+        Ok(GridParams {
+            mz_bins: BinningParams::new(bin_width, mz_range.0, mz_range.1)?,
+            im_bins: im_bin_width
+                .map(|x| BinningParams::new(x, im_range.0, im_range.1))
+                .transpose()?,
+            num_scans,
+        })
+    }
+}
+
+/// Non-empty cells of a frame as (row, column, summed intensity), by cell.
+fn grid_cells(
+    frame: &PyFrame,
+    mz_converter: &Tof2MzConverter,
+    im_converter: &Scan2ImConverter,
+    params: &GridParams,
+) -> Vec<(u32, u32, f64)> {
+    let mut cells = vec![];
+    for scan in 0..frame.scan_offsets.len().saturating_sub(1) {
+        let row = match &params.im_bins {
+            Some(x) => x.bin_index(im_converter.convert(scan as f64)),
+            None => Some(scan).filter(|x| *x < params.num_scans),
+        };
+        let Some(row) = row else {
+            continue;
+        };
+        for peak in frame.scan_offsets[scan]..frame.scan_offsets[scan + 1] {
+            let mz = mz_converter.convert(frame.tof_indices[peak] as f64);
+            if let Some(column) = params.mz_bins.bin_index(mz) {
+                cells.push((row as u32, column as u32, frame.intensities[peak] as f64));
+            }
+        }
+    }
+    cells.sort_by_key(|x| (x.0, x.1));
+    cells.dedup_by(|next, kept| {
+        let same_cell = (next.0, next.1) == (kept.0, kept.1);
+        if same_cell {
+            kept.2 += next.2;
+        }
+        same_cell
+    });
+    cells
+}
+
+/// Frames rendered on a (frame, mobility, m/z) grid, stored sparse as
+/// coordinates of the non-empty cells. Intensities are the summed raw
+/// intensities of all events in a cell.
+#[pyclass(name = "FrameGrid")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PyFrameGrid {
+    #[pyo3(get)]
+    pub frame_indices: Vec<usize>,
+    #[pyo3(get)]
+    pub rt_values: Vec<f64>,
+    /// 1/K0 of every row: of the scan, or the center of the 1/K0 bin.
+    #[pyo3(get)]
+    pub mobility_values: Vec<f64>,
+    /// Center m/z of every column.
+    #[pyo3(get)]
+    pub mz_values: Vec<f64>,
+    /// True when rows are scans, false when they are 1/K0 bins.
+    #[pyo3(get)]
+    pub by_scan: bool,
+    /// Cell coordinates and intensities of the non-empty cells.
+    #[pyo3(get)]
+    pub frame_positions: Vec<u32>,
+    #[pyo3(get)]
+    pub rows: Vec<u32>,
+    #[pyo3(get)]
+    pub columns: Vec<u32>,
+    #[pyo3(get)]
+    pub values: Vec<f64>,
+}
+
+impl PyFrameGrid {
+    pub fn from_frames(
+        frames: &[PyFrame],
+        mz_converter: &Tof2MzConverter,
+        im_converter: &Scan2ImConverter,
+        params: &GridParams,
+    ) -> Self {
+        let cells: Vec<Vec<(u32, u32, f64)>> = frames
+            .par_iter()
+            .map(|x| grid_cells(x, mz_converter, im_converter, params))
+            .collect();
+        let mut grid = PyFrameGrid {
+            frame_indices: frames.iter().map(|x| x.index).collect(),
+            rt_values: frames.iter().map(|x| x.rt).collect(),
+            mobility_values: match &params.im_bins {
+                Some(x) => x.bin_centers(),
+                None => (0..params.num_scans)
+                    .map(|x| im_converter.convert(x as f64))
+                    .collect(),
+            },
+            mz_values: params.mz_bins.bin_centers(),
+            by_scan: params.im_bins.is_none(),
+            frame_positions: vec![],
+            rows: vec![],
+            columns: vec![],
+            values: vec![],
+        };
+        for (position, frame_cells) in cells.into_iter().enumerate() {
+            for (row, column, value) in frame_cells {
+                grid.frame_positions.push(position as u32);
+                grid.rows.push(row);
+                grid.columns.push(column);
+                grid.values.push(value);
+            }
+        }
+        grid
+    }
+}
+
+#[pymethods]
+impl PyFrameGrid {
+    pub fn __repr__(slf: &Bound<'_, Self>) -> PyResult<String> {
+        let class_name: Bound<'_, PyString> = slf.get_type().qualname()?;
+        let x = slf.borrow();
+        Ok(format!(
+            "{}(shape={:?}, by_scan={}, nnz={})",
+            class_name,
+            x.shape(),
+            x.by_scan,
+            x.values.len(),
+        ))
+    }
+
+    /// (num_frames, num_rows, num_mz_bins)
+    #[getter]
+    pub fn shape(&self) -> (usize, usize, usize) {
+        (
+            self.frame_indices.len(),
+            self.mobility_values.len(),
+            self.mz_values.len(),
+        )
+    }
+
+    /// A dense float64 numpy array of `shape`.
+    pub fn to_numpy<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let (_, num_rows, num_columns) = self.shape();
+        let (frame_size, row_size) = (num_rows * num_columns, num_columns);
+        let values = (0..self.values.len()).map(|i| {
+            (
+                self.frame_positions[i] as usize * frame_size
+                    + self.rows[i] as usize * row_size
+                    + self.columns[i] as usize,
+                self.values[i],
+            )
+        });
+        dense_numpy_array(py, self.frame_indices.len() * frame_size, values)?
+            .call_method1("reshape", (self.shape(),))
+    }
+
+    /// A `scipy.sparse.csr_matrix` of shape (num_frames * num_rows,
+    /// num_mz_bins), with the rows of each frame one after the other.
+    pub fn to_scipy<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let sparse = py.import("scipy.sparse")?;
+        let (num_frames, num_rows, num_columns) = self.shape();
+        let flat_rows = self
+            .frame_positions
+            .iter()
+            .zip(self.rows.iter())
+            .map(|(frame, row)| *frame as i64 * num_rows as i64 + *row as i64);
+        let values = numpy_array(py, self.values.iter().map(|x| x.to_le_bytes()), "<f8")?;
+        let rows = numpy_array(py, flat_rows.map(|x| x.to_le_bytes()), "<i8")?;
+        let columns = numpy_array(
+            py,
+            self.columns.iter().map(|x| (*x as i64).to_le_bytes()),
+            "<i8",
+        )?;
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("shape", (num_frames * num_rows, num_columns))?;
+        sparse
+            .getattr("coo_matrix")?
+            .call(((values, (rows, columns)),), Some(&kwargs))?
+            .call_method0("tocsr")
+    }
+}
@@ -0,0 +1,222 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::RowAccessor;
+use pyo3::exceptions::PyIOError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::tdf_sql::{ReadableSqlTable, SqlFrameInfo, TdfSqlReader};
+use crate::timsrust_enums::PyAcquisitionType;
+
+const MINITDF_GLOBAL_METADATA: &str = "converter.GlobalMetaData.ms2.parquet";
+
+/// Key/value pairs of the GlobalMetadata table, in table order.
+pub fn read_global_metadata_entries(path: &Path) -> PyResult<Vec<(String, String)>> {
+    let parquet_path = if path.is_dir() {
+        path.join(MINITDF_GLOBAL_METADATA)
+    } else {
+        path.to_path_buf()
+    };
+    let is_parquet = parquet_path.extension().and_then(|x| x.to_str()) == Some("parquet");
+    if is_parquet && parquet_path.exists() {
+        return read_parquet_entries(&parquet_path).map_err(|e| PyIOError::new_err(e.to_string()));
+    }
+    let tdf_sql_reader = TdfSqlReader::open(path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+    read_sql_entries(&tdf_sql_reader).map_err(|e| PyIOError::new_err(e.to_string()))
+}
+
+fn read_sql_entries(tdf_sql_reader: &TdfSqlReader) -> rusqlite::Result<Vec<(String, String)>> {
+    let mut stmt = tdf_sql_reader
+        .connection()
+        .prepare("SELECT Key, Value FROM GlobalMetadata")?;
+    let rows = stmt.query_map([], |row| {
+        let value: Option<String> = row.get(1).ok();
+        Ok((row.get(0)?, value.unwrap_or_default()))
+    })?;
+    rows.collect()
+}
+
+fn read_parquet_entries(path: &Path) -> parquet::errors::Result<Vec<(String, String)>> {
+    let reader = SerializedFileReader::new(File::open(path)?)?;
+    let columns = reader
+        .metadata()
+        .file_metadata()
+        .schema_descr()
+        .columns()
+        .to_vec();
+    let position = |name: &str| columns.iter().position(|x| x.name() == name);
+    let (Some(key), Some(value)) = (position("Key"), position("Value")) else {
+        return Err(parquet::errors::ParquetError::General(
+            "Expected Key and Value columns".to_string(),
+        ));
+    };
+    reader
+        .get_row_iter(None)?
+        .map(|row| {
+            Ok((
+                row.get_string(key)?.clone(),
+                row.get_string(value).cloned().unwrap_or_default(),
+            ))
+        })
+        .collect()
+}
+
+/// Acquisition type from the msms types of the Frames table (Unknown for minitdf).
+fn read_acquisition_type(path: &Path) -> PyAcquisitionType {
+    let Ok(frames) = TdfSqlReader::open(path).and_then(|x| SqlFrameInfo::from_sql_reader(&x))
+    else {
+        return PyAcquisitionType::Unknown;
+    };
+    PyAcquisitionType::from_msms_types(frames.iter().map(|x| x.msms_type))
+}
+
+/// The GlobalMetadata table of a `.d` folder (analysis.tdf) or `.ms2`
+/// minitdf folder (converter.GlobalMetaData.ms2.parquet).
+///
+/// Common keys are available as typed attributes, which are None when the
+/// key is not in the table. All keys are available with `to_dict`.
+#[pyclass(name = "GlobalMetadata")]
+pub struct PyGlobalMetadata {
+    #[pyo3(get)]
+    pub path: PathBuf,
+    #[pyo3(get)]
+    pub acquisition_type: PyAcquisitionType,
+    #[pyo3(get)]
+    pub schema_type: Option<String>,
+    #[pyo3(get)]
+    pub schema_version: Option<(u32, u32)>,
+    #[pyo3(get)]
+    pub instrument_name: Option<String>,
+    #[pyo3(get)]
+    pub instrument_vendor: Option<String>,
+    #[pyo3(get)]
+    pub instrument_serial_number: Option<String>,
+    #[pyo3(get)]
+    pub acquisition_software: Option<String>,
+    #[pyo3(get)]
+    pub acquisition_software_vendor: Option<String>,
+    #[pyo3(get)]
+    pub acquisition_software_version: Option<String>,
+    #[pyo3(get)]
+    pub acquisition_firmware_version: Option<String>,
+    /// ISO 8601 string, as stored by the acquisition software.
+    #[pyo3(get)]
+    pub acquisition_date_time: Option<String>,
+    #[pyo3(get)]
+    pub operator_name: Option<String>,
+    #[pyo3(get)]
+    pub sample_name: Option<String>,
+    #[pyo3(get)]
+    pub method_name: Option<String>,
+    #[pyo3(get)]
+    pub description: Option<String>,
+    #[pyo3(get)]
+    pub analysis_id: Option<String>,
+    #[pyo3(get)]
+    pub closed_properly: Option<bool>,
+    #[pyo3(get)]
+    pub tims_compression_type: Option<u8>,
+    #[pyo3(get)]
+    pub max_num_peaks_per_scan: Option<u64>,
+    #[pyo3(get)]
+    pub mz_acq_range: Option<(f64, f64)>,
+    #[pyo3(get)]
+    pub one_over_k0_acq_range: Option<(f64, f64)>,
+    pub entries: Vec<(String, String)>,
+}
+
+impl PyGlobalMetadata {
+    pub fn from_entries(
+        path: PathBuf,
+        acquisition_type: PyAcquisitionType,
+        entries: Vec<(String, String)>,
+    ) -> Self {
+        // minitdf files use lowerCamelCase for some of the keys
+        let text = |key: &str| {
+            entries
+                .iter()
+                .find(|(x, _)| x.eq_ignore_ascii_case(key))
+                .map(|(_, value)| value.clone())
+        };
+        let number = |key: &str| text(key).and_then(|x| x.trim().parse::<f64>().ok());
+        let pair = |lower: &str, upper: &str| number(lower).zip(number(upper));
+        let schema_version = text("SchemaVersionMajor")
+            .and_then(|x| x.parse().ok())
+            .zip(text("SchemaVersionMinor").and_then(|x| x.parse().ok()));
+        PyGlobalMetadata {
+            path,
+            acquisition_type,
+            schema_type: text("SchemaType"),
+            schema_version,
+            instrument_name: text("InstrumentName"),
+            instrument_vendor: text("InstrumentVendor"),
+            instrument_serial_number: text("InstrumentSerialNumber"),
+            acquisition_software: text("AcquisitionSoftware"),
+            acquisition_software_vendor: text("AcquisitionSoftwareVendor"),
+            acquisition_software_version: text("AcquisitionSoftwareVersion"),
+            acquisition_firmware_version: text("AcquisitionFirmwareVersion"),
+            acquisition_date_time: text("AcquisitionDateTime"),
+            operator_name: text("OperatorName"),
+            sample_name: text("SampleName"),
+            method_name: text("MethodName"),
+            description: text("Description"),
+            analysis_id: text("AnalysisId"),
+            closed_properly: text("ClosedProperly").map(|x| x == "1" || x == "True"),
+            tims_compression_type: text("TimsCompressionType").and_then(|x| x.parse().ok()),
+            max_num_peaks_per_scan: text("MaxNumPeaksPerScan").and_then(|x| x.parse().ok()),
+            mz_acq_range: pair("MzAcqRangeLower", "MzAcqRangeUpper"),
+            one_over_k0_acq_range: pair("OneOverK0AcqRangeLower", "OneOverK0AcqRangeUpper"),
+            entries,
+        }
+    }
+}
+
+#[pymethods]
+impl PyGlobalMetadata {
+    /// Accepts a `.d` or `.ms2` folder, an analysis.tdf file or a
+    /// GlobalMetaData parquet file.
+    #[new]
+    pub fn new(path: PathBuf) -> PyResult<Self> {
+        let entries = read_global_metadata_entries(&path)?;
+        let acquisition_type = read_acquisition_type(&path);
+        Ok(Self::from_entries(path, acquisition_type, entries))
+    }
+
+    /// All key/value pairs, with values as stored (strings).
+    pub fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        for (key, value) in self.entries.iter() {
+            dict.set_item(key, value)?;
+        }
+        Ok(dict)
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.entries
+            .iter()
+            .find(|(x, _)| x == key)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn __len__(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn __repr__(&self) -> String {
+        let quoted = |x: &Option<String>| match x {
+            Some(x) => format!("'{}'", x),
+            None => "None".to_string(),
+        };
+        format!(
+            "GlobalMetadata(path='{}', acquisition_type={}, instrument_name={}, sample_name={}, acquisition_date_time={}, len={})",
+            self.path.to_str().unwrap_or("None"),
+            self.acquisition_type,
+            quoted(&self.instrument_name),
+            quoted(&self.sample_name),
+            quoted(&self.acquisition_date_time),
+            self.entries.len()
+        )
+    }
+}
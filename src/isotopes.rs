@@ -0,0 +1,70 @@
+/// Mass difference between 13C and 12C.
+pub const ISOTOPE_SPACING: f64 = 1.003_354_835;
+
+/// Peaks of one isotope envelope, by index, from the monoisotopic peak up.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IsotopeEnvelope {
+    pub charge: usize,
+    pub peaks: Vec<usize>,
+}
+
+/// Index of the compatible peak closest to `mz` within `tolerance_ppm`, in
+/// peaks sorted by m/z.
+pub fn closest_peak(
+    mzs: &[f64],
+    mz: f64,
+    tolerance_ppm: f64,
+    is_compatible: impl Fn(usize) -> bool,
+) -> Option<usize> {
+    let tolerance = mz * tolerance_ppm / 1e6;
+    let start = mzs.partition_point(|x| *x < mz - tolerance);
+    let end = mzs.partition_point(|x| *x <= mz + tolerance);
+    (start..end)
+        .filter(|i| is_compatible(*i))
+        .min_by(|a, b| (mzs[*a] - mz).abs().total_cmp(&(mzs[*b] - mz).abs()))
+}
+
+/// The isotope envelope around `seed`, in peaks sorted by m/z, for the
+/// charge (up to `max_charge`) that explains the most peaks. Lower isotopes
+/// are only followed while they keep at least `min_ratio` of the intensity
+/// of the next one, so the envelope starts at the monoisotopic peak.
+/// None when no charge finds a single isotope.
+pub fn isotope_envelope(
+    mzs: &[f64],
+    intensities: &[f64],
+    seed: usize,
+    max_charge: usize,
+    tolerance_ppm: f64,
+    min_ratio: f64,
+    is_compatible: impl Fn(usize) -> bool,
+) -> Option<IsotopeEnvelope> {
+    let mut best: Option<IsotopeEnvelope> = None;
+    for charge in 1..=max_charge {
+        let spacing = ISOTOPE_SPACING / charge as f64;
+        let mut lower = vec![];
+        let mut current = seed;
+        while let Some(peak) = closest_peak(mzs, mzs[current] - spacing, tolerance_ppm, |i| {
+            i != seed && !lower.contains(&i) && is_compatible(i)
+        }) {
+            if intensities[peak] < min_ratio * intensities[current] {
+                break;
+            }
+            lower.push(peak);
+            current = peak;
+        }
+        let mut peaks: Vec<usize> = lower.into_iter().rev().collect();
+        peaks.push(seed);
+        let mut current = seed;
+        while let Some(peak) = closest_peak(mzs, mzs[current] + spacing, tolerance_ppm, |i| {
+            !peaks.contains(&i) && is_compatible(i)
+        }) {
+            peaks.push(peak);
+            current = peak;
+        }
+        // On ties the higher charge wins, as it also explains the peaks in between
+        if peaks.len() > 1 && best.as_ref().is_none_or(|x| peaks.len() >= x.peaks.len()) {
+            best = Some(IsotopeEnvelope { charge, peaks });
+        }
+    }
+    best
+}
@@ -1,54 +1,114 @@
+pub mod arrow_stream;
+pub mod binning;
+pub mod ccs;
+pub mod convert;
+pub mod cycles;
+pub mod dataframes;
+pub mod dia_extraction;
+pub mod features;
+pub mod fragments;
+pub mod frame_grids;
+pub mod global_metadata;
+pub mod isotopes;
+pub mod library;
+pub mod maldi;
+pub mod method;
+pub mod peak_picking;
+pub mod precursor_envelopes;
+pub mod prm;
+pub mod similarity;
+pub mod spectrum_processing;
+pub mod spectrum_sources;
+pub mod summary;
+pub mod tdf_sql;
 pub mod timsrust_converters;
 pub mod timsrust_enums;
 pub mod timsrust_readers;
 pub mod timsrust_structs;
 
-use pyo3::exceptions::PyIOError;
 use pyo3::prelude::*;
 
+use crate::binning::{PyBinnedSpectra, PyBinnedSpectraIterator};
+use crate::ccs::{py_ccs_to_im, py_im_to_ccs, PyDriftGas};
+use crate::cycles::{PyCycle, PyCycleIterator};
+use crate::dataframes::{to_arrow, to_columns, to_pandas, to_polars, PyArrowTable};
+use crate::dia_extraction::{PyDiaTarget, PyDiaTraces};
+use crate::features::PyFeature;
+use crate::fragments::{py_fragment_ions, PyFragmentAnnotation, PyFragmentIon};
+use crate::frame_grids::PyFrameGrid;
+use crate::global_metadata::PyGlobalMetadata;
+use crate::library::{PyLibraryHit, PyLibrarySpectrum, PySpectralLibrary};
+use crate::maldi::{PyIonImage, PyMaldiFrameInfo};
+use crate::method::{PyAcquisitionSettings, PyDiaWindow, PyLcModule, PyMethodReader, PyPrmTarget};
+use crate::peak_picking::PyPickedPeaks;
+use crate::precursor_envelopes::PyIsotopeEnvelope;
+use crate::prm::PyPrmTransitions;
+use crate::similarity::py_pairwise_similarity;
+use crate::summary::{summarize, PySummary};
+use crate::tdf_sql::query_tdf;
 use crate::timsrust_enums::{PyAcquisitionType, PyMSLevel};
 use crate::timsrust_readers::{PyFrameReader, PySpectrumReader};
 use crate::timsrust_structs::{PyFrame, PyMetadata, PyPrecursor, PyQuadrupoleSettings, PySpectrum};
 
 #[pyfunction]
 fn read_all_frames(path: String) -> PyResult<Vec<PyFrame>> {
-    let reader = timsrust::readers::FrameReader::new(&path).unwrap();
-    let tims_reader = PyFrameReader { reader, i: 0 };
-    tims_reader.read_all_frames()
+    PyFrameReader::new(&path)?.read_all_frames()
 }
 
 #[pyfunction]
 fn read_all_spectra(path: String) -> PyResult<Vec<PySpectrum>> {
-    let reader = timsrust::readers::SpectrumReader::build()
-        .with_path(&path)
-        .finalize();
-
-    if reader.is_err() {
-        return Err(PyIOError::new_err(reader.err().unwrap().to_string()));
-    }
-    let reader = reader.unwrap();
-    reader
-        .get_all()
-        .into_iter()
-        .map(|x| match x {
-            Ok(x) => Ok(PySpectrum::from(x)),
-            Err(e) => Err(PyIOError::new_err(e.to_string())),
-        })
-        .collect()
+    PySpectrumReader::new(&path, false, false, None)?.read_all_spectra()
 }
 
 #[pymodule]
 fn timsrust_pyo3(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(read_all_frames, m)?)?;
     m.add_function(wrap_pyfunction!(read_all_spectra, m)?)?;
+    m.add_function(wrap_pyfunction!(summarize, m)?)?;
+    m.add_function(wrap_pyfunction!(query_tdf, m)?)?;
+    m.add_function(wrap_pyfunction!(py_im_to_ccs, m)?)?;
+    m.add_function(wrap_pyfunction!(py_ccs_to_im, m)?)?;
+    m.add_function(wrap_pyfunction!(py_pairwise_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(py_fragment_ions, m)?)?;
+    m.add_function(wrap_pyfunction!(to_columns, m)?)?;
+    m.add_function(wrap_pyfunction!(to_arrow, m)?)?;
+    m.add_function(wrap_pyfunction!(to_polars, m)?)?;
+    m.add_function(wrap_pyfunction!(to_pandas, m)?)?;
+    m.add_class::<PyArrowTable>()?;
+    m.add_class::<PyBinnedSpectra>()?;
+    m.add_class::<PyBinnedSpectraIterator>()?;
+    m.add_class::<PyCycle>()?;
+    m.add_class::<PyCycleIterator>()?;
     m.add_class::<PyFrame>()?;
     m.add_class::<PyFrameReader>()?;
+    m.add_class::<PyDiaTarget>()?;
+    m.add_class::<PyDiaTraces>()?;
+    m.add_class::<PyFrameGrid>()?;
+    m.add_class::<PyFragmentIon>()?;
+    m.add_class::<PyFragmentAnnotation>()?;
     m.add_class::<PySpectrumReader>()?;
     m.add_class::<PyMetadata>()?;
+    m.add_class::<PyGlobalMetadata>()?;
+    m.add_class::<PyMethodReader>()?;
+    m.add_class::<PyDiaWindow>()?;
+    m.add_class::<PyPrmTarget>()?;
+    m.add_class::<PyPrmTransitions>()?;
+    m.add_class::<PySpectralLibrary>()?;
+    m.add_class::<PyLibrarySpectrum>()?;
+    m.add_class::<PyLibraryHit>()?;
+    m.add_class::<PyMaldiFrameInfo>()?;
+    m.add_class::<PyIonImage>()?;
+    m.add_class::<PyPickedPeaks>()?;
+    m.add_class::<PyFeature>()?;
+    m.add_class::<PyIsotopeEnvelope>()?;
+    m.add_class::<PyLcModule>()?;
+    m.add_class::<PyAcquisitionSettings>()?;
     m.add_class::<PyPrecursor>()?;
     m.add_class::<PyQuadrupoleSettings>()?;
     m.add_class::<PySpectrum>()?;
+    m.add_class::<PySummary>()?;
     m.add_class::<PyAcquisitionType>()?;
     m.add_class::<PyMSLevel>()?;
+    m.add_class::<PyDriftGas>()?;
     Ok(())
 }
@@ -0,0 +1,541 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use arrow_array::{
+    Array, ArrayRef, Float32Array, Float64Array, Int64Array, ListArray, StringArray, UInt64Array,
+};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyString;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::similarity::{score_peaks, sort_peaks, SimilarityMetric, Tolerance};
+use crate::timsrust_structs::PySpectrum;
+
+#[derive(Debug)]
+pub enum LibraryError {
+    Io(String),
+    Format(String),
+}
+
+impl Display for LibraryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LibraryError::Io(e) => write!(f, "Could not read library: {}", e),
+            LibraryError::Format(e) => write!(f, "Invalid library: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for LibraryError {
+    fn from(e: std::io::Error) -> Self {
+        LibraryError::Io(e.to_string())
+    }
+}
+
+impl From<parquet::errors::ParquetError> for LibraryError {
+    fn from(e: parquet::errors::ParquetError) -> Self {
+        LibraryError::Format(e.to_string())
+    }
+}
+
+impl From<arrow_schema::ArrowError> for LibraryError {
+    fn from(e: arrow_schema::ArrowError) -> Self {
+        LibraryError::Format(e.to_string())
+    }
+}
+
+impl From<LibraryError> for PyErr {
+    fn from(e: LibraryError) -> Self {
+        match e {
+            LibraryError::Io(_) => PyIOError::new_err(e.to_string()),
+            LibraryError::Format(_) => PyValueError::new_err(e.to_string()),
+        }
+    }
+}
+
+#[pyclass(name = "LibrarySpectrum")]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PyLibrarySpectrum {
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub precursor_mz: f64,
+    #[pyo3(get)]
+    pub charge: Option<usize>,
+    #[pyo3(get)]
+    pub mz_values: Vec<f64>,
+    #[pyo3(get)]
+    pub intensities: Vec<f64>,
+}
+
+#[pymethods]
+impl PyLibrarySpectrum {
+    pub fn __repr__(&self) -> String {
+        format!(
+            "LibrarySpectrum(name={}, precursor_mz={}, charge={}, num_peaks={})",
+            self.name,
+            self.precursor_mz,
+            match self.charge {
+                Some(x) => x.to_string(),
+                None => "None".to_string(),
+            },
+            self.mz_values.len(),
+        )
+    }
+}
+
+/// A library spectrum matching a query spectrum.
+#[pyclass(name = "LibraryHit")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PyLibraryHit {
+    /// Index of the query spectrum.
+    #[pyo3(get)]
+    pub spectrum_index: usize,
+    /// Position of the library spectrum in the library, by precursor m/z.
+    #[pyo3(get)]
+    pub library_index: usize,
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub precursor_mz: f64,
+    #[pyo3(get)]
+    pub charge: Option<usize>,
+    /// Starting from 1 for the best hit of a spectrum.
+    #[pyo3(get)]
+    pub rank: usize,
+    #[pyo3(get)]
+    pub score: f64,
+    #[pyo3(get)]
+    pub matched_peaks: usize,
+    #[pyo3(get)]
+    pub precursor_error_ppm: f64,
+}
+
+#[pymethods]
+impl PyLibraryHit {
+    pub fn __repr__(&self) -> String {
+        format!(
+            "LibraryHit(spectrum_index={}, name={}, rank={}, score={}, matched_peaks={}, precursor_error_ppm={})",
+            self.spectrum_index,
+            self.name,
+            self.rank,
+            self.score,
+            self.matched_peaks,
+            self.precursor_error_ppm,
+        )
+    }
+}
+
+/// Settings of a library search.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchParams {
+    pub precursor_tolerance: Tolerance,
+    pub fragment_tolerance: Tolerance,
+    pub metric: SimilarityMetric,
+    pub top_n: usize,
+    pub min_matched_peaks: usize,
+}
+
+/// Charge from a peptide style name such as `PEPTIDE/2`.
+fn charge_from_name(name: &str) -> Option<usize> {
+    let (_, charge) = name.rsplit_once('/')?;
+    charge.split('_').next()?.parse().ok()
+}
+
+/// Value of `key=value` in an MSP comment, as used by NIST libraries.
+fn comment_value<'a>(comment: &'a str, key: &str) -> Option<&'a str> {
+    comment
+        .split_whitespace()
+        .find_map(|x| x.strip_prefix(key)?.strip_prefix('='))
+}
+
+/// Spectra of an MSP file. The precursor m/z is read from `PrecursorMZ` or
+/// a `Parent=` comment, the charge from `Charge`, `Charge=` in the comment
+/// or a `/charge` name suffix.
+pub fn read_msp(path: &Path) -> Result<Vec<PyLibrarySpectrum>, LibraryError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = vec![];
+    let mut current: Option<PyLibrarySpectrum> = None;
+    let mut comment = String::new();
+    let mut finish = |entry: Option<PyLibrarySpectrum>, comment: &str| {
+        if let Some(mut entry) = entry {
+            if entry.precursor_mz.is_nan() {
+                entry.precursor_mz = comment_value(comment, "Parent")
+                    .and_then(|x| x.parse().ok())
+                    .unwrap_or(f64::NAN);
+            }
+            if entry.charge.is_none() {
+                entry.charge = comment_value(comment, "Charge")
+                    .and_then(|x| x.trim_end_matches('+').parse().ok())
+                    .or_else(|| charge_from_name(&entry.name));
+            }
+            if entry.precursor_mz.is_nan() {
+                return Err(LibraryError::Format(format!(
+                    "No precursor m/z for '{}'",
+                    entry.name
+                )));
+            }
+            entries.push(entry);
+        }
+        Ok(())
+    };
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = || LibraryError::Format(format!("line {}: '{}'", number + 1, line));
+        if let Some((key, value)) = line.split_once(':') {
+            let value = value.trim();
+            match key.trim().to_lowercase().as_str() {
+                "name" => {
+                    finish(current.take(), &comment)?;
+                    comment.clear();
+                    current = Some(PyLibrarySpectrum {
+                        name: value.to_string(),
+                        precursor_mz: f64::NAN,
+                        ..Default::default()
+                    });
+                }
+                "precursormz" | "precursor_mz" => {
+                    if let Some(entry) = current.as_mut() {
+                        entry.precursor_mz = value.parse().map_err(|_| invalid())?;
+                    }
+                }
+                "charge" => {
+                    if let Some(entry) = current.as_mut() {
+                        entry.charge = value.trim_end_matches('+').parse().ok();
+                    }
+                }
+                "comment" => comment = value.to_string(),
+                _ => {}
+            }
+            continue;
+        }
+        let Some(entry) = current.as_mut() else {
+            continue;
+        };
+        // Peaks are "mz intensity [annotation]", several per line when
+        // separated by semicolons
+        for peak in line.split(';').filter(|x| !x.trim().is_empty()) {
+            let mut values = peak
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|x| !x.is_empty());
+            let (Some(mz), Some(intensity)) = (values.next(), values.next()) else {
+                return Err(invalid());
+            };
+            entry.mz_values.push(mz.parse().map_err(|_| invalid())?);
+            entry
+                .intensities
+                .push(intensity.parse().map_err(|_| invalid())?);
+        }
+    }
+    finish(current.take(), &comment)?;
+    Ok(entries)
+}
+
+fn float_list(array: &ArrayRef, row: usize) -> Result<Vec<f64>, LibraryError> {
+    let list = array
+        .as_any()
+        .downcast_ref::<ListArray>()
+        .ok_or_else(|| LibraryError::Format("peak columns must be lists".to_string()))?;
+    let values = list.value(row);
+    if let Some(x) = values.as_any().downcast_ref::<Float64Array>() {
+        return Ok(x.values().to_vec());
+    }
+    if let Some(x) = values.as_any().downcast_ref::<Float32Array>() {
+        return Ok(x.values().iter().map(|v| *v as f64).collect());
+    }
+    Err(LibraryError::Format(
+        "peak columns must be lists of floats".to_string(),
+    ))
+}
+
+fn optional_usize(array: Option<&ArrayRef>, row: usize) -> Option<usize> {
+    let array = array?;
+    if array.is_null(row) {
+        return None;
+    }
+    if let Some(x) = array.as_any().downcast_ref::<UInt64Array>() {
+        return Some(x.value(row) as usize);
+    }
+    let x = array.as_any().downcast_ref::<Int64Array>()?;
+    usize::try_from(x.value(row)).ok()
+}
+
+/// Spectra of a parquet library with a float `precursor_mz` column and float
+/// list columns `mz_values` and `intensities`, and optionally `name` and
+/// `precursor_charge` columns. Files written by `timsrust-convert` follow
+/// this schema. Rows without precursor m/z are skipped.
+pub fn read_parquet_library(path: &Path) -> Result<Vec<PyLibrarySpectrum>, LibraryError> {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?.build()?;
+    let mut entries = vec![];
+    for batch in reader {
+        let batch = batch?;
+        let missing = |x: &str| LibraryError::Format(format!("missing column '{}'", x));
+        let precursor_mz = batch
+            .column_by_name("precursor_mz")
+            .and_then(|x| x.as_any().downcast_ref::<Float64Array>())
+            .ok_or_else(|| missing("precursor_mz"))?;
+        let mz_values = batch
+            .column_by_name("mz_values")
+            .ok_or_else(|| missing("mz_values"))?;
+        let intensities = batch
+            .column_by_name("intensities")
+            .ok_or_else(|| missing("intensities"))?;
+        let names = batch
+            .column_by_name("name")
+            .and_then(|x| x.as_any().downcast_ref::<StringArray>());
+        let charges = batch.column_by_name("precursor_charge");
+        for row in 0..batch.num_rows() {
+            if precursor_mz.is_null(row) {
+                continue;
+            }
+            entries.push(PyLibrarySpectrum {
+                name: match names {
+                    Some(x) if !x.is_null(row) => x.value(row).to_string(),
+                    _ => entries.len().to_string(),
+                },
+                precursor_mz: precursor_mz.value(row),
+                charge: optional_usize(charges, row).filter(|x| *x > 0),
+                mz_values: float_list(mz_values, row)?,
+                intensities: float_list(intensities, row)?,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Spectral library indexed by precursor m/z.
+#[pyclass(name = "SpectralLibrary")]
+pub struct PySpectralLibrary {
+    /// Sorted by precursor m/z.
+    pub entries: Vec<PyLibrarySpectrum>,
+    /// Peaks of every entry as from `sort_peaks`.
+    peaks: Vec<Vec<(f64, f64)>>,
+}
+
+impl PySpectralLibrary {
+    pub fn from_entries(mut entries: Vec<PyLibrarySpectrum>) -> Self {
+        entries.sort_by(|a, b| a.precursor_mz.total_cmp(&b.precursor_mz));
+        let peaks = entries
+            .iter()
+            .map(|x| sort_peaks(&x.mz_values, &x.intensities))
+            .collect();
+        PySpectralLibrary { entries, peaks }
+    }
+
+    /// The `top_n` best hits of `spectrum` among entries within the precursor
+    /// tolerance and of compatible charge, best first.
+    pub fn search_spectrum(
+        &self,
+        spectrum: &PySpectrum,
+        params: &SearchParams,
+    ) -> Vec<PyLibraryHit> {
+        let (precursor_mz, charge) = match &spectrum.precursor {
+            Some(x) => (x.mz, x.charge.filter(|x| *x > 0)),
+            None => (spectrum.isolation_mz, None),
+        };
+        if !precursor_mz.is_finite() || precursor_mz <= 0.0 {
+            return vec![];
+        }
+        let width = params.precursor_tolerance.width(precursor_mz);
+        let start = self
+            .entries
+            .partition_point(|x| x.precursor_mz < precursor_mz - width);
+        let end = self
+            .entries
+            .partition_point(|x| x.precursor_mz <= precursor_mz + width);
+        let peaks = sort_peaks(&spectrum.mz_values, &spectrum.intensities);
+        let mut hits: Vec<PyLibraryHit> = (start..end)
+            .filter(|i| match (charge, self.entries[*i].charge) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            })
+            .filter_map(|i| {
+                let (score, matched_peaks) = score_peaks(
+                    &peaks,
+                    &self.peaks[i],
+                    params.metric,
+                    &params.fragment_tolerance,
+                );
+                if matched_peaks < params.min_matched_peaks {
+                    return None;
+                }
+                let entry = &self.entries[i];
+                Some(PyLibraryHit {
+                    spectrum_index: spectrum.index,
+                    library_index: i,
+                    name: entry.name.clone(),
+                    precursor_mz: entry.precursor_mz,
+                    charge: entry.charge,
+                    rank: 0,
+                    score,
+                    matched_peaks,
+                    precursor_error_ppm: (precursor_mz - entry.precursor_mz) / entry.precursor_mz
+                        * 1e6,
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(params.top_n);
+        for (rank, hit) in hits.iter_mut().enumerate() {
+            hit.rank = rank + 1;
+        }
+        hits
+    }
+
+    pub fn search_spectra(
+        &self,
+        spectra: &[PySpectrum],
+        params: &SearchParams,
+    ) -> Vec<Vec<PyLibraryHit>> {
+        spectra
+            .par_iter()
+            .map(|x| self.search_spectrum(x, params))
+            .collect()
+    }
+}
+
+/// Search settings from the python keyword arguments shared by all search
+/// methods.
+pub fn search_params(
+    precursor_tolerance: f64,
+    precursor_unit: &str,
+    fragment_tolerance: f64,
+    fragment_unit: &str,
+    metric: &str,
+    top_n: usize,
+    min_matched_peaks: usize,
+) -> PyResult<SearchParams> {
+    Ok(SearchParams {
+        precursor_tolerance: Tolerance::parse(precursor_tolerance, precursor_unit)?,
+        fragment_tolerance: Tolerance::parse(fragment_tolerance, fragment_unit)?,
+        metric: SimilarityMetric::parse(metric)?,
+        top_n,
+        min_matched_peaks,
+    })
+}
+
+#[pymethods]
+impl PySpectralLibrary {
+    /// Reads an MSP (.msp) or parquet (.parquet) library, see `read_msp`
+    /// and `read_parquet_library` for the supported fields.
+    #[new]
+    pub fn new(path: &str) -> PyResult<Self> {
+        let path = Path::new(path);
+        let extension = path
+            .extension()
+            .and_then(|x| x.to_str())
+            .map(|x| x.to_lowercase());
+        let entries = match extension.as_deref() {
+            Some("msp") => read_msp(path)?,
+            Some("parquet") => read_parquet_library(path)?,
+            _ => {
+                return Err(PyValueError::new_err(
+                    "Unknown library format, expected a .msp or .parquet file",
+                ))
+            }
+        };
+        Ok(Self::from_entries(entries))
+    }
+
+    pub fn __len__(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn __repr__(slf: &Bound<'_, Self>) -> PyResult<String> {
+        let class_name: Bound<'_, PyString> = slf.get_type().qualname()?;
+        Ok(format!(
+            "{}(num_spectra={})",
+            class_name,
+            slf.borrow().entries.len()
+        ))
+    }
+
+    pub fn get(&self, index: usize) -> PyResult<PyLibrarySpectrum> {
+        self.entries
+            .get(index)
+            .cloned()
+            .ok_or_else(|| PyValueError::new_err(format!("No library spectrum {}", index)))
+    }
+
+    /// The `top_n` best library hits of `spectrum`, among library spectra
+    /// within `precursor_tolerance` of its precursor m/z (isolation m/z
+    /// without precursor) and with the same charge when both are known.
+    /// Fragments are scored with `metric`, see `pairwise_similarity`.
+    #[pyo3(signature = (
+        spectrum,
+        precursor_tolerance=20.0,
+        precursor_unit="ppm",
+        fragment_tolerance=20.0,
+        fragment_unit="ppm",
+        metric="cosine",
+        top_n=5,
+        min_matched_peaks=1
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn search(
+        &self,
+        spectrum: &PySpectrum,
+        precursor_tolerance: f64,
+        precursor_unit: &str,
+        fragment_tolerance: f64,
+        fragment_unit: &str,
+        metric: &str,
+        top_n: usize,
+        min_matched_peaks: usize,
+    ) -> PyResult<Vec<PyLibraryHit>> {
+        let params = search_params(
+            precursor_tolerance,
+            precursor_unit,
+            fragment_tolerance,
+            fragment_unit,
+            metric,
+            top_n,
+            min_matched_peaks,
+        )?;
+        Ok(self.search_spectrum(spectrum, &params))
+    }
+
+    /// `search` for many spectra, in parallel.
+    #[pyo3(signature = (
+        spectra,
+        precursor_tolerance=20.0,
+        precursor_unit="ppm",
+        fragment_tolerance=20.0,
+        fragment_unit="ppm",
+        metric="cosine",
+        top_n=5,
+        min_matched_peaks=1
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn search_all(
+        &self,
+        py: Python<'_>,
+        spectra: Vec<PySpectrum>,
+        precursor_tolerance: f64,
+        precursor_unit: &str,
+        fragment_tolerance: f64,
+        fragment_unit: &str,
+        metric: &str,
+        top_n: usize,
+        min_matched_peaks: usize,
+    ) -> PyResult<Vec<Vec<PyLibraryHit>>> {
+        let params = search_params(
+            precursor_tolerance,
+            precursor_unit,
+            fragment_tolerance,
+            fragment_unit,
+            metric,
+            top_n,
+            min_matched_peaks,
+        )?;
+        Ok(py.allow_threads(|| self.search_spectra(&spectra, &params)))
+    }
+}
@@ -0,0 +1,229 @@
+use pyo3::prelude::*;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use timsrust::converters::{ConvertableDomain, Scan2ImConverter, Tof2MzConverter};
+use timsrust::readers::{FrameReader, FrameReaderError};
+
+use crate::tdf_sql::{ReadableSqlTable, SqlMaldiFrame, TdfSqlReader};
+use crate::timsrust_converters::scan_range;
+
+/// Spot of a MALDI frame on the target, with the laser settings it was
+/// acquired with (MaldiFrameInfo table).
+#[pyclass(name = "MaldiFrameInfo")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PyMaldiFrameInfo {
+    /// Frame id, as in `Frame.index`.
+    #[pyo3(get)]
+    pub frame: usize,
+    #[pyo3(get)]
+    pub spot_name: String,
+    #[pyo3(get)]
+    pub chip: Option<usize>,
+    #[pyo3(get)]
+    pub region: Option<usize>,
+    /// Pixel column of the imaging raster.
+    #[pyo3(get)]
+    pub x: i64,
+    /// Pixel row of the imaging raster.
+    #[pyo3(get)]
+    pub y: i64,
+    /// Stage position in µm.
+    #[pyo3(get)]
+    pub motor_position: (Option<f64>, Option<f64>, Option<f64>),
+    #[pyo3(get)]
+    pub laser_info: Option<String>,
+    /// In percent.
+    #[pyo3(get)]
+    pub laser_power: Option<f64>,
+    /// In Hz.
+    #[pyo3(get)]
+    pub laser_rep_rate: Option<f64>,
+    #[pyo3(get)]
+    pub laser_shots: Option<usize>,
+    /// Beam scan size in µm.
+    #[pyo3(get)]
+    pub laser_beam_scan_size: (Option<f64>, Option<f64>),
+}
+
+impl From<&SqlMaldiFrame> for PyMaldiFrameInfo {
+    fn from(x: &SqlMaldiFrame) -> Self {
+        PyMaldiFrameInfo {
+            frame: x.frame,
+            spot_name: x.spot_name.clone(),
+            chip: x.chip,
+            region: x.region,
+            x: x.x,
+            y: x.y,
+            motor_position: (x.motor_x, x.motor_y, x.motor_z),
+            laser_info: x.laser_info.clone(),
+            laser_power: x.laser_power,
+            laser_rep_rate: x.laser_rep_rate,
+            laser_shots: x.laser_shots,
+            laser_beam_scan_size: (x.laser_beam_scan_size_x, x.laser_beam_scan_size_y),
+        }
+    }
+}
+
+#[pymethods]
+impl PyMaldiFrameInfo {
+    pub fn __repr__(&self) -> String {
+        format!(
+            "MaldiFrameInfo(frame={}, spot_name='{}', x={}, y={}, laser_power={})",
+            self.frame,
+            self.spot_name,
+            self.x,
+            self.y,
+            self.laser_power
+                .map_or("None".to_string(), |x| x.to_string()),
+        )
+    }
+}
+
+/// The MaldiFrameInfo rows of a run, sorted by frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MaldiLayout {
+    pub spots: Vec<SqlMaldiFrame>,
+}
+
+impl MaldiLayout {
+    /// None when the run has no MALDI frames.
+    pub fn read(tdf_sql_reader: &TdfSqlReader) -> rusqlite::Result<Option<Self>> {
+        if !tdf_sql_reader.has_table("MaldiFrameInfo")? {
+            return Ok(None);
+        }
+        let spots = SqlMaldiFrame::from_sql_reader(tdf_sql_reader)?;
+        if spots.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(MaldiLayout { spots }))
+    }
+
+    /// Spot of a frame, by frame id.
+    pub fn spot(&self, frame_id: usize) -> Option<&SqlMaldiFrame> {
+        self.spots
+            .binary_search_by_key(&frame_id, |x| x.frame)
+            .ok()
+            .map(|i| &self.spots[i])
+    }
+
+    /// Summed intensity per spot of all peaks within `tolerance_ppm` of `mz`,
+    /// optionally restricted to a 1/K0 range.
+    pub fn ion_image(
+        &self,
+        frame_reader: &FrameReader,
+        mz_converter: &Tof2MzConverter,
+        im_converter: &Scan2ImConverter,
+        mz: f64,
+        tolerance_ppm: f64,
+        im_range: Option<(f64, f64)>,
+    ) -> Result<PyIonImage, FrameReaderError> {
+        let tolerance = mz * tolerance_ppm / 1e6;
+        let lower_tof = mz_converter.invert(mz - tolerance);
+        let upper_tof = mz_converter.invert(mz + tolerance);
+        let intensities = self
+            .spots
+            .par_iter()
+            .map(|spot| {
+                let frame = frame_reader.get(spot.frame - 1)?;
+                let num_scans = frame.scan_offsets.len().saturating_sub(1);
+                let (scan_start, scan_end) = match im_range {
+                    Some((lower_im, upper_im)) => {
+                        scan_range(im_converter, lower_im, upper_im, num_scans)
+                    }
+                    None => (0, num_scans),
+                };
+                let mut intensity = 0.0;
+                for scan in scan_start..scan_end {
+                    for peak in frame.scan_offsets[scan]..frame.scan_offsets[scan + 1] {
+                        let tof = frame.tof_indices[peak] as f64;
+                        if (lower_tof..=upper_tof).contains(&tof) {
+                            intensity += frame.intensities[peak] as f64;
+                        }
+                    }
+                }
+                Ok(intensity)
+            })
+            .collect::<Result<Vec<f64>, FrameReaderError>>()?;
+        Ok(PyIonImage {
+            mz,
+            tolerance_ppm,
+            im_range,
+            x: self.spots.iter().map(|x| x.x).collect(),
+            y: self.spots.iter().map(|x| x.y).collect(),
+            frame_indices: self.spots.iter().map(|x| x.frame).collect(),
+            intensities,
+        })
+    }
+}
+
+/// Intensity of one m/z (and optional 1/K0) window for every MALDI spot.
+///
+/// `x`, `y`, `frame_indices` and `intensities` have one entry per spot;
+/// `to_grid` lays them out as a dense image.
+#[pyclass(name = "IonImage")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PyIonImage {
+    #[pyo3(get)]
+    pub mz: f64,
+    #[pyo3(get)]
+    pub tolerance_ppm: f64,
+    #[pyo3(get)]
+    pub im_range: Option<(f64, f64)>,
+    #[pyo3(get)]
+    pub x: Vec<i64>,
+    #[pyo3(get)]
+    pub y: Vec<i64>,
+    #[pyo3(get)]
+    pub frame_indices: Vec<usize>,
+    #[pyo3(get)]
+    pub intensities: Vec<f64>,
+}
+
+#[pymethods]
+impl PyIonImage {
+    /// (x_min, y_min, x_max, y_max) of the acquired spots.
+    #[getter]
+    fn bounds(&self) -> Option<(i64, i64, i64, i64)> {
+        Some((
+            *self.x.iter().min()?,
+            *self.y.iter().min()?,
+            *self.x.iter().max()?,
+            *self.y.iter().max()?,
+        ))
+    }
+
+    /// Rows (y) of columns (x) from the minimum to the maximum position,
+    /// with `fill` for positions without a spot.
+    #[pyo3(signature = (fill=0.0))]
+    pub fn to_grid(&self, fill: f64) -> Vec<Vec<f64>> {
+        let Some((x_min, y_min, x_max, y_max)) = self.bounds() else {
+            return vec![];
+        };
+        let width = (x_max - x_min + 1) as usize;
+        let height = (y_max - y_min + 1) as usize;
+        let mut grid = vec![vec![fill; width]; height];
+        for ((x, y), intensity) in self
+            .x
+            .iter()
+            .zip(self.y.iter())
+            .zip(self.intensities.iter())
+        {
+            grid[(y - y_min) as usize][(x - x_min) as usize] = *intensity;
+        }
+        grid
+    }
+
+    pub fn __len__(&self) -> usize {
+        self.intensities.len()
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "IonImage(mz={}, tolerance_ppm={}, im_range={}, num_spots={})",
+            self.mz,
+            self.tolerance_ppm,
+            self.im_range
+                .map_or("None".to_string(), |x| format!("{:?}", x)),
+            self.intensities.len(),
+        )
+    }
+}
@@ -0,0 +1,698 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use pyo3::exceptions::PyIOError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use roxmltree::{Document, Node};
+use rusqlite::Row;
+
+use crate::tdf_sql::{ParseDefault, ReadableSqlTable, TdfSqlReader};
+
+const SUBMETHODS: &str = "submethods.xml";
+const DIA_SETTINGS: &str = "diaSettings.diasqlite";
+const PRM_SETTINGS: &str = "prmSettings.prmsqlite";
+const HYSTAR_METHOD: &str = "hystar.method";
+const ACQUISITION_METHOD_SUFFIX: &str = "Acquisition.method";
+
+/// The `.m` folder of a `.d` folder (or the path itself if it is one).
+pub fn find_method_folder(path: &Path) -> Result<PathBuf, String> {
+    let is_method = |x: &Path| x.is_dir() && x.extension().and_then(|x| x.to_str()) == Some("m");
+    if is_method(path) {
+        return Ok(path.to_path_buf());
+    }
+    let d_folder = if path.is_file() {
+        path.parent().unwrap_or(path)
+    } else {
+        path
+    };
+    let mut candidates: Vec<PathBuf> = fs::read_dir(d_folder)
+        .map_err(|e| format!("{}: {}", d_folder.display(), e))?
+        .filter_map(|x| x.ok().map(|x| x.path()))
+        .filter(|x| is_method(x))
+        .collect();
+    candidates.sort();
+    candidates
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("No .m method folder in {}", d_folder.display()))
+}
+
+fn read_key_values(
+    reader: &TdfSqlReader,
+    table_name: &str,
+) -> rusqlite::Result<Vec<(String, String)>> {
+    let mut stmt = reader
+        .connection()
+        .prepare(&format!("SELECT Key, Value FROM {}", table_name))?;
+    let rows = stmt.query_map([], |row| {
+        let value: Option<String> = row.get(1).ok();
+        Ok((row.get(0)?, value.unwrap_or_default()))
+    })?;
+    rows.collect()
+}
+
+fn key_values_to_dict<'py>(
+    py: Python<'py>,
+    entries: &[(String, String)],
+) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    for (key, value) in entries.iter() {
+        dict.set_item(key, value)?;
+    }
+    Ok(dict)
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SqlDiaWindowSpecification {
+    pub window_type: u8,
+    pub cycle_id: usize,
+    pub im_start: Option<f64>,
+    pub im_end: Option<f64>,
+    pub isolation_mz: Option<f64>,
+    pub isolation_width: Option<f64>,
+    pub collision_energy: Option<f64>,
+}
+
+impl ReadableSqlTable for SqlDiaWindowSpecification {
+    fn get_sql_query() -> String {
+        "SELECT Type, CycleId, OneOverK0Start, OneOverK0End, IsolationMz, IsolationWidth, CollisionEnergy FROM DiaWindowsSpecification ORDER BY Id".to_string()
+    }
+
+    fn from_sql_row(row: &Row) -> Self {
+        SqlDiaWindowSpecification {
+            window_type: row.parse_default(0),
+            cycle_id: row.parse_default(1),
+            im_start: row.parse_optional("OneOverK0Start"),
+            im_end: row.parse_optional("OneOverK0End"),
+            isolation_mz: row.parse_optional("IsolationMz"),
+            isolation_width: row.parse_optional("IsolationWidth"),
+            collision_energy: row.parse_optional("CollisionEnergy"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SqlPrmTargetSpecification {
+    pub id: usize,
+    pub isolation_mz: f64,
+    pub isolation_width: f64,
+    pub im_lower: f64,
+    pub im_upper: f64,
+    pub collision_energy: Option<f64>,
+    pub external_id: Option<String>,
+    pub im: Option<f64>,
+    pub monoisotopic_mz: Option<f64>,
+    pub rt: Option<f64>,
+    pub rt_range: Option<f64>,
+    pub charge: Option<usize>,
+    pub description: Option<String>,
+}
+
+impl ReadableSqlTable for SqlPrmTargetSpecification {
+    fn get_sql_query() -> String {
+        "SELECT t.Id, t.IsolationMz, t.IsolationWidth, t.OneOverK0LowerLimit, t.OneOverK0UpperLimit, t.CollisionEnergy, a.ExternalId, a.OneOverK0, a.MonoisotopicMz, a.TimeInSeconds, a.TimeRangeInSeconds, a.Charge, a.Description FROM PrmTargetSpecification t LEFT JOIN PrmTargetAdditionalCharacteristics a ON t.Id = a.Id ORDER BY t.Id".to_string()
+    }
+
+    fn from_sql_row(row: &Row) -> Self {
+        SqlPrmTargetSpecification {
+            id: row.parse_default(0),
+            isolation_mz: row.parse_default(1),
+            isolation_width: row.parse_default(2),
+            im_lower: row.parse_default(3),
+            im_upper: row.parse_default(4),
+            collision_energy: row.parse_optional("CollisionEnergy"),
+            external_id: row.parse_optional("ExternalId"),
+            im: row.parse_optional("OneOverK0"),
+            monoisotopic_mz: row.parse_optional("MonoisotopicMz"),
+            rt: row.parse_optional("TimeInSeconds"),
+            rt_range: row.parse_optional("TimeRangeInSeconds"),
+            charge: row.parse_optional("Charge"),
+            description: row.parse_optional("Description"),
+        }
+    }
+}
+
+/// One isolation window of the diaPASEF scheme (diaSettings.diasqlite).
+#[pyclass(name = "DiaWindow")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PyDiaWindow {
+    #[pyo3(get)]
+    pub window_group: usize,
+    #[pyo3(get)]
+    pub lower_im: f64,
+    #[pyo3(get)]
+    pub upper_im: f64,
+    #[pyo3(get)]
+    pub isolation_mz: f64,
+    #[pyo3(get)]
+    pub isolation_width: f64,
+    /// None when the collision energy follows the mobility ramp.
+    #[pyo3(get)]
+    pub collision_energy: Option<f64>,
+}
+
+#[pymethods]
+impl PyDiaWindow {
+    pub fn __repr__(&self) -> String {
+        format!(
+            "DiaWindow(window_group={}, lower_im={}, upper_im={}, isolation_mz={}, isolation_width={}, collision_energy={})",
+            self.window_group,
+            self.lower_im,
+            self.upper_im,
+            self.isolation_mz,
+            self.isolation_width,
+            self.collision_energy.map_or("None".to_string(), |x| x.to_string()),
+        )
+    }
+}
+
+/// One target of the PRM-PASEF target list (prmSettings.prmsqlite).
+///
+/// The retention time fields are in seconds and are only set when the
+/// target list was imported with additional characteristics.
+#[pyclass(name = "PrmTarget")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PyPrmTarget {
+    #[pyo3(get)]
+    pub id: usize,
+    #[pyo3(get)]
+    pub isolation_mz: f64,
+    #[pyo3(get)]
+    pub isolation_width: f64,
+    #[pyo3(get)]
+    pub lower_im: f64,
+    #[pyo3(get)]
+    pub upper_im: f64,
+    #[pyo3(get)]
+    pub collision_energy: Option<f64>,
+    #[pyo3(get)]
+    pub external_id: Option<String>,
+    #[pyo3(get)]
+    pub im: Option<f64>,
+    #[pyo3(get)]
+    pub monoisotopic_mz: Option<f64>,
+    #[pyo3(get)]
+    pub rt: Option<f64>,
+    #[pyo3(get)]
+    pub rt_range: Option<f64>,
+    #[pyo3(get)]
+    pub charge: Option<usize>,
+    #[pyo3(get)]
+    pub description: Option<String>,
+}
+
+impl From<SqlPrmTargetSpecification> for PyPrmTarget {
+    fn from(x: SqlPrmTargetSpecification) -> Self {
+        PyPrmTarget {
+            id: x.id,
+            isolation_mz: x.isolation_mz,
+            isolation_width: x.isolation_width,
+            lower_im: x.im_lower,
+            upper_im: x.im_upper,
+            collision_energy: x.collision_energy,
+            external_id: x.external_id,
+            im: x.im,
+            monoisotopic_mz: x.monoisotopic_mz,
+            rt: x.rt,
+            rt_range: x.rt_range,
+            charge: x.charge,
+            description: x.description,
+        }
+    }
+}
+
+#[pymethods]
+impl PyPrmTarget {
+    pub fn __repr__(&self) -> String {
+        format!(
+            "PrmTarget(id={}, isolation_mz={}, isolation_width={}, lower_im={}, upper_im={}, external_id={})",
+            self.id,
+            self.isolation_mz,
+            self.isolation_width,
+            self.lower_im,
+            self.upper_im,
+            quoted(&self.external_id),
+        )
+    }
+}
+
+/// The method of one HyStar module (LC, autosampler) from hystar.method.
+///
+/// `parameters` are the `(name, value, unit)` triplets that HyStar shows in
+/// its method report; for gradient pumps these include the gradient table.
+#[pyclass(name = "LcModule")]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PyLcModule {
+    #[pyo3(get)]
+    pub module_name: String,
+    #[pyo3(get)]
+    pub method_name: Option<String>,
+    #[pyo3(get)]
+    pub description: Option<String>,
+    /// In minutes.
+    #[pyo3(get)]
+    pub run_time: Option<f64>,
+    #[pyo3(get)]
+    pub devices: Vec<String>,
+    #[pyo3(get)]
+    pub parameters: Vec<(String, String, String)>,
+}
+
+#[pymethods]
+impl PyLcModule {
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.parameters
+            .iter()
+            .find(|(x, _, _)| x == name)
+            .map(|(_, value, _)| value.clone())
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "LcModule(module_name='{}', method_name={}, run_time={}, devices=[{}])",
+            self.module_name,
+            quoted(&self.method_name),
+            self.run_time.map_or("None".to_string(), |x| x.to_string()),
+            self.devices
+                .iter()
+                .map(|x| format!("'{}'", x))
+                .collect::<Vec<_>>()
+                .join(", "),
+        )
+    }
+}
+
+fn quoted(x: &Option<String>) -> String {
+    match x {
+        Some(x) => format!("'{}'", x),
+        None => "None".to_string(),
+    }
+}
+
+fn child_text(node: Node, tag: &str) -> Option<String> {
+    node.children()
+        .find(|x| x.has_tag_name(tag))
+        .and_then(|x| x.text())
+        .map(|x| x.trim().to_string())
+}
+
+fn descendant_text(node: Node, tag: &str) -> Option<String> {
+    node.descendants()
+        .find(|x| x.has_tag_name(tag))
+        .and_then(|x| x.text())
+        .map(|x| x.trim().to_string())
+}
+
+/// HyStar nests each module method as an escaped XML document.
+fn parse_lc_module(text: &str) -> Option<PyLcModule> {
+    let document = Document::parse(text.trim()).ok()?;
+    let data = document
+        .descendants()
+        .find(|x| x.has_tag_name("HyStarMethodData"))?;
+    let mut module = PyLcModule {
+        module_name: child_text(data, "ModuleName")
+            .map(|x| x.split('\t').next().unwrap_or_default().to_string())
+            .unwrap_or_default(),
+        method_name: child_text(data, "MethodName"),
+        description: child_text(data, "Description"),
+        run_time: descendant_text(data, "TotalRunTime").and_then(|x| x.parse().ok()),
+        ..Default::default()
+    };
+    let stylesheet = child_text(data, "Stylesheet").unwrap_or_default();
+    if let Ok(stylesheet) = Document::parse(&stylesheet) {
+        for device in stylesheet
+            .descendants()
+            .filter(|x| x.has_tag_name("SubDevice"))
+        {
+            if let Some(name) = child_text(device, "SubDeviceName") {
+                module.devices.push(name);
+            }
+            for parameter in device.children().filter(|x| x.has_tag_name("Parameter")) {
+                module.parameters.push((
+                    child_text(parameter, "Name").unwrap_or_default(),
+                    child_text(parameter, "Value").unwrap_or_default(),
+                    child_text(parameter, "Unit").unwrap_or_default(),
+                ));
+            }
+        }
+    }
+    Some(module)
+}
+
+pub fn read_lc_modules(path: &Path) -> Result<Vec<PyLcModule>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let document = Document::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(document
+        .descendants()
+        .filter(|x| x.has_tag_name("ModuleMethodData"))
+        .filter_map(|x| x.text())
+        .filter_map(parse_lc_module)
+        .collect())
+}
+
+/// A parameter of the timsTOF acquisition method, either a single value or
+/// a list of values.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MethodValue {
+    Int(i64),
+    Double(f64),
+    Text(String),
+    IntList(Vec<i64>),
+    DoubleList(Vec<f64>),
+    TextList(Vec<String>),
+}
+
+impl MethodValue {
+    fn from_node(node: Node) -> Option<Self> {
+        let value = node.attribute("value").unwrap_or_default();
+        let entries = || node.children().filter_map(|x| x.attribute("value"));
+        let value = match node.tag_name().name() {
+            "para_int" => MethodValue::Int(value.parse().ok()?),
+            "para_double" => MethodValue::Double(value.parse().ok()?),
+            "para_string" => MethodValue::Text(value.to_string()),
+            "para_vec_int" => {
+                MethodValue::IntList(entries().filter_map(|x| x.parse().ok()).collect())
+            }
+            "para_vec_double" => {
+                MethodValue::DoubleList(entries().filter_map(|x| x.parse().ok()).collect())
+            }
+            "para_vec_string" => MethodValue::TextList(entries().map(|x| x.to_string()).collect()),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            MethodValue::Int(x) => Some(*x as f64),
+            MethodValue::Double(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    fn get_f64(&self, index: usize) -> Option<f64> {
+        match self {
+            MethodValue::IntList(x) => x.get(index).map(|x| *x as f64),
+            MethodValue::DoubleList(x) => x.get(index).copied(),
+            _ => None,
+        }
+    }
+
+    fn to_object(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let object = match self {
+            MethodValue::Int(x) => x.into_pyobject(py)?.into_any(),
+            MethodValue::Double(x) => x.into_pyobject(py)?.into_any(),
+            MethodValue::Text(x) => x.into_pyobject(py)?.into_any(),
+            MethodValue::IntList(x) => x.into_pyobject(py)?.into_any(),
+            MethodValue::DoubleList(x) => x.into_pyobject(py)?.into_any(),
+            MethodValue::TextList(x) => x.into_pyobject(py)?.into_any(),
+        };
+        Ok(object.unbind())
+    }
+}
+
+/// Key settings of the timsTOF acquisition method.
+///
+/// Typed attributes are taken from the first method segment for the ion
+/// polarity of the method; all parameters of that segment are available
+/// with `to_dict` and `get`, keyed by their `permname`.
+#[pyclass(name = "AcquisitionSettings")]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PyAcquisitionSettings {
+    #[pyo3(get)]
+    pub path: PathBuf,
+    #[pyo3(get)]
+    pub software_version: Option<String>,
+    /// Same codes as the ScanMode column of the Frames table.
+    #[pyo3(get)]
+    pub scan_mode: Option<u8>,
+    #[pyo3(get)]
+    pub polarity: Option<String>,
+    #[pyo3(get)]
+    pub mz_range: Option<(f64, f64)>,
+    #[pyo3(get)]
+    pub im_range: Option<(f64, f64)>,
+    /// In milliseconds.
+    #[pyo3(get)]
+    pub ramp_time: Option<f64>,
+    pub parameters: Vec<(String, MethodValue)>,
+}
+
+impl PyAcquisitionSettings {
+    fn find(&self, name: &str) -> Option<&MethodValue> {
+        self.parameters
+            .iter()
+            .find(|(x, _)| x == name)
+            .map(|(_, value)| value)
+    }
+}
+
+pub fn read_acquisition_settings(path: &Path) -> Result<PyAcquisitionSettings, String> {
+    let text = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    // Written as ISO-8859-1, which maps one to one on the first 256 code points
+    let text: String = text.into_iter().map(char::from).collect();
+    let document = Document::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut settings = PyAcquisitionSettings {
+        path: path.to_path_buf(),
+        software_version: document
+            .descendants()
+            .find(|x| x.has_tag_name("fileinfo"))
+            .and_then(|x| x.attribute("appversion"))
+            .map(|x| x.to_string()),
+        ..Default::default()
+    };
+    let Some(segment) = document.descendants().find(|x| x.has_tag_name("segment")) else {
+        return Ok(settings);
+    };
+    let parameter = |x: Node| {
+        Some((
+            x.attribute("permname")?.to_string(),
+            MethodValue::from_node(x)?,
+        ))
+    };
+    settings.parameters = segment.children().filter_map(parameter).collect();
+    let polarity = match settings.find("Mode_IonPolarity") {
+        Some(MethodValue::Int(1)) => "negative",
+        _ => "positive",
+    };
+    let dependent = segment.children().filter(|x| {
+        x.has_tag_name("dependent")
+            && x.attributes()
+                .all(|a| a.name() == "polarity" && a.value() == polarity)
+    });
+    for node in dependent {
+        settings
+            .parameters
+            .extend(node.children().filter_map(parameter));
+    }
+    let find = |name: &str| {
+        settings
+            .parameters
+            .iter()
+            .find(|(x, _)| x == name)
+            .map(|(_, value)| value)
+    };
+    let number = |name: &str| find(name).and_then(|x| x.as_f64());
+    // The imeX ramp settings are lists with one entry per imeX mode
+    let ramp = |name: &str| {
+        let mode = number("IMS_imeX_Mode")? as usize;
+        find(name)?.get_f64(mode)
+    };
+    let scan_mode = number("Mode_ScanMode").map(|x| x as u8);
+    let polarity = find("Mode_IonPolarity").map(|_| polarity.to_string());
+    let mz_range = number("Mode_ScanBegin").zip(number("Mode_ScanEnd"));
+    let im_range = ramp("IMS_imeX_RampStart").zip(ramp("IMS_imeX_RampEnd"));
+    let ramp_time = ramp("IMS_imeX_RampTime");
+    Ok(PyAcquisitionSettings {
+        scan_mode,
+        polarity,
+        mz_range,
+        im_range,
+        ramp_time,
+        ..settings
+    })
+}
+
+#[pymethods]
+impl PyAcquisitionSettings {
+    pub fn get(&self, py: Python<'_>, name: &str) -> PyResult<Option<PyObject>> {
+        self.find(name).map(|x| x.to_object(py)).transpose()
+    }
+
+    pub fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        for (key, value) in self.parameters.iter() {
+            dict.set_item(key, value.to_object(py)?)?;
+        }
+        Ok(dict)
+    }
+
+    pub fn __len__(&self) -> usize {
+        self.parameters.len()
+    }
+
+    pub fn __repr__(&self) -> String {
+        let optional = |x: Option<String>| x.unwrap_or("None".to_string());
+        format!(
+            "AcquisitionSettings(scan_mode={}, polarity={}, mz_range={}, im_range={}, ramp_time={})",
+            optional(self.scan_mode.map(|x| x.to_string())),
+            quoted(&self.polarity),
+            optional(self.mz_range.map(|x| format!("{:?}", x))),
+            optional(self.im_range.map(|x| format!("{:?}", x))),
+            optional(self.ramp_time.map(|x| x.to_string())),
+        )
+    }
+}
+
+fn read_submethods(path: &Path) -> Result<Vec<(String, Vec<String>)>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let document = Document::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(document
+        .descendants()
+        .filter(|x| x.has_tag_name("submethod"))
+        .map(|x| {
+            let files = x
+                .descendants()
+                .filter(|x| x.has_tag_name("file"))
+                .filter_map(|x| x.attribute("name"))
+                .map(|x| x.to_string())
+                .collect();
+            (
+                x.attribute("program").unwrap_or_default().to_string(),
+                files,
+            )
+        })
+        .collect())
+}
+
+/// The acquisition method (`.m` folder) stored in a `.d` folder.
+///
+/// Each part of the method is optional, as not every instrument or
+/// acquisition mode writes all files. Missing parts are empty or None.
+#[pyclass(name = "MethodReader")]
+pub struct PyMethodReader {
+    #[pyo3(get)]
+    pub path: PathBuf,
+    #[pyo3(get)]
+    pub name: String,
+    /// `(program, files)` pairs from submethods.xml.
+    #[pyo3(get)]
+    pub submethods: Vec<(String, Vec<String>)>,
+    #[pyo3(get)]
+    pub dia_windows: Vec<PyDiaWindow>,
+    #[pyo3(get)]
+    pub prm_targets: Vec<PyPrmTarget>,
+    #[pyo3(get)]
+    pub lc_modules: Vec<PyLcModule>,
+    #[pyo3(get)]
+    pub acquisition: Option<PyAcquisitionSettings>,
+    pub dia_entries: Vec<(String, String)>,
+    pub prm_entries: Vec<(String, String)>,
+}
+
+impl PyMethodReader {
+    pub fn from_folder(path: PathBuf) -> Result<Self, String> {
+        let name = path
+            .file_name()
+            .and_then(|x| x.to_str())
+            .unwrap_or_default()
+            .to_string();
+        let submethods_path = path.join(SUBMETHODS);
+        let submethods = if submethods_path.exists() {
+            read_submethods(&submethods_path)?
+        } else {
+            vec![]
+        };
+        let mut reader = PyMethodReader {
+            path,
+            name,
+            submethods,
+            dia_windows: vec![],
+            prm_targets: vec![],
+            lc_modules: vec![],
+            acquisition: None,
+            dia_entries: vec![],
+            prm_entries: vec![],
+        };
+        let dia_path = reader.path.join(DIA_SETTINGS);
+        if dia_path.exists() {
+            let sql_reader = TdfSqlReader::open_immutable(&dia_path).map_err(|e| e.to_string())?;
+            reader.dia_entries =
+                read_key_values(&sql_reader, "DiaGlobalMethodInfo").map_err(|e| e.to_string())?;
+            reader.dia_windows = SqlDiaWindowSpecification::from_sql_reader(&sql_reader)
+                .map_err(|e| e.to_string())?
+                .into_iter()
+                // Type 0 rows are the MS1 frames of the cycle
+                .filter(|x| x.window_type == 1)
+                .map(|x| PyDiaWindow {
+                    window_group: x.cycle_id,
+                    lower_im: x.im_start.unwrap_or_default(),
+                    upper_im: x.im_end.unwrap_or_default(),
+                    isolation_mz: x.isolation_mz.unwrap_or_default(),
+                    isolation_width: x.isolation_width.unwrap_or_default(),
+                    collision_energy: x.collision_energy,
+                })
+                .collect();
+        }
+        let prm_path = reader.path.join(PRM_SETTINGS);
+        if prm_path.exists() {
+            let sql_reader = TdfSqlReader::open_immutable(&prm_path).map_err(|e| e.to_string())?;
+            reader.prm_entries =
+                read_key_values(&sql_reader, "PrmGlobalMethodInfo").map_err(|e| e.to_string())?;
+            reader.prm_targets = SqlPrmTargetSpecification::from_sql_reader(&sql_reader)
+                .map_err(|e| e.to_string())?
+                .into_iter()
+                .map(PyPrmTarget::from)
+                .collect();
+        }
+        let hystar_path = reader.path.join(HYSTAR_METHOD);
+        if hystar_path.exists() {
+            reader.lc_modules = read_lc_modules(&hystar_path)?;
+        }
+        let acquisition_path = fs::read_dir(&reader.path)
+            .map_err(|e| e.to_string())?
+            .filter_map(|x| x.ok().map(|x| x.path()))
+            .find(|x| {
+                x.file_name()
+                    .and_then(|x| x.to_str())
+                    .is_some_and(|x| x.ends_with(ACQUISITION_METHOD_SUFFIX))
+            });
+        if let Some(acquisition_path) = acquisition_path {
+            reader.acquisition = Some(read_acquisition_settings(&acquisition_path)?);
+        }
+        Ok(reader)
+    }
+}
+
+#[pymethods]
+impl PyMethodReader {
+    /// Accepts a `.d` folder, its analysis.tdf file or the `.m` folder itself.
+    #[new]
+    pub fn new(path: PathBuf) -> PyResult<Self> {
+        let method_path = find_method_folder(&path).map_err(PyIOError::new_err)?;
+        Self::from_folder(method_path).map_err(PyIOError::new_err)
+    }
+
+    /// Key/value pairs of the DiaGlobalMethodInfo table.
+    #[getter]
+    fn dia_settings<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        key_values_to_dict(py, &self.dia_entries)
+    }
+
+    /// Key/value pairs of the PrmGlobalMethodInfo table.
+    #[getter]
+    fn prm_settings<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        key_values_to_dict(py, &self.prm_entries)
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "MethodReader(path='{}', num_dia_windows={}, num_prm_targets={}, num_lc_modules={})",
+            self.path.to_str().unwrap_or("None"),
+            self.dia_windows.len(),
+            self.prm_targets.len(),
+            self.lc_modules.len(),
+        )
+    }
+}
@@ -0,0 +1,160 @@
+use pyo3::prelude::*;
+use timsrust::converters::{ConvertableDomain, Scan2ImConverter, Tof2MzConverter};
+
+use crate::timsrust_structs::PyFrame;
+
+/// Settings of the 2D (tof x scan) peak picking.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PeakPickingParams {
+    /// Peaks with a lower apex intensity are dropped.
+    pub min_intensity: f64,
+    /// Maximum tof index distance of an event to the apex of its peak.
+    pub tof_tolerance: u32,
+    /// Maximum scan distance of an event to the apex of its peak.
+    pub scan_tolerance: usize,
+}
+
+impl Default for PeakPickingParams {
+    fn default() -> Self {
+        PeakPickingParams {
+            min_intensity: 0.0,
+            tof_tolerance: 3,
+            scan_tolerance: 5,
+        }
+    }
+}
+
+/// Centroided peaks of a frame, as columns with one entry per peak, from the
+/// most to the least intense apex.
+///
+/// `mz_values` and `mobility_values` are the intensity weighted centroids,
+/// `mz_widths` and `mobility_widths` the span of the events of each peak.
+#[pyclass(name = "PickedPeaks")]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PyPickedPeaks {
+    #[pyo3(get)]
+    pub frame_index: usize,
+    #[pyo3(get)]
+    pub rt: f64,
+    #[pyo3(get)]
+    pub mz_values: Vec<f64>,
+    #[pyo3(get)]
+    pub mobility_values: Vec<f64>,
+    #[pyo3(get)]
+    pub apex_intensities: Vec<f64>,
+    #[pyo3(get)]
+    pub summed_intensities: Vec<f64>,
+    #[pyo3(get)]
+    pub mz_widths: Vec<f64>,
+    #[pyo3(get)]
+    pub mobility_widths: Vec<f64>,
+    /// Fractional tof index of each centroid.
+    #[pyo3(get)]
+    pub tof_indices: Vec<f64>,
+    /// Fractional scan index of each centroid.
+    #[pyo3(get)]
+    pub scan_indices: Vec<f64>,
+    /// Number of raw events merged into each peak.
+    #[pyo3(get)]
+    pub num_events: Vec<usize>,
+}
+
+#[pymethods]
+impl PyPickedPeaks {
+    pub fn __len__(&self) -> usize {
+        self.mz_values.len()
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "PickedPeaks(frame_index={}, rt={}, num_peaks={})",
+            self.frame_index,
+            self.rt,
+            self.mz_values.len(),
+        )
+    }
+}
+
+/// Greedy 2D peak picking: starting from the most intense event, every
+/// unassigned event within the tof and scan tolerances of an apex is merged
+/// into its peak.
+pub fn pick_peaks(
+    frame: &PyFrame,
+    mz_converter: &Tof2MzConverter,
+    im_converter: &Scan2ImConverter,
+    params: &PeakPickingParams,
+) -> PyPickedPeaks {
+    let num_scans = frame.scan_offsets.len().saturating_sub(1);
+    let mut scans = vec![0; frame.tof_indices.len()];
+    for scan in 0..num_scans {
+        scans[frame.scan_offsets[scan]..frame.scan_offsets[scan + 1]].fill(scan);
+    }
+    let mut order: Vec<usize> = (0..frame.tof_indices.len()).collect();
+    order.sort_by(|a, b| frame.intensities[*b].cmp(&frame.intensities[*a]));
+    let mut assigned = vec![false; frame.tof_indices.len()];
+    let mut peaks = PyPickedPeaks {
+        frame_index: frame.index,
+        rt: frame.rt,
+        ..Default::default()
+    };
+    for apex in order {
+        if assigned[apex] {
+            continue;
+        }
+        let apex_intensity = frame.intensities[apex] as f64;
+        if apex_intensity < params.min_intensity {
+            break;
+        }
+        let apex_scan = scans[apex];
+        let apex_tof = frame.tof_indices[apex];
+        let lower_tof = apex_tof.saturating_sub(params.tof_tolerance);
+        let upper_tof = apex_tof.saturating_add(params.tof_tolerance);
+        let mut summed_intensity = 0.0;
+        let mut tof_sum = 0.0;
+        let mut scan_sum = 0.0;
+        let mut tof_range = (apex_tof, apex_tof);
+        let mut scan_range = (apex_scan, apex_scan);
+        let mut num_events = 0;
+        let last_scan = (apex_scan + params.scan_tolerance).min(num_scans.saturating_sub(1));
+        for scan in apex_scan.saturating_sub(params.scan_tolerance)..=last_scan {
+            let start = frame.scan_offsets[scan];
+            let end = frame.scan_offsets[scan + 1];
+            let tofs = &frame.tof_indices[start..end];
+            let first = start + tofs.partition_point(|x| *x < lower_tof);
+            let last = start + tofs.partition_point(|x| *x <= upper_tof);
+            let events = assigned[first..last]
+                .iter_mut()
+                .zip(&frame.tof_indices[first..last])
+                .zip(&frame.intensities[first..last]);
+            for ((is_assigned, &tof), &intensity) in events {
+                if *is_assigned {
+                    continue;
+                }
+                *is_assigned = true;
+                let intensity = intensity as f64;
+                summed_intensity += intensity;
+                tof_sum += tof as f64 * intensity;
+                scan_sum += scan as f64 * intensity;
+                tof_range = (tof_range.0.min(tof), tof_range.1.max(tof));
+                scan_range = (scan_range.0.min(scan), scan_range.1.max(scan));
+                num_events += 1;
+            }
+        }
+        let tof = tof_sum / summed_intensity;
+        let scan = scan_sum / summed_intensity;
+        peaks.mz_values.push(mz_converter.convert(tof));
+        peaks.mobility_values.push(im_converter.convert(scan));
+        peaks.apex_intensities.push(apex_intensity);
+        peaks.summed_intensities.push(summed_intensity);
+        peaks
+            .mz_widths
+            .push(mz_converter.convert(tof_range.1) - mz_converter.convert(tof_range.0));
+        peaks.mobility_widths.push(
+            im_converter.convert(scan_range.0 as f64) - im_converter.convert(scan_range.1 as f64),
+        );
+        peaks.tof_indices.push(tof);
+        peaks.scan_indices.push(scan);
+        peaks.num_events.push(num_events);
+    }
+    peaks
+}
@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+
+use pyo3::prelude::*;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use timsrust::converters::{ConvertableDomain, Scan2ImConverter, Tof2MzConverter};
+use timsrust::readers::{FrameReader, FrameReaderError};
+use timsrust::Frame;
+
+use crate::isotopes::{closest_peak, isotope_envelope, ISOTOPE_SPACING};
+use crate::tdf_sql::SqlFrameInfo;
+use crate::timsrust_converters::scan_range;
+use crate::timsrust_enums::PyMSLevel;
+use crate::timsrust_structs::PyPrecursor;
+
+/// Settings of the precursor isotope envelope detection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EnvelopeParams {
+    pub mz_tolerance_ppm: f64,
+    /// Half width of the 1/K0 window around the precursor.
+    pub im_tolerance: f64,
+    pub max_charge: usize,
+}
+
+/// Isotope envelope of a precursor in the MS1 frame closest to its rt.
+#[pyclass(name = "IsotopeEnvelope")]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PyIsotopeEnvelope {
+    #[pyo3(get)]
+    pub charge: usize,
+    #[pyo3(get)]
+    pub monoisotopic_mz: f64,
+    /// Summed intensity of all isotopes.
+    #[pyo3(get)]
+    pub intensity: f64,
+    #[pyo3(get)]
+    pub mzs: Vec<f64>,
+    #[pyo3(get)]
+    pub intensities: Vec<f64>,
+    /// Id of the MS1 frame the envelope was found in.
+    #[pyo3(get)]
+    pub frame_index: usize,
+}
+
+#[pymethods]
+impl PyIsotopeEnvelope {
+    pub fn __len__(&self) -> usize {
+        self.mzs.len()
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "IsotopeEnvelope(charge={}, monoisotopic_mz={}, intensity={}, num_isotopes={}, frame_index={})",
+            self.charge,
+            self.monoisotopic_mz,
+            self.intensity,
+            self.mzs.len(),
+            self.frame_index,
+        )
+    }
+}
+
+/// Position in the frame reader of the MS1 frame closest to `precursor`, by
+/// rt or, when its rt is unknown, by frame id.
+fn closest_ms1_frame(frames: &[SqlFrameInfo], precursor: &PyPrecursor) -> Option<usize> {
+    let distance = |x: &SqlFrameInfo| {
+        if precursor.rt.is_finite() {
+            (x.rt - precursor.rt).abs()
+        } else {
+            (x.id as f64 - precursor.frame_index as f64).abs()
+        }
+    };
+    (0..frames.len())
+        .filter(|i| PyMSLevel::from_msms_type(frames[*i].msms_type) == PyMSLevel::MS1)
+        .min_by(|a, b| distance(&frames[*a]).total_cmp(&distance(&frames[*b])))
+}
+
+/// Centroided m/z and intensities of a frame within `mz_range` and the 1/K0
+/// window around `im` (all scans when `im` is unknown), merging events at
+/// most two tof indices apart.
+fn centroid_spectrum(
+    frame: &Frame,
+    mz_converter: &Tof2MzConverter,
+    im_converter: &Scan2ImConverter,
+    mz_range: (f64, f64),
+    im: f64,
+    im_tolerance: f64,
+) -> (Vec<f64>, Vec<f64>) {
+    let num_scans = frame.scan_offsets.len().saturating_sub(1);
+    let (scan_start, scan_end) = if im.is_finite() {
+        scan_range(
+            im_converter,
+            im - im_tolerance,
+            im + im_tolerance,
+            num_scans,
+        )
+    } else {
+        (0, num_scans)
+    };
+    let lower_tof = mz_converter.invert(mz_range.0).max(0.0) as u32;
+    let upper_tof = mz_converter.invert(mz_range.1).ceil() as u32;
+    let mut events: Vec<(u32, f64)> = vec![];
+    for scan in scan_start..scan_end {
+        for peak in frame.scan_offsets[scan]..frame.scan_offsets[scan + 1] {
+            let tof = frame.tof_indices[peak];
+            if (lower_tof..=upper_tof).contains(&tof) {
+                events.push((tof, frame.intensities[peak] as f64));
+            }
+        }
+    }
+    events.sort_by_key(|x| x.0);
+    let mut mzs = vec![];
+    let mut intensities = vec![];
+    let mut i = 0;
+    while i < events.len() {
+        let mut j = i + 1;
+        while j < events.len() && events[j].0 - events[j - 1].0 <= 2 {
+            j += 1;
+        }
+        let intensity: f64 = events[i..j].iter().map(|x| x.1).sum();
+        let tof = events[i..j].iter().map(|x| x.0 as f64 * x.1).sum::<f64>() / intensity;
+        mzs.push(mz_converter.convert(tof));
+        intensities.push(intensity);
+        i = j;
+    }
+    (mzs, intensities)
+}
+
+fn detect_envelope(
+    frame: &Frame,
+    precursor: &PyPrecursor,
+    mz_converter: &Tof2MzConverter,
+    im_converter: &Scan2ImConverter,
+    params: &EnvelopeParams,
+) -> Option<PyIsotopeEnvelope> {
+    let max_isotopes = 6.0;
+    let mz_range = (
+        precursor.mz - max_isotopes * ISOTOPE_SPACING,
+        precursor.mz + max_isotopes * ISOTOPE_SPACING,
+    );
+    let (mzs, intensities) = centroid_spectrum(
+        frame,
+        mz_converter,
+        im_converter,
+        mz_range,
+        precursor.im,
+        params.im_tolerance,
+    );
+    let seed = closest_peak(&mzs, precursor.mz, params.mz_tolerance_ppm, |_| true)?;
+    // Lower isotopes are only followed while they could be monoisotopic
+    let envelope = isotope_envelope(
+        &mzs,
+        &intensities,
+        seed,
+        params.max_charge,
+        params.mz_tolerance_ppm,
+        0.2,
+        |_| true,
+    )?;
+    let isotope_mzs: Vec<f64> = envelope.peaks.iter().map(|x| mzs[*x]).collect();
+    let isotope_intensities: Vec<f64> = envelope.peaks.iter().map(|x| intensities[*x]).collect();
+    Some(PyIsotopeEnvelope {
+        charge: envelope.charge,
+        monoisotopic_mz: isotope_mzs[0],
+        intensity: isotope_intensities.iter().sum(),
+        mzs: isotope_mzs,
+        intensities: isotope_intensities,
+        frame_index: frame.index,
+    })
+}
+
+/// Isotope envelopes of `precursors`, each in its closest MS1 frame. Frames
+/// are read once and in parallel.
+pub fn detect_envelopes(
+    frame_reader: &FrameReader,
+    frames: &[SqlFrameInfo],
+    precursors: &[PyPrecursor],
+    mz_converter: &Tof2MzConverter,
+    im_converter: &Scan2ImConverter,
+    params: &EnvelopeParams,
+) -> Result<Vec<Option<PyIsotopeEnvelope>>, FrameReaderError> {
+    let mut by_frame: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (i, precursor) in precursors.iter().enumerate() {
+        if let Some(position) = closest_ms1_frame(frames, precursor) {
+            by_frame.entry(position).or_default().push(i);
+        }
+    }
+    let envelopes = by_frame
+        .into_iter()
+        .collect::<Vec<(usize, Vec<usize>)>>()
+        .into_par_iter()
+        .map(|(position, members)| {
+            let frame = frame_reader.get(position)?;
+            Ok(members
+                .into_iter()
+                .map(|i| {
+                    let envelope =
+                        detect_envelope(&frame, &precursors[i], mz_converter, im_converter, params);
+                    (i, envelope)
+                })
+                .collect::<Vec<(usize, Option<PyIsotopeEnvelope>)>>())
+        })
+        .collect::<Result<Vec<Vec<(usize, Option<PyIsotopeEnvelope>)>>, FrameReaderError>>()?;
+    let mut result = vec![None; precursors.len()];
+    for (i, envelope) in envelopes.into_iter().flatten() {
+        result[i] = envelope;
+    }
+    Ok(result)
+}
+
+/// A copy of `precursor` with the charge and monoisotopic m/z of `envelope`
+/// when its charge is missing, and its intensity when that is missing, or
+/// both regardless with `overwrite`. timsrust reads missing values from the
+/// Precursors table as 0, so those count as missing too.
+pub fn annotate_precursor(
+    precursor: &PyPrecursor,
+    envelope: Option<&PyIsotopeEnvelope>,
+    overwrite: bool,
+) -> PyPrecursor {
+    let mut precursor = precursor.clone();
+    if let Some(envelope) = envelope {
+        if overwrite || precursor.charge.is_none_or(|x| x == 0) {
+            precursor.charge = Some(envelope.charge);
+            precursor.mz = envelope.monoisotopic_mz;
+        }
+        if overwrite || precursor.intensity.is_none_or(|x| x == 0.0) {
+            precursor.intensity = Some(envelope.intensity);
+        }
+    }
+    precursor
+}
@@ -0,0 +1,224 @@
+use std::path::Path;
+
+use pyo3::prelude::*;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use timsrust::converters::{ConvertableDomain, Scan2ImConverter, Tof2MzConverter};
+use timsrust::readers::{FrameReader, FrameReaderError};
+use timsrust::Precursor;
+
+use crate::method::PyPrmTarget;
+use crate::tdf_sql::{ReadableSqlTable, SqlFrameInfo, SqlPrmFrameMsMs, SqlPrmTarget, TdfSqlReader};
+use crate::timsrust_enums::{PyAcquisitionType, PyMSLevel};
+use crate::timsrust_structs::{PyFrame, PyQuadrupoleSettings};
+
+/// True for prm-PASEF runs, which timsrust reads frames from but cannot
+/// build spectra for.
+pub fn is_prm_run(path: impl AsRef<Path>) -> bool {
+    TdfSqlReader::open(path)
+        .and_then(|x| SqlFrameInfo::from_sql_reader(&x))
+        .is_ok_and(|frames| {
+            PyAcquisitionType::from_msms_types(frames.iter().map(|x| x.msms_type))
+                == PyAcquisitionType::PRMPASEF
+        })
+}
+
+/// The prm-PASEF isolations of a run (sorted by frame and scan) and the
+/// targets they refer to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PrmLayout {
+    pub isolations: Vec<SqlPrmFrameMsMs>,
+    pub targets: Vec<SqlPrmTarget>,
+}
+
+impl PrmLayout {
+    /// None when the run has no prm-PASEF frames.
+    pub fn read(tdf_sql_reader: &TdfSqlReader) -> rusqlite::Result<Option<Self>> {
+        if !tdf_sql_reader.has_table("PrmFrameMsMsInfo")? {
+            return Ok(None);
+        }
+        let isolations = SqlPrmFrameMsMs::from_sql_reader(tdf_sql_reader)?;
+        if isolations.is_empty() {
+            return Ok(None);
+        }
+        let targets = if tdf_sql_reader.has_table("PrmTargets")? {
+            SqlPrmTarget::from_sql_reader(tdf_sql_reader)?
+        } else {
+            vec![]
+        };
+        Ok(Some(PrmLayout {
+            isolations,
+            targets,
+        }))
+    }
+
+    /// Isolations of a frame, by frame id.
+    pub fn frame_isolations(&self, frame_id: usize) -> &[SqlPrmFrameMsMs] {
+        let start = self.isolations.partition_point(|x| x.frame < frame_id);
+        let end = self.isolations.partition_point(|x| x.frame <= frame_id);
+        &self.isolations[start..end]
+    }
+
+    pub fn target(&self, id: usize) -> Option<&SqlPrmTarget> {
+        self.targets.iter().find(|x| x.id == id)
+    }
+
+    /// Marks a frame of this run as prm-PASEF, with its quadrupole settings
+    /// and targets taken from the PrmFrameMsMsInfo table.
+    pub fn label_frame(&self, frame: &mut PyFrame) {
+        frame.acquisition_type = PyAcquisitionType::PRMPASEF;
+        let isolations = self.frame_isolations(frame.index);
+        if isolations.is_empty() {
+            return;
+        }
+        frame.ms_level = PyMSLevel::MS2;
+        frame.quadrupole_settings = PyQuadrupoleSettings {
+            index: 0,
+            scan_starts: isolations.iter().map(|x| x.scan_start).collect(),
+            scan_ends: isolations.iter().map(|x| x.scan_end).collect(),
+            isolation_mz: isolations.iter().map(|x| x.isolation_mz).collect(),
+            isolation_width: isolations.iter().map(|x| x.isolation_width).collect(),
+            collision_energy: isolations.iter().map(|x| x.collision_energy).collect(),
+        };
+        frame.prm_targets = isolations.iter().map(|x| x.target).collect();
+    }
+
+    /// The target of an isolation as precursor, falling back on the
+    /// isolation window when the target is not in the PrmTargets table.
+    pub fn precursor(&self, isolation: &SqlPrmFrameMsMs) -> Precursor {
+        let target = self.target(isolation.target);
+        Precursor {
+            mz: target.map_or(isolation.isolation_mz, |x| x.monoisotopic_mz),
+            rt: target.map_or(f64::NAN, |x| x.rt),
+            im: target.map_or(f64::NAN, |x| x.im),
+            charge: target.and_then(|x| x.charge),
+            intensity: None,
+            index: isolation.target,
+            frame_index: isolation.frame,
+        }
+    }
+
+    /// All targets, with the isolation settings of their first isolation.
+    pub fn py_targets(&self, im_converter: &Scan2ImConverter) -> Vec<PyPrmTarget> {
+        self.targets
+            .iter()
+            .map(|target| {
+                let isolation = self.isolations.iter().find(|x| x.target == target.id);
+                PyPrmTarget {
+                    id: target.id,
+                    isolation_mz: isolation.map_or(f64::NAN, |x| x.isolation_mz),
+                    isolation_width: isolation.map_or(f64::NAN, |x| x.isolation_width),
+                    lower_im: isolation
+                        .map_or(f64::NAN, |x| im_converter.convert(x.scan_end as f64)),
+                    upper_im: isolation
+                        .map_or(f64::NAN, |x| im_converter.convert(x.scan_start as f64)),
+                    collision_energy: isolation.map(|x| x.collision_energy),
+                    external_id: target.external_id.clone(),
+                    im: Some(target.im),
+                    monoisotopic_mz: Some(target.monoisotopic_mz),
+                    rt: Some(target.rt),
+                    rt_range: None,
+                    charge: target.charge,
+                    description: target.description.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Summed fragment intensities of every isolation of a target, within
+    /// `tolerance_ppm` of each fragment m/z and the isolated scan range.
+    pub fn extract_transitions(
+        &self,
+        frame_reader: &FrameReader,
+        mz_converter: &Tof2MzConverter,
+        target: usize,
+        fragment_mzs: &[f64],
+        tolerance_ppm: f64,
+    ) -> Result<PyPrmTransitions, FrameReaderError> {
+        let tof_windows: Vec<(f64, f64)> = fragment_mzs
+            .iter()
+            .map(|mz| {
+                let tolerance = mz * tolerance_ppm / 1e6;
+                (
+                    mz_converter.invert(mz - tolerance),
+                    mz_converter.invert(mz + tolerance),
+                )
+            })
+            .collect();
+        let isolations: Vec<&SqlPrmFrameMsMs> = self
+            .isolations
+            .iter()
+            .filter(|x| x.target == target)
+            .collect();
+        let points = isolations
+            .into_par_iter()
+            .map(|isolation| {
+                let frame = frame_reader.get(isolation.frame - 1)?;
+                let mut intensities = vec![0.0; tof_windows.len()];
+                let scan_end = isolation
+                    .scan_end
+                    .min(frame.scan_offsets.len().saturating_sub(1));
+                for scan in isolation.scan_start..scan_end {
+                    for peak in frame.scan_offsets[scan]..frame.scan_offsets[scan + 1] {
+                        let tof = frame.tof_indices[peak] as f64;
+                        for (i, (lower, upper)) in tof_windows.iter().enumerate() {
+                            if (*lower..=*upper).contains(&tof) {
+                                intensities[i] += frame.intensities[peak] as f64;
+                            }
+                        }
+                    }
+                }
+                Ok((isolation.frame, frame.rt, intensities))
+            })
+            .collect::<Result<Vec<(usize, f64, Vec<f64>)>, FrameReaderError>>()?;
+        let mut transitions = PyPrmTransitions {
+            target,
+            fragment_mzs: fragment_mzs.to_vec(),
+            frame_indices: vec![],
+            rt: vec![],
+            intensities: vec![vec![]; fragment_mzs.len()],
+        };
+        for (frame, rt, intensities) in points {
+            transitions.frame_indices.push(frame);
+            transitions.rt.push(rt);
+            for (trace, intensity) in transitions.intensities.iter_mut().zip(intensities) {
+                trace.push(intensity);
+            }
+        }
+        Ok(transitions)
+    }
+}
+
+/// Fragment ion chromatograms of a single prm-PASEF target.
+///
+/// There is one point per frame in which the target was isolated, and
+/// `intensities` holds one trace per fragment m/z, aligned with `rt`.
+#[pyclass(name = "PrmTransitions")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PyPrmTransitions {
+    #[pyo3(get)]
+    pub target: usize,
+    #[pyo3(get)]
+    pub fragment_mzs: Vec<f64>,
+    #[pyo3(get)]
+    pub frame_indices: Vec<usize>,
+    #[pyo3(get)]
+    pub rt: Vec<f64>,
+    #[pyo3(get)]
+    pub intensities: Vec<Vec<f64>>,
+}
+
+#[pymethods]
+impl PyPrmTransitions {
+    pub fn __len__(&self) -> usize {
+        self.rt.len()
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "PrmTransitions(target={}, fragment_mzs={:?}, num_points={})",
+            self.target,
+            self.fragment_mzs,
+            self.rt.len(),
+        )
+    }
+}
@@ -0,0 +1,293 @@
+use std::f64::consts::PI;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
+use crate::timsrust_structs::PySpectrum;
+
+/// Peak matching tolerance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tolerance {
+    Ppm(f64),
+    Da(f64),
+}
+
+impl Tolerance {
+    pub fn parse(tolerance: f64, unit: &str) -> PyResult<Self> {
+        match unit {
+            "ppm" => Ok(Tolerance::Ppm(tolerance)),
+            "da" | "Da" => Ok(Tolerance::Da(tolerance)),
+            x => Err(PyValueError::new_err(format!(
+                "Unknown tolerance unit '{}', expected 'ppm' or 'da'",
+                x
+            ))),
+        }
+    }
+
+    pub fn width(&self, mz: f64) -> f64 {
+        match self {
+            Tolerance::Ppm(x) => mz * x / 1e6,
+            Tolerance::Da(x) => *x,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimilarityMetric {
+    Cosine,
+    /// 1 - 2 * angle / pi, with the angle between the intensity vectors.
+    ContrastAngle,
+    /// Entropy similarity with the intensity weighting of Li et al. (2021).
+    Entropy,
+    UnweightedEntropy,
+    /// Number of matched peaks.
+    MatchedPeaks,
+}
+
+impl SimilarityMetric {
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "cosine" => Ok(SimilarityMetric::Cosine),
+            "contrast_angle" => Ok(SimilarityMetric::ContrastAngle),
+            "entropy" => Ok(SimilarityMetric::Entropy),
+            "unweighted_entropy" => Ok(SimilarityMetric::UnweightedEntropy),
+            "matched_peaks" => Ok(SimilarityMetric::MatchedPeaks),
+            x => Err(PyValueError::new_err(format!(
+                "Unknown metric '{}', expected 'cosine', 'contrast_angle', 'entropy', 'unweighted_entropy' or 'matched_peaks'",
+                x
+            ))),
+        }
+    }
+}
+
+/// Peaks of a spectrum sorted by m/z, without zero intensities.
+fn sorted_peaks(spectrum: &PySpectrum) -> Vec<(f64, f64)> {
+    sort_peaks(&spectrum.mz_values, &spectrum.intensities)
+}
+
+/// (m/z, intensity) pairs sorted by m/z, without zero intensities.
+pub fn sort_peaks(mz_values: &[f64], intensities: &[f64]) -> Vec<(f64, f64)> {
+    let mut peaks: Vec<(f64, f64)> = mz_values
+        .iter()
+        .zip(intensities.iter())
+        .filter(|(_, intensity)| **intensity > 0.0)
+        .map(|(mz, intensity)| (*mz, *intensity))
+        .collect();
+    peaks.sort_by(|a, b| a.0.total_cmp(&b.0));
+    peaks
+}
+
+/// Matched peak pairs (index in `a`, index in `b`), each peak used at most
+/// once, greedily by highest intensity product.
+fn match_peaks(a: &[(f64, f64)], b: &[(f64, f64)], tolerance: &Tolerance) -> Vec<(usize, usize)> {
+    let mut candidates = vec![];
+    let mut start = 0;
+    for (i, (mz, intensity)) in a.iter().enumerate() {
+        let width = tolerance.width(*mz);
+        while start < b.len() && b[start].0 < mz - width {
+            start += 1;
+        }
+        for (j, other) in b.iter().enumerate().skip(start) {
+            if other.0 > mz + width {
+                break;
+            }
+            candidates.push((intensity * other.1, i, j));
+        }
+    }
+    candidates.sort_by(|x, y| y.0.total_cmp(&x.0));
+    let mut used_a = vec![false; a.len()];
+    let mut used_b = vec![false; b.len()];
+    let mut pairs = vec![];
+    for (_, i, j) in candidates {
+        if !used_a[i] && !used_b[j] {
+            used_a[i] = true;
+            used_b[j] = true;
+            pairs.push((i, j));
+        }
+    }
+    pairs
+}
+
+fn cosine(a: &[(f64, f64)], b: &[(f64, f64)], pairs: &[(usize, usize)]) -> f64 {
+    let norm = |x: &[(f64, f64)]| x.iter().map(|p| p.1 * p.1).sum::<f64>().sqrt();
+    let (norm_a, norm_b) = (norm(a), norm(b));
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    let dot: f64 = pairs.iter().map(|(i, j)| a[*i].1 * b[*j].1).sum();
+    (dot / (norm_a * norm_b)).min(1.0)
+}
+
+fn entropy(values: impl Iterator<Item = f64>) -> f64 {
+    -values.filter(|x| *x > 0.0).map(|x| x * x.ln()).sum::<f64>()
+}
+
+/// Intensities as probabilities, sharpened for spectra with an entropy
+/// below 3 when `weighted`.
+fn entropy_weights(peaks: &[(f64, f64)], weighted: bool) -> Vec<f64> {
+    let normalize = |x: Vec<f64>| {
+        let total: f64 = x.iter().sum();
+        x.into_iter().map(|v| v / total).collect::<Vec<f64>>()
+    };
+    let probabilities = normalize(peaks.iter().map(|x| x.1).collect());
+    let spectral_entropy = entropy(probabilities.iter().cloned());
+    if !weighted || spectral_entropy >= 3.0 {
+        return probabilities;
+    }
+    let weight = 0.25 + 0.25 * spectral_entropy;
+    normalize(probabilities.iter().map(|x| x.powf(weight)).collect())
+}
+
+fn entropy_similarity(
+    a: &[(f64, f64)],
+    b: &[(f64, f64)],
+    pairs: &[(usize, usize)],
+    weighted: bool,
+) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let (weights_a, weights_b) = (entropy_weights(a, weighted), entropy_weights(b, weighted));
+    // Only matched peaks change the entropy of the merged spectrum
+    let mut matched_a = vec![false; a.len()];
+    let mut matched_b = vec![false; b.len()];
+    for (i, j) in pairs {
+        matched_a[*i] = true;
+        matched_b[*j] = true;
+    }
+    let merged = pairs
+        .iter()
+        .map(|(i, j)| (weights_a[*i] + weights_b[*j]) / 2.0)
+        .chain(
+            (0..a.len())
+                .filter(|i| !matched_a[*i])
+                .map(|i| weights_a[i] / 2.0),
+        )
+        .chain(
+            (0..b.len())
+                .filter(|j| !matched_b[*j])
+                .map(|j| weights_b[j] / 2.0),
+        );
+    let difference = 2.0 * entropy(merged)
+        - entropy(weights_a.iter().cloned())
+        - entropy(weights_b.iter().cloned());
+    (1.0 - difference / 4f64.ln()).clamp(0.0, 1.0)
+}
+
+fn peak_similarity(
+    a: &[(f64, f64)],
+    b: &[(f64, f64)],
+    metric: SimilarityMetric,
+    tolerance: &Tolerance,
+) -> f64 {
+    score_peaks(a, b, metric, tolerance).0
+}
+
+/// Similarity and number of matched peaks of two peak lists from
+/// `sort_peaks`.
+pub fn score_peaks(
+    a: &[(f64, f64)],
+    b: &[(f64, f64)],
+    metric: SimilarityMetric,
+    tolerance: &Tolerance,
+) -> (f64, usize) {
+    let pairs = match_peaks(a, b, tolerance);
+    let score = match metric {
+        SimilarityMetric::Cosine => cosine(a, b, &pairs),
+        SimilarityMetric::ContrastAngle if a.is_empty() || b.is_empty() => 0.0,
+        SimilarityMetric::ContrastAngle => 1.0 - 2.0 * cosine(a, b, &pairs).acos() / PI,
+        SimilarityMetric::Entropy => entropy_similarity(a, b, &pairs, true),
+        SimilarityMetric::UnweightedEntropy => entropy_similarity(a, b, &pairs, false),
+        SimilarityMetric::MatchedPeaks => pairs.len() as f64,
+    };
+    (score, pairs.len())
+}
+
+/// Similarity of two spectra, 0 when either has no peaks.
+pub fn similarity(
+    a: &PySpectrum,
+    b: &PySpectrum,
+    metric: SimilarityMetric,
+    tolerance: &Tolerance,
+) -> f64 {
+    peak_similarity(&sorted_peaks(a), &sorted_peaks(b), metric, tolerance)
+}
+
+/// Similarities of `query` to every spectrum of `spectra`, in parallel.
+pub fn one_vs_many(
+    query: &PySpectrum,
+    spectra: &[PySpectrum],
+    metric: SimilarityMetric,
+    tolerance: &Tolerance,
+) -> Vec<f64> {
+    let query = sorted_peaks(query);
+    spectra
+        .par_iter()
+        .map(|x| peak_similarity(&query, &sorted_peaks(x), metric, tolerance))
+        .collect()
+}
+
+/// Similarity matrix of `spectra` against `others`, or against themselves
+/// when `others` is None, in which case only one triangle is computed.
+pub fn pairwise(
+    spectra: &[PySpectrum],
+    others: Option<&[PySpectrum]>,
+    metric: SimilarityMetric,
+    tolerance: &Tolerance,
+) -> Vec<Vec<f64>> {
+    let peaks: Vec<Vec<(f64, f64)>> = spectra.par_iter().map(sorted_peaks).collect();
+    let Some(others) = others else {
+        let upper: Vec<Vec<f64>> = (0..peaks.len())
+            .into_par_iter()
+            .map(|i| {
+                peaks[i..]
+                    .iter()
+                    .map(|x| peak_similarity(&peaks[i], x, metric, tolerance))
+                    .collect()
+            })
+            .collect();
+        return (0..peaks.len())
+            .map(|i| {
+                (0..peaks.len())
+                    .map(|j| {
+                        if j >= i {
+                            upper[i][j - i]
+                        } else {
+                            upper[j][i - j]
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+    };
+    let other_peaks: Vec<Vec<(f64, f64)>> = others.par_iter().map(sorted_peaks).collect();
+    peaks
+        .par_iter()
+        .map(|a| {
+            other_peaks
+                .iter()
+                .map(|b| peak_similarity(a, b, metric, tolerance))
+                .collect()
+        })
+        .collect()
+}
+
+/// Similarity matrix of `spectra` against `others`, or all against all when
+/// `others` is None. `metric` is one of 'cosine', 'contrast_angle',
+/// 'entropy', 'unweighted_entropy' or 'matched_peaks', and peaks match
+/// within `tolerance` in `unit`, 'ppm' or 'da'.
+#[pyfunction]
+#[pyo3(name = "pairwise_similarity", signature = (spectra, others=None, metric="cosine", tolerance=20.0, unit="ppm"))]
+pub fn py_pairwise_similarity(
+    py: Python<'_>,
+    spectra: Vec<PySpectrum>,
+    others: Option<Vec<PySpectrum>>,
+    metric: &str,
+    tolerance: f64,
+    unit: &str,
+) -> PyResult<Vec<Vec<f64>>> {
+    let metric = SimilarityMetric::parse(metric)?;
+    let tolerance = Tolerance::parse(tolerance, unit)?;
+    Ok(py.allow_threads(|| pairwise(&spectra, others.as_deref(), metric, &tolerance)))
+}
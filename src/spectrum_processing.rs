@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::isotopes::{closest_peak, ISOTOPE_SPACING};
+use crate::timsrust_structs::PySpectrum;
+
+pub const PROTON_MASS: f64 = 1.007_276_467;
+
+/// Mass scale of deisotoped peaks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MassMode {
+    /// The observed m/z of the monoisotopic peak.
+    #[default]
+    Mz,
+    /// The m/z the peak would have with charge 1.
+    SinglyCharged,
+    /// The uncharged monoisotopic mass.
+    Neutral,
+}
+
+impl MassMode {
+    pub fn parse(name: Option<&str>) -> PyResult<Self> {
+        match name {
+            None | Some("mz") => Ok(MassMode::Mz),
+            Some("singly_charged") => Ok(MassMode::SinglyCharged),
+            Some("neutral") => Ok(MassMode::Neutral),
+            Some(x) => Err(PyValueError::new_err(format!(
+                "Unknown mass mode '{}', expected 'mz', 'singly_charged' or 'neutral'",
+                x
+            ))),
+        }
+    }
+
+    /// Peaks of unknown charge (0) are taken as singly charged.
+    fn convert(&self, mz: f64, charge: usize) -> f64 {
+        let charge = charge.max(1) as f64;
+        match self {
+            MassMode::Mz => mz,
+            MassMode::SinglyCharged => (mz - PROTON_MASS) * charge + PROTON_MASS,
+            MassMode::Neutral => (mz - PROTON_MASS) * charge,
+        }
+    }
+}
+
+/// Settings of MS2 deisotoping.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeisotopingParams {
+    pub tolerance_ppm: f64,
+    pub max_charge: usize,
+    pub mass_mode: MassMode,
+}
+
+impl Default for DeisotopingParams {
+    fn default() -> Self {
+        DeisotopingParams {
+            tolerance_ppm: 10.0,
+            max_charge: 3,
+            mass_mode: MassMode::Mz,
+        }
+    }
+}
+
+/// Intensity scaling of a spectrum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Normalization {
+    Sqrt,
+    /// ln(1 + intensity), so zero intensities stay zero.
+    Log,
+    /// Intensities divided by the total ion current, summing to 1.
+    Tic,
+}
+
+impl Normalization {
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "sqrt" => Ok(Normalization::Sqrt),
+            "log" => Ok(Normalization::Log),
+            "tic" => Ok(Normalization::Tic),
+            x => Err(PyValueError::new_err(format!(
+                "Unknown normalization '{}', expected 'sqrt', 'log' or 'tic'",
+                x
+            ))),
+        }
+    }
+}
+
+/// One transform of a spectrum processing pipeline.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProcessingStep {
+    /// Drops peaks below an absolute intensity or below a fraction of the
+    /// base peak.
+    IntensityFilter {
+        min_intensity: f64,
+        min_relative_intensity: f64,
+    },
+    /// Keeps the `n` most intense peaks, overall or per m/z window.
+    TopN {
+        n: usize,
+        mz_window: Option<f64>,
+    },
+    /// Drops peaks at the precursor m/z and its first `isotopes` isotopes.
+    PrecursorRemoval {
+        tolerance_ppm: f64,
+        isotopes: usize,
+    },
+    MzRange {
+        lower: f64,
+        upper: f64,
+    },
+    Normalize(Normalization),
+    Deisotope(DeisotopingParams),
+}
+
+impl ProcessingStep {
+    pub fn apply(&self, spectrum: &mut PySpectrum) {
+        match *self {
+            ProcessingStep::IntensityFilter {
+                min_intensity,
+                min_relative_intensity,
+            } => {
+                let base_peak = spectrum.intensities.iter().cloned().fold(0.0, f64::max);
+                let threshold = min_intensity.max(min_relative_intensity * base_peak);
+                spectrum.retain_peaks(|_, intensity| intensity >= threshold);
+            }
+            ProcessingStep::TopN { n, mz_window } => top_n(spectrum, n, mz_window),
+            ProcessingStep::PrecursorRemoval {
+                tolerance_ppm,
+                isotopes,
+            } => {
+                let Some(precursor) = &spectrum.precursor else {
+                    return;
+                };
+                let charge = precursor.charge.unwrap_or(1).max(1) as f64;
+                let targets: Vec<f64> = (0..=isotopes)
+                    .map(|i| precursor.mz + i as f64 * ISOTOPE_SPACING / charge)
+                    .collect();
+                spectrum.retain_peaks(|mz, _| {
+                    targets
+                        .iter()
+                        .all(|x| (mz - x).abs() > x * tolerance_ppm / 1e6)
+                });
+            }
+            ProcessingStep::MzRange { lower, upper } => {
+                spectrum.retain_peaks(|mz, _| (lower..=upper).contains(&mz))
+            }
+            ProcessingStep::Normalize(normalization) => normalize(spectrum, normalization),
+            ProcessingStep::Deisotope(params) => deisotope(spectrum, &params),
+        }
+    }
+}
+
+/// Applies the steps of a pipeline in order.
+pub fn process_spectrum(spectrum: &mut PySpectrum, steps: &[ProcessingStep]) {
+    for step in steps {
+        step.apply(spectrum);
+    }
+}
+
+impl PySpectrum {
+    /// Keeps only the peaks at `indices`, in that order, for all per peak
+    /// arrays.
+    pub fn select_peaks(&mut self, indices: &[usize]) {
+        fn select<T: Copy>(values: &[T], indices: &[usize]) -> Vec<T> {
+            indices.iter().map(|x| values[*x]).collect()
+        }
+        self.mz_values = select(&self.mz_values, indices);
+        self.intensities = select(&self.intensities, indices);
+        if let Some(x) = &self.mobility_values {
+            self.mobility_values = Some(select(x, indices));
+        }
+        if let Some(x) = &self.scan_indices {
+            self.scan_indices = Some(select(x, indices));
+        }
+        if let Some(x) = &self.charges {
+            self.charges = Some(select(x, indices));
+        }
+    }
+
+    /// Keeps the peaks for which `keep(mz, intensity)` holds, in order.
+    pub fn retain_peaks(&mut self, keep: impl Fn(f64, f64) -> bool) {
+        let indices: Vec<usize> = (0..self.mz_values.len())
+            .filter(|i| keep(self.mz_values[*i], self.intensities[*i]))
+            .collect();
+        self.select_peaks(&indices);
+    }
+
+    /// A copy of this spectrum with `step` applied.
+    pub fn processed(&self, step: ProcessingStep) -> PySpectrum {
+        let mut spectrum = self.clone();
+        step.apply(&mut spectrum);
+        spectrum
+    }
+
+    pub fn sort_peaks_by_mz(&mut self) {
+        let mut order: Vec<usize> = (0..self.mz_values.len()).collect();
+        order.sort_by(|a, b| self.mz_values[*a].total_cmp(&self.mz_values[*b]));
+        self.select_peaks(&order);
+    }
+}
+
+/// Isotope cluster starting at `mono` for `charge`, with intensities
+/// decreasing from one isotope to the next, as for fragments.
+fn isotope_cluster(
+    mzs: &[f64],
+    intensities: &[f64],
+    assigned: &[bool],
+    mono: usize,
+    charge: usize,
+    tolerance_ppm: f64,
+) -> Vec<usize> {
+    let mut cluster = vec![mono];
+    let mut current = mono;
+    let spacing = ISOTOPE_SPACING / charge as f64;
+    while let Some(peak) = closest_peak(mzs, mzs[current] + spacing, tolerance_ppm, |i| {
+        !assigned[i] && intensities[i] < intensities[current]
+    }) {
+        cluster.push(peak);
+        current = peak;
+    }
+    cluster
+}
+
+/// Collapses the isotope clusters of a spectrum to their monoisotopic peak
+/// carrying the summed cluster intensity, and sets the charge of every peak
+/// (0 when no isotope was found). Peaks are walked from low to high m/z and
+/// the charge explaining the longest cluster wins.
+pub fn deisotope(spectrum: &mut PySpectrum, params: &DeisotopingParams) {
+    spectrum.sort_peaks_by_mz();
+    let mzs = spectrum.mz_values.clone();
+    let intensities = spectrum.intensities.clone();
+    let mut assigned = vec![false; mzs.len()];
+    let mut kept = vec![];
+    let mut charges = vec![];
+    let mut summed = vec![];
+    for mono in 0..mzs.len() {
+        if assigned[mono] {
+            continue;
+        }
+        // On ties the last, highest, charge wins
+        let (charge, cluster) = (1..=params.max_charge)
+            .map(|charge| {
+                let cluster = isotope_cluster(
+                    &mzs,
+                    &intensities,
+                    &assigned,
+                    mono,
+                    charge,
+                    params.tolerance_ppm,
+                );
+                (charge, cluster)
+            })
+            .max_by_key(|(_, cluster)| cluster.len())
+            .unwrap_or((0, vec![mono]));
+        for peak in cluster.iter() {
+            assigned[*peak] = true;
+        }
+        kept.push(mono);
+        charges.push(if cluster.len() > 1 { charge } else { 0 });
+        summed.push(cluster.iter().map(|x| intensities[*x]).sum());
+    }
+    spectrum.select_peaks(&kept);
+    spectrum.intensities = summed;
+    spectrum.mz_values = spectrum
+        .mz_values
+        .iter()
+        .zip(charges.iter())
+        .map(|(mz, charge)| params.mass_mode.convert(*mz, *charge))
+        .collect();
+    spectrum.charges = Some(charges);
+    if params.mass_mode != MassMode::Mz {
+        spectrum.sort_peaks_by_mz();
+    }
+}
+
+/// Keeps the `n` most intense peaks, per m/z window of width `mz_window`
+/// when given, in their original order.
+fn top_n(spectrum: &mut PySpectrum, n: usize, mz_window: Option<f64>) {
+    let window_of = |i: usize| match mz_window {
+        Some(width) => (spectrum.mz_values[i] / width).floor() as i64,
+        None => 0,
+    };
+    let mut order: Vec<usize> = (0..spectrum.mz_values.len()).collect();
+    order.sort_by(|a, b| spectrum.intensities[*b].total_cmp(&spectrum.intensities[*a]));
+    let mut counts: HashMap<i64, usize> = HashMap::new();
+    let mut kept: Vec<usize> = order
+        .into_iter()
+        .filter(|i| {
+            let count = counts.entry(window_of(*i)).or_default();
+            *count += 1;
+            *count <= n
+        })
+        .collect();
+    kept.sort();
+    spectrum.select_peaks(&kept);
+}
+
+fn normalize(spectrum: &mut PySpectrum, normalization: Normalization) {
+    match normalization {
+        Normalization::Sqrt => spectrum.intensities.iter_mut().for_each(|x| *x = x.sqrt()),
+        Normalization::Log => spectrum.intensities.iter_mut().for_each(|x| *x = x.ln_1p()),
+        Normalization::Tic => {
+            let tic: f64 = spectrum.intensities.iter().sum();
+            if tic > 0.0 {
+                spectrum.intensities.iter_mut().for_each(|x| *x /= tic);
+            }
+        }
+    }
+}
@@ -0,0 +1,599 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use pyo3::exceptions::PyIOError;
+use pyo3::PyErr;
+use timsrust::converters::{ConvertableDomain, Scan2ImConverter, Tof2MzConverter};
+use timsrust::readers::{
+    FrameReader, FrameReaderError, MetadataReader, MetadataReaderError, QuadWindowExpansionStrategy,
+};
+use timsrust::Frame;
+
+use crate::prm::PrmLayout;
+use crate::tdf_sql::{
+    find_tdf, ReadableSqlTable, SqlDiaFrame, SqlDiaWindow, SqlFrameInfo, SqlPasefFrameMsMs,
+    SqlPrmFrameMsMs, TdfSqlReader,
+};
+
+/// Scan range of a single frame that contributed peaks to a spectrum.
+///
+/// `frame` is the 0-based position of the frame in the run (as used by
+/// `FrameReader.get`), and scans go from `scan_start` (inclusive) to
+/// `scan_end` (exclusive), as in timsrust.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpectrumSlice {
+    pub frame: usize,
+    pub scan_start: usize,
+    pub scan_end: usize,
+}
+
+/// All the frame slices a spectrum was built from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpectrumSource {
+    pub slices: Vec<SpectrumSlice>,
+    /// Id of the DDA precursor or prm-PASEF target the spectrum belongs to.
+    pub precursor: Option<usize>,
+}
+
+/// Number of decompressed frames kept around, so spectra sharing a frame
+/// (DIA windows, DDA precursors) do not decompress it again.
+const FRAME_CACHE_SIZE: usize = 16;
+
+#[derive(Debug)]
+pub enum SpectrumSourceError {
+    Sql(rusqlite::Error),
+    FrameReader(FrameReaderError),
+    Metadata(MetadataReaderError),
+    UnsupportedAcquisition,
+}
+
+impl Display for SpectrumSourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpectrumSourceError::Sql(e) => write!(f, "{}", e),
+            SpectrumSourceError::FrameReader(e) => write!(f, "{}", e),
+            SpectrumSourceError::Metadata(e) => write!(f, "{}", e),
+            SpectrumSourceError::UnsupportedAcquisition => {
+                write!(f, "Unsupported acquisition type for spectrum sources")
+            }
+        }
+    }
+}
+
+impl From<rusqlite::Error> for SpectrumSourceError {
+    fn from(e: rusqlite::Error) -> Self {
+        SpectrumSourceError::Sql(e)
+    }
+}
+
+impl From<FrameReaderError> for SpectrumSourceError {
+    fn from(e: FrameReaderError) -> Self {
+        SpectrumSourceError::FrameReader(e)
+    }
+}
+
+impl From<MetadataReaderError> for SpectrumSourceError {
+    fn from(e: MetadataReaderError) -> Self {
+        SpectrumSourceError::Metadata(e)
+    }
+}
+
+impl From<SpectrumSourceError> for PyErr {
+    fn from(e: SpectrumSourceError) -> Self {
+        PyIOError::new_err(e.to_string())
+    }
+}
+
+/// Summary of where a spectrum was acquired.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpectrumSourceInfo {
+    /// Average rt of the frames, None when none of them is known.
+    pub rt: Option<f64>,
+    pub frame_indices: Vec<usize>,
+    pub lower_im: f64,
+    pub upper_im: f64,
+}
+
+/// Maps every spectrum of a timsrust `SpectrumReader` back to the frames
+/// and scans it was built from.
+///
+/// The order of the sources matches the spectrum indices timsrust uses, so
+/// `sources[i]` describes `SpectrumReader.get(i)`.
+pub fn read_spectrum_sources(
+    tdf_sql_reader: &TdfSqlReader,
+    sql_frames: &[SqlFrameInfo],
+    strategy: &QuadWindowExpansionStrategy,
+    im_converter: &Scan2ImConverter,
+) -> Result<Vec<SpectrumSource>, SpectrumSourceError> {
+    if sql_frames.iter().any(|x| x.msms_type == 8) {
+        dda_spectrum_sources(tdf_sql_reader)
+    } else if sql_frames.iter().any(|x| x.msms_type == 9) {
+        dia_spectrum_sources(tdf_sql_reader, strategy, im_converter)
+    } else if sql_frames.iter().any(|x| x.msms_type == 10) {
+        prm_spectrum_sources(tdf_sql_reader)
+    } else {
+        Err(SpectrumSourceError::UnsupportedAcquisition)
+    }
+}
+
+fn dda_spectrum_sources(
+    tdf_sql_reader: &TdfSqlReader,
+) -> Result<Vec<SpectrumSource>, SpectrumSourceError> {
+    let mut pasef_frames = SqlPasefFrameMsMs::from_sql_reader(tdf_sql_reader)?;
+    // Stable sort, so frames within a precursor keep the table order (as timsrust does)
+    pasef_frames.sort_by_key(|x| x.precursor);
+    let mut sources: Vec<SpectrumSource> = vec![];
+    let mut last_precursor = None;
+    for pasef_frame in pasef_frames {
+        if last_precursor != Some(pasef_frame.precursor) {
+            sources.push(SpectrumSource {
+                slices: vec![],
+                precursor: Some(pasef_frame.precursor),
+            });
+            last_precursor = Some(pasef_frame.precursor);
+        }
+        if let Some(source) = sources.last_mut() {
+            source.slices.push(SpectrumSlice {
+                frame: pasef_frame.frame - 1,
+                scan_start: pasef_frame.scan_start,
+                scan_end: pasef_frame.scan_end,
+            });
+        }
+    }
+    Ok(sources)
+}
+
+fn dia_spectrum_sources(
+    tdf_sql_reader: &TdfSqlReader,
+    strategy: &QuadWindowExpansionStrategy,
+    im_converter: &Scan2ImConverter,
+) -> Result<Vec<SpectrumSource>, SpectrumSourceError> {
+    let dia_frames = SqlDiaFrame::from_sql_reader(tdf_sql_reader)?;
+    let dia_windows = SqlDiaWindow::from_sql_reader(tdf_sql_reader)?;
+    let num_groups = dia_windows
+        .iter()
+        .map(|x| x.window_group)
+        .max()
+        .unwrap_or(0);
+    let mut groups: Vec<Vec<&SqlDiaWindow>> = vec![vec![]; num_groups];
+    for window in dia_windows.iter() {
+        groups[window.window_group - 1].push(window);
+    }
+    for group in groups.iter_mut() {
+        group.sort_by_key(|x| x.scan_start);
+    }
+
+    let mut sources = vec![];
+    for dia_frame in dia_frames.iter() {
+        for window in groups[dia_frame.window_group - 1].iter() {
+            for (scan_start, scan_end) in
+                scan_range_subsplit(window.scan_start, window.scan_end, strategy, im_converter)
+            {
+                sources.push(SpectrumSource {
+                    slices: vec![SpectrumSlice {
+                        frame: dia_frame.frame - 1,
+                        scan_start,
+                        scan_end,
+                    }],
+                    precursor: None,
+                });
+            }
+        }
+    }
+    Ok(sources)
+}
+
+/// One source per isolation of the PrmFrameMsMsInfo table, in table order.
+fn prm_spectrum_sources(
+    tdf_sql_reader: &TdfSqlReader,
+) -> Result<Vec<SpectrumSource>, SpectrumSourceError> {
+    Ok(SqlPrmFrameMsMs::from_sql_reader(tdf_sql_reader)?
+        .into_iter()
+        .map(|x| SpectrumSource {
+            slices: vec![SpectrumSlice {
+                frame: x.frame - 1,
+                scan_start: x.scan_start,
+                scan_end: x.scan_end,
+            }],
+            precursor: Some(x.target),
+        })
+        .collect())
+}
+
+/// Same splitting of a quadrupole window in sub-windows as timsrust does
+/// when building DIA spectra.
+fn scan_range_subsplit(
+    start: usize,
+    end: usize,
+    strategy: &QuadWindowExpansionStrategy,
+    im_converter: &Scan2ImConverter,
+) -> Vec<(usize, usize)> {
+    match strategy {
+        QuadWindowExpansionStrategy::None => vec![(start, end)],
+        QuadWindowExpansionStrategy::Even(num_splits) => {
+            let sub_subwindow_width = (end - start) / (num_splits + 1);
+            (0..*num_splits)
+                .map(|i| {
+                    (
+                        start + (sub_subwindow_width * i),
+                        start + (sub_subwindow_width * (i + 2)),
+                    )
+                })
+                .collect()
+        }
+        QuadWindowExpansionStrategy::UniformMobility((span, step), converter) => {
+            let converter = converter.unwrap_or(*im_converter);
+            let mut curr_start_offset = start;
+            let mut curr_start_im = converter.convert(curr_start_offset as f64);
+            let mut curr_end_im = curr_start_im - span;
+            let mut curr_end_offset = converter.invert(curr_end_im) as usize;
+            let mut out = Vec::new();
+            while curr_end_offset < end {
+                out.push((curr_start_offset, curr_end_offset));
+                curr_start_im -= step;
+                curr_start_offset = converter.invert(curr_start_im) as usize;
+                curr_end_im = curr_start_im - span;
+                curr_end_offset = converter.invert(curr_end_im) as usize;
+            }
+            if curr_start_offset < end {
+                out.push((curr_start_offset, end));
+            }
+            out
+        }
+        QuadWindowExpansionStrategy::UniformScan((span, step)) => {
+            let mut curr_start_offset = start;
+            let mut curr_end_offset = start + span;
+            let mut out = Vec::new();
+            while curr_end_offset < end {
+                out.push((curr_start_offset, curr_end_offset));
+                curr_start_offset += step;
+                curr_end_offset += step;
+            }
+            if curr_start_offset < end {
+                out.push((curr_start_offset, end));
+            }
+            out
+        }
+    }
+}
+
+/// A spectrum in acquisition order, either built by timsrust (referenced
+/// by its index there) or an MS1 spectrum built from the given source.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpectrumEntry {
+    Ms2(usize),
+    Ms1(SpectrumSource),
+}
+
+/// One source per MS1 frame, or one per mobility slice of each MS1 frame
+/// when a (span, step) in 1/K0 is given.
+pub fn ms1_spectrum_sources(
+    frames: &[SqlFrameInfo],
+    mobility_span_step: Option<(f64, f64)>,
+    im_converter: &Scan2ImConverter,
+) -> Vec<SpectrumSource> {
+    let strategy = match mobility_span_step {
+        Some(span_step) => {
+            QuadWindowExpansionStrategy::UniformMobility(span_step, Some(*im_converter))
+        }
+        None => QuadWindowExpansionStrategy::None,
+    };
+    frames
+        .iter()
+        .enumerate()
+        .filter(|(_, frame)| frame.msms_type == 0)
+        .flat_map(|(position, frame)| {
+            scan_range_subsplit(0, frame.num_scans, &strategy, im_converter)
+                .into_iter()
+                .map(move |(scan_start, scan_end)| SpectrumSource {
+                    slices: vec![SpectrumSlice {
+                        frame: position,
+                        scan_start,
+                        scan_end,
+                    }],
+                    precursor: None,
+                })
+        })
+        .collect()
+}
+
+/// Merges MS2 and MS1 spectra, ordered by the first frame they were acquired in.
+pub fn interleave_spectra(
+    ms2_sources: &[SpectrumSource],
+    ms1_sources: Vec<SpectrumSource>,
+) -> Vec<SpectrumEntry> {
+    let first_frame = |source: &SpectrumSource| source.slices.iter().map(|x| x.frame).min();
+    let mut entries: Vec<(Option<usize>, SpectrumEntry)> = ms2_sources
+        .iter()
+        .enumerate()
+        .map(|(index, source)| (first_frame(source), SpectrumEntry::Ms2(index)))
+        .chain(
+            ms1_sources
+                .into_iter()
+                .map(|source| (first_frame(&source), SpectrumEntry::Ms1(source))),
+        )
+        .collect();
+    // Stable, so spectra from the same frame keep their original order
+    entries.sort_by_key(|x| x.0);
+    entries.into_iter().map(|x| x.1).collect()
+}
+
+/// Traces spectra back to the raw frames they were built from, which allows
+/// resolving the ion mobility of their peaks or building new spectra.
+///
+/// The sources only need the analysis.tdf, frames are only opened once
+/// peaks are read.
+pub struct SpectrumSourceReader {
+    pub sources: Vec<SpectrumSource>,
+    pub frames: Vec<SqlFrameInfo>,
+    pub im_converter: Scan2ImConverter,
+    pub mz_converter: Tof2MzConverter,
+    /// Only set for prm-PASEF runs, with one isolation per source.
+    pub prm: Option<PrmLayout>,
+    path: PathBuf,
+    frame_reader: OnceLock<FrameReader>,
+    frame_cache: Mutex<VecDeque<Arc<Frame>>>,
+}
+
+impl SpectrumSourceReader {
+    pub fn new(
+        path: impl AsRef<Path>,
+        strategy: &QuadWindowExpansionStrategy,
+    ) -> Result<Self, SpectrumSourceError> {
+        let tdf_sql_reader = TdfSqlReader::open(&path)?;
+        let metadata = MetadataReader::new(find_tdf(&path))?;
+        let frames = SqlFrameInfo::from_sql_reader(&tdf_sql_reader)?;
+        let sources =
+            read_spectrum_sources(&tdf_sql_reader, &frames, strategy, &metadata.im_converter)?;
+        let prm = PrmLayout::read(&tdf_sql_reader)?;
+        Ok(SpectrumSourceReader {
+            sources,
+            frames,
+            im_converter: metadata.im_converter,
+            mz_converter: metadata.mz_converter,
+            prm,
+            path: path.as_ref().to_path_buf(),
+            frame_reader: OnceLock::new(),
+            frame_cache: Mutex::new(VecDeque::with_capacity(FRAME_CACHE_SIZE)),
+        })
+    }
+
+    fn frame_reader(&self) -> Result<&FrameReader, SpectrumSourceError> {
+        if let Some(frame_reader) = self.frame_reader.get() {
+            return Ok(frame_reader);
+        }
+        // Concurrent callers may both open the reader, only one is kept
+        let _ = self.frame_reader.set(FrameReader::new(&self.path)?);
+        Ok(self.frame_reader.get().expect("frame reader was just set"))
+    }
+
+    /// A frame by 0-based position, decompressed at most once while it is
+    /// among the most recently read frames.
+    fn frame(&self, position: usize) -> Result<Arc<Frame>, SpectrumSourceError> {
+        let id = self.frames.get(position).map(|x| x.id);
+        let cached =
+            |cache: &VecDeque<Arc<Frame>>| cache.iter().find(|x| Some(x.index) == id).cloned();
+        if let Some(frame) = self.frame_cache.lock().ok().and_then(|x| cached(&x)) {
+            return Ok(frame);
+        }
+        let frame = Arc::new(self.frame_reader()?.get(position)?);
+        if let Ok(mut cache) = self.frame_cache.lock() {
+            if cached(&cache).is_none() {
+                if cache.len() == FRAME_CACHE_SIZE {
+                    cache.pop_front();
+                }
+                cache.push_back(frame.clone());
+            }
+        }
+        Ok(frame)
+    }
+
+    /// Retention time, frame ids and 1/K0 span of a source.
+    pub fn source_info(&self, source: &SpectrumSource) -> Option<SpectrumSourceInfo> {
+        let frames: Vec<&SqlFrameInfo> = source
+            .slices
+            .iter()
+            .filter_map(|x| self.frames.get(x.frame))
+            .collect();
+        let rt = match frames.len() {
+            0 => None,
+            num_frames => Some(frames.iter().map(|x| x.rt).sum::<f64>() / num_frames as f64),
+        };
+        let mut frame_indices: Vec<usize> = frames.iter().map(|x| x.id).collect();
+        frame_indices.sort_unstable();
+        frame_indices.dedup();
+        let scan_start = source.slices.iter().map(|x| x.scan_start).min()?;
+        let scan_end = source.slices.iter().map(|x| x.scan_end).max()?;
+        // Low scan numbers are high 1/K0 values
+        Some(SpectrumSourceInfo {
+            rt,
+            frame_indices,
+            lower_im: self.im_converter.convert(scan_end as f64),
+            upper_im: self.im_converter.convert(scan_start as f64),
+        })
+    }
+
+    /// Intensity weighted average scan (and its 1/K0) for each of the given m/z values.
+    ///
+    /// The m/z values need to come from the spectrum built from this source,
+    /// since peaks are matched back to the raw data by their tof index. This
+    /// uses the uncalibrated m/z converter, so the spectrum must not be
+    /// calibrated.
+    pub fn resolve_mobility(
+        &self,
+        source: &SpectrumSource,
+        mz_values: &[f64],
+    ) -> Result<(Vec<f64>, Vec<usize>), SpectrumSourceError> {
+        let (tofs, scans) = self.weighted_scans(source)?;
+        let scan_indices: Vec<f64> = mz_values
+            .iter()
+            .map(|mz| {
+                let tof = self.mz_converter.invert(*mz).round() as u32;
+                match tofs.binary_search(&tof) {
+                    Ok(i) => scans[i],
+                    Err(i) => nearest(&tofs, &scans, i, tof),
+                }
+            })
+            .collect();
+        let mobility_values = scan_indices
+            .iter()
+            .map(|x| self.im_converter.convert(*x))
+            .collect();
+        let scan_indices = scan_indices
+            .iter()
+            .map(|x| if x.is_nan() { 0 } else { x.round() as usize })
+            .collect();
+        Ok((mobility_values, scan_indices))
+    }
+
+    /// Sums all peaks of a source per tof index, then smooths and centroids
+    /// them the same way timsrust does for its spectra.
+    pub fn build_spectrum(
+        &self,
+        source: &SpectrumSource,
+        smoothing_window: u32,
+        centroiding_window: u32,
+    ) -> Result<(Vec<f64>, Vec<f64>), SpectrumSourceError> {
+        let peaks = self.read_peaks(source)?;
+        let mut tof_indices: Vec<u32> = vec![];
+        let mut intensities: Vec<u64> = vec![];
+        for (i, (tof, intensity, _)) in peaks.iter().enumerate() {
+            if i > 0 && peaks[i - 1].0 == *tof {
+                if let Some(last) = intensities.last_mut() {
+                    *last += intensity;
+                }
+            } else {
+                tof_indices.push(*tof);
+                intensities.push(*intensity);
+            }
+        }
+        let intensities = smooth(&tof_indices, &intensities, smoothing_window);
+        let local_maxima =
+            find_sparse_local_maxima_mask(&tof_indices, &intensities, centroiding_window);
+        let mz_values = tof_indices
+            .iter()
+            .zip(local_maxima.iter())
+            .filter(|(_, keep)| **keep)
+            .map(|(tof, _)| self.mz_converter.convert(*tof))
+            .collect();
+        let intensities = intensities
+            .iter()
+            .zip(local_maxima.iter())
+            .filter(|(_, keep)| **keep)
+            .map(|(intensity, _)| *intensity as f64)
+            .collect();
+        Ok((mz_values, intensities))
+    }
+
+    /// All (tof, intensity, scan) peaks of a source, sorted by tof.
+    fn read_peaks(
+        &self,
+        source: &SpectrumSource,
+    ) -> Result<Vec<(u32, u64, usize)>, SpectrumSourceError> {
+        let mut peaks: Vec<(u32, u64, usize)> = vec![];
+        for slice in source.slices.iter() {
+            let frame = self.frame(slice.frame)?;
+            if frame.intensities.is_empty() {
+                continue;
+            }
+            let scan_end = slice.scan_end.min(frame.scan_offsets.len() - 1);
+            for scan in slice.scan_start..scan_end {
+                for peak in frame.scan_offsets[scan]..frame.scan_offsets[scan + 1] {
+                    peaks.push((
+                        frame.tof_indices[peak],
+                        frame.intensities[peak] as u64,
+                        scan,
+                    ));
+                }
+            }
+        }
+        peaks.sort_by_key(|x| x.0);
+        Ok(peaks)
+    }
+
+    /// Sorted tof indices and their intensity weighted scan within a source.
+    fn weighted_scans(
+        &self,
+        source: &SpectrumSource,
+    ) -> Result<(Vec<u32>, Vec<f64>), SpectrumSourceError> {
+        let peaks = self.read_peaks(source)?;
+        let mut tofs: Vec<u32> = vec![];
+        let mut scans: Vec<f64> = vec![];
+        let mut summed_intensity: u64 = 0;
+        let mut summed_scan: f64 = 0.0;
+        for (i, (tof, intensity, scan)) in peaks.iter().enumerate() {
+            summed_intensity += intensity;
+            summed_scan += (*intensity as f64) * (*scan as f64);
+            if peaks.get(i + 1).map(|x| x.0) != Some(*tof) {
+                tofs.push(*tof);
+                scans.push(match summed_intensity {
+                    0 => *scan as f64,
+                    _ => summed_scan / summed_intensity as f64,
+                });
+                summed_intensity = 0;
+                summed_scan = 0.0;
+            }
+        }
+        Ok((tofs, scans))
+    }
+}
+
+/// Adds the intensity of all neighbours within `window` tof indices, as in timsrust.
+fn smooth(tof_indices: &[u32], intensities: &[u64], window: u32) -> Vec<u64> {
+    let mut smooth_intensities = intensities.to_vec();
+    for (current_index, current_tof) in tof_indices.iter().enumerate() {
+        for (next_index, next_tof) in tof_indices.iter().enumerate().skip(current_index + 1) {
+            if (next_tof - current_tof) > window {
+                break;
+            }
+            smooth_intensities[current_index] += intensities[next_index];
+            smooth_intensities[next_index] += intensities[current_index];
+        }
+    }
+    smooth_intensities
+}
+
+/// Marks the peaks that are the most intense within `window` tof indices, as in timsrust.
+fn find_sparse_local_maxima_mask(
+    tof_indices: &[u32],
+    intensities: &[u64],
+    window: u32,
+) -> Vec<bool> {
+    let mut local_maxima = vec![true; tof_indices.len()];
+    for (index, tof) in tof_indices.iter().enumerate() {
+        for (next_index, next_tof) in tof_indices.iter().enumerate().skip(index + 1) {
+            if (next_tof - tof) > window {
+                break;
+            }
+            if intensities[index] < intensities[next_index] {
+                local_maxima[index] = false
+            } else {
+                local_maxima[next_index] = false
+            }
+        }
+    }
+    local_maxima
+}
+
+fn nearest(tofs: &[u32], scans: &[f64], insertion: usize, tof: u32) -> f64 {
+    let before = insertion.checked_sub(1);
+    let after = if insertion < tofs.len() {
+        Some(insertion)
+    } else {
+        None
+    };
+    match (before, after) {
+        (Some(b), Some(a)) => {
+            if tof - tofs[b] <= tofs[a] - tof {
+                scans[b]
+            } else {
+                scans[a]
+            }
+        }
+        (Some(b), None) => scans[b],
+        (None, Some(a)) => scans[a],
+        (None, None) => f64::NAN,
+    }
+}
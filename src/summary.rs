@@ -0,0 +1,327 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use pyo3::exceptions::PyIOError;
+use pyo3::prelude::*;
+use serde_json::{json, Value};
+use timsrust::converters::ConvertableDomain;
+use timsrust::readers::{MetadataReader, SpectrumReaderConfig};
+
+use crate::tdf_sql::{
+    find_tdf, ReadableSqlTable, SqlDiaWindow, SqlFrameMetadata, SqlPrecursorCharge, TdfSqlReader,
+};
+use crate::timsrust_enums::{PyAcquisitionType, PyMSLevel};
+use crate::timsrust_readers::PySpectrumReader;
+
+fn io_error(e: impl ToString) -> PyErr {
+    PyIOError::new_err(e.to_string())
+}
+
+/// Min, quartiles, max and mean of a set of values.
+fn distribution(values: &[f64]) -> Value {
+    if values.is_empty() {
+        return Value::Null;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let quantile = |q: f64| sorted[((sorted.len() - 1) as f64 * q).round() as usize];
+    json!({
+        "min": sorted[0],
+        "q25": quantile(0.25),
+        "median": quantile(0.5),
+        "q75": quantile(0.75),
+        "max": sorted[sorted.len() - 1],
+        "mean": sorted.iter().sum::<f64>() / sorted.len() as f64,
+    })
+}
+
+fn charge_counts(charges: impl Iterator<Item = Option<usize>>) -> Value {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for charge in charges {
+        let key = charge.map_or("unknown".to_string(), |x| x.to_string());
+        *counts.entry(key).or_default() += 1;
+    }
+    json!(counts)
+}
+
+/// Gaps between consecutive frames longer than `rt_gap_factor` times the
+/// median frame to frame time.
+fn rt_gaps(frames: &[SqlFrameMetadata], rt_gap_factor: f64) -> Vec<Value> {
+    let deltas: Vec<f64> = frames.windows(2).map(|x| x[1].rt - x[0].rt).collect();
+    let median = match distribution(&deltas).get("median") {
+        Some(x) => x.as_f64().unwrap_or(0.0),
+        None => return vec![],
+    };
+    frames
+        .windows(2)
+        .filter(|x| x[1].rt - x[0].rt > median * rt_gap_factor)
+        .map(|x| {
+            json!({
+                "frame": x[0].id,
+                "rt_start": x[0].rt,
+                "rt_end": x[1].rt,
+                "duration": x[1].rt - x[0].rt,
+            })
+        })
+        .collect()
+}
+
+fn tdf_report(path: &Path, rt_gap_factor: f64) -> PyResult<PySummary> {
+    let tdf_sql_reader = TdfSqlReader::open(path).map_err(io_error)?;
+    let metadata = MetadataReader::new(find_tdf(path)).map_err(io_error)?;
+    let frames = SqlFrameMetadata::from_sql_reader(&tdf_sql_reader).map_err(io_error)?;
+    let acquisition_type = PyAcquisitionType::from_msms_types(frames.iter().map(|x| x.msms_type));
+    let ms_level = |x: &SqlFrameMetadata| PyMSLevel::from_msms_type(x.msms_type);
+
+    let mut frames_per_ms_level: BTreeMap<String, usize> = BTreeMap::new();
+    let mut peaks_per_ms_level: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for frame in frames.iter() {
+        let level = ms_level(frame).to_string();
+        *frames_per_ms_level.entry(level.clone()).or_default() += 1;
+        peaks_per_ms_level
+            .entry(level)
+            .or_default()
+            .push(frame.num_peaks.unwrap_or_default() as f64);
+    }
+    let peaks_per_frame: BTreeMap<String, Value> = peaks_per_ms_level
+        .iter()
+        .map(|(level, peaks)| (level.clone(), distribution(peaks)))
+        .collect();
+
+    let ms1_frames: Vec<&SqlFrameMetadata> = frames
+        .iter()
+        .filter(|x| ms_level(x) == PyMSLevel::MS1)
+        .collect();
+    let tic = json!({
+        "rt": ms1_frames.iter().map(|x| x.rt).collect::<Vec<f64>>(),
+        "intensity": ms1_frames.iter().map(|x| x.summed_intensities).collect::<Vec<Option<u64>>>(),
+    });
+
+    let precursors = if tdf_sql_reader.has_table("Precursors").map_err(io_error)? {
+        let precursors = SqlPrecursorCharge::from_sql_reader(&tdf_sql_reader).map_err(io_error)?;
+        json!({
+            "count": precursors.len(),
+            "charges": charge_counts(precursors.iter().map(|x| x.charge)),
+        })
+    } else {
+        Value::Null
+    };
+
+    let mut window_groups: BTreeMap<usize, Vec<Value>> = BTreeMap::new();
+    if tdf_sql_reader
+        .has_table("DiaFrameMsMsWindows")
+        .map_err(io_error)?
+    {
+        for window in SqlDiaWindow::from_sql_reader(&tdf_sql_reader).map_err(io_error)? {
+            window_groups
+                .entry(window.window_group)
+                .or_default()
+                .push(json!({
+                    "isolation_mz": window.isolation_mz,
+                    "isolation_width": window.isolation_width,
+                    "collision_energy": window.collision_energy,
+                    "scan_start": window.scan_start,
+                    "scan_end": window.scan_end,
+                    "lower_im": metadata.im_converter.convert(window.scan_end as f64),
+                    "upper_im": metadata.im_converter.convert(window.scan_start as f64),
+                }));
+        }
+    }
+    let window_layout: Vec<Value> = window_groups
+        .into_iter()
+        .map(|(window_group, windows)| json!({"window_group": window_group, "windows": windows}))
+        .collect();
+
+    let report = json!({
+        "path": path.to_string_lossy(),
+        "acquisition_type": acquisition_type.to_string(),
+        "num_frames": frames.len(),
+        "frames_per_ms_level": frames_per_ms_level,
+        "rt_range": [frames.first().map(|x| x.rt), frames.last().map(|x| x.rt)],
+        "im_range": [metadata.lower_im, metadata.upper_im],
+        "mz_range": [metadata.lower_mz, metadata.upper_mz],
+        "peaks_per_frame": peaks_per_frame,
+        "tic": tic,
+        "precursors": precursors,
+        "window_groups": window_layout,
+        "anomalies": {
+            "empty_frames": frames.iter().filter(|x| x.num_peaks == Some(0)).map(|x| x.id).collect::<Vec<usize>>(),
+            "rt_gaps": rt_gaps(&frames, rt_gap_factor),
+        },
+    });
+    Ok(PySummary {
+        report,
+        tic_svg: tic_svg(&ms1_frames),
+    })
+}
+
+/// minitdf files have no frames, so the report is built from the spectra.
+fn minitdf_report(path: &Path) -> PyResult<PySummary> {
+    let reader = PySpectrumReader::from_config(
+        &path.to_string_lossy(),
+        SpectrumReaderConfig::default(),
+        false,
+        false,
+        None,
+    )?;
+    let spectra = reader.read_spectra(0..reader.len())?;
+    let precursors: Vec<_> = spectra
+        .iter()
+        .filter_map(|x| x.precursor.as_ref())
+        .collect();
+    let rts: Vec<f64> = precursors.iter().map(|x| x.rt).collect();
+    let ims: Vec<f64> = precursors.iter().map(|x| x.im).collect();
+    let mzs: Vec<f64> = spectra.iter().flat_map(|x| x.mz_values.clone()).collect();
+    let range = |x: &[f64]| match distribution(x) {
+        Value::Null => json!([null, null]),
+        x => json!([x["min"], x["max"]]),
+    };
+    let peaks: Vec<f64> = spectra.iter().map(|x| x.mz_values.len() as f64).collect();
+    let empty_spectra: Vec<usize> = spectra
+        .iter()
+        .filter(|x| x.mz_values.is_empty())
+        .map(|x| x.index)
+        .collect();
+    let report = json!({
+        "path": path.to_string_lossy(),
+        "acquisition_type": PyAcquisitionType::Unknown.to_string(),
+        "num_spectra": spectra.len(),
+        "rt_range": range(&rts),
+        "im_range": range(&ims),
+        "mz_range": range(&mzs),
+        "peaks_per_spectrum": distribution(&peaks),
+        "precursors": {
+            "count": precursors.len(),
+            "charges": charge_counts(precursors.iter().map(|x| x.charge)),
+        },
+        "anomalies": {"empty_spectra": empty_spectra},
+    });
+    Ok(PySummary {
+        report,
+        tic_svg: String::new(),
+    })
+}
+
+fn html_value(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let rows: String = map
+                .iter()
+                .map(|(key, value)| {
+                    format!(
+                        "<tr><th>{}</th><td>{}</td></tr>",
+                        html_escape(key),
+                        html_value(value)
+                    )
+                })
+                .collect();
+            format!("<table>{}</table>", rows)
+        }
+        Value::Array(values) if values.iter().any(|x| x.is_object()) => {
+            values.iter().map(html_value).collect()
+        }
+        Value::String(x) => html_escape(x),
+        Value::Null => "-".to_string(),
+        x => html_escape(&x.to_string()),
+    }
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Polyline of the TIC, scaled to a 600x150 box. Frames without a summed
+/// intensity are left out.
+fn tic_svg(ms1_frames: &[&SqlFrameMetadata]) -> String {
+    let points: Vec<(f64, f64)> = ms1_frames
+        .iter()
+        .filter_map(|x| Some((x.rt, x.summed_intensities? as f64)))
+        .collect();
+    if points.len() < 2 {
+        return String::new();
+    }
+    let (min_rt, max_rt) = (points[0].0, points[points.len() - 1].0);
+    let max_intensity = points.iter().map(|x| x.1).fold(1.0, f64::max);
+    let points: Vec<String> = points
+        .iter()
+        .map(|(rt, intensity)| {
+            format!(
+                "{:.1},{:.1}",
+                (rt - min_rt) / (max_rt - min_rt).max(f64::EPSILON) * 600.0,
+                150.0 - intensity / max_intensity * 150.0
+            )
+        })
+        .collect();
+    format!(
+        "<svg width=\"600\" height=\"150\"><polyline fill=\"none\" stroke=\"black\" points=\"{}\"/></svg>",
+        points.join(" ")
+    )
+}
+
+/// QC report of a run, see `summarize`.
+#[pyclass(name = "Summary")]
+pub struct PySummary {
+    pub report: Value,
+    /// Rendered from the typed frames, as the report only has the raw TIC.
+    tic_svg: String,
+}
+
+#[pymethods]
+impl PySummary {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.report).unwrap_or_default()
+    }
+
+    pub fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        py.import("json")?
+            .getattr("loads")?
+            .call1((serde_json::to_string(&self.report).unwrap_or_default(),))
+    }
+
+    /// Standalone html page with the report tables and the TIC.
+    pub fn to_html(&self) -> String {
+        let mut report = self.report.clone();
+        if let Some(x) = report.as_object_mut() {
+            x.remove("tic");
+        }
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Run summary</title>\
+            <style>table{{border-collapse:collapse}}th,td{{border:1px solid #ccc;padding:2px 6px;text-align:left;vertical-align:top}}</style></head>\n\
+            <body>\n<h1>Run summary</h1>\n{}\n<h2>TIC</h2>\n{}\n</body>\n</html>\n",
+            html_value(&report),
+            self.tic_svg
+        )
+    }
+
+    pub fn __repr__(&self) -> String {
+        let count = match self.report.get("num_frames") {
+            Some(x) => format!("num_frames={}", x),
+            None => format!("num_spectra={}", self.report["num_spectra"]),
+        };
+        format!(
+            "Summary(path='{}', acquisition_type={}, {})",
+            self.report["path"].as_str().unwrap_or_default(),
+            self.report["acquisition_type"].as_str().unwrap_or_default(),
+            count,
+        )
+    }
+}
+
+/// Summarizes a .d or .ms2 folder for quality control.
+///
+/// Frame level numbers come from the analysis.tdf tables, so the frames
+/// themselves are never decompressed. Frames more than `rt_gap_factor`
+/// times the median frame spacing apart are reported as rt gaps.
+#[pyfunction]
+#[pyo3(signature = (path, rt_gap_factor=5.0))]
+pub fn summarize(path: &str, rt_gap_factor: f64) -> PyResult<PySummary> {
+    let path = Path::new(path);
+    let is_minitdf = path.extension().and_then(|x| x.to_str()) == Some("ms2");
+    if is_minitdf {
+        minitdf_report(path)
+    } else {
+        tdf_report(path, rt_gap_factor)
+    }
+}
@@ -0,0 +1,564 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyList;
+use rusqlite::types::{FromSql, Value};
+use rusqlite::{params_from_iter, Connection, OpenFlags, Row};
+
+use crate::dataframes::{Column, ColumnTable, PyArrowTable};
+
+/// Read-only connection to the `analysis.tdf` sqlite file of a `.d` folder.
+#[derive(Debug)]
+pub struct TdfSqlReader {
+    connection: Connection,
+    path: PathBuf,
+}
+
+impl TdfSqlReader {
+    /// Opens either an `analysis.tdf` file or the `.d` folder containing it.
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        let path = find_tdf(path);
+        let connection = Connection::open_with_flags(
+            &path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        Ok(TdfSqlReader { connection, path })
+    }
+
+    /// Opens a sqlite file that nobody writes to as immutable, so reading it
+    /// never creates `-shm`/`-wal` files next to it (e.g. the settings files
+    /// of a method folder, which are in WAL mode).
+    pub fn open_immutable(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let escaped = path
+            .to_string_lossy()
+            .replace('%', "%25")
+            .replace('?', "%3f")
+            .replace('#', "%23");
+        let connection = Connection::open_with_flags(
+            format!("file:{}?immutable=1", escaped),
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+                | OpenFlags::SQLITE_OPEN_URI
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        Ok(TdfSqlReader { connection, path })
+    }
+
+    pub fn get_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    pub fn has_table(&self, table_name: &str) -> rusqlite::Result<bool> {
+        let count: usize = self.connection.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [table_name],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    /// Runs a single read-only statement and returns its columns in select order.
+    pub fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<(String, Vec<Value>)>, String> {
+        let mut stmt = self.connection.prepare(sql).map_err(|e| e.to_string())?;
+        if !stmt.readonly() {
+            return Err("Only read-only statements are allowed".to_string());
+        }
+        let names: Vec<String> = stmt.column_names().iter().map(|x| x.to_string()).collect();
+        let mut columns: Vec<Vec<Value>> = vec![vec![]; names.len()];
+        let mut rows = stmt
+            .query(params_from_iter(params.iter()))
+            .map_err(|e| e.to_string())?;
+        while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+            for (i, column) in columns.iter_mut().enumerate() {
+                column.push(row.get(i).map_err(|e| e.to_string())?);
+            }
+        }
+        Ok(names.into_iter().zip(columns).collect())
+    }
+
+    pub fn read_column_from_table<T: FromSql + Default>(
+        &self,
+        column_name: &str,
+        table_name: &str,
+    ) -> rusqlite::Result<Vec<T>> {
+        let query = format!("SELECT {} FROM {}", column_name, table_name);
+        let mut stmt = self.connection.prepare(&query)?;
+        let rows = stmt.query_map([], |row| Ok(row.parse_default(0)))?;
+        rows.collect()
+    }
+}
+
+/// Resolves the `analysis.tdf` file for a path that may point to the `.d` folder.
+pub fn find_tdf(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    if path.is_dir() {
+        path.join("analysis.tdf")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+pub trait ParseDefault {
+    fn parse_default<T: Default + FromSql>(&self, index: usize) -> T;
+
+    /// None when the column is missing (older schemas) or NULL.
+    fn parse_optional<T: FromSql>(&self, column_name: &str) -> Option<T>;
+}
+
+impl ParseDefault for Row<'_> {
+    fn parse_default<T: Default + FromSql>(&self, index: usize) -> T {
+        self.get(index).unwrap_or_default()
+    }
+
+    fn parse_optional<T: FromSql>(&self, column_name: &str) -> Option<T> {
+        self.get::<_, Option<T>>(column_name).ok().flatten()
+    }
+}
+
+/// A table of `analysis.tdf` that maps one row to one struct.
+pub trait ReadableSqlTable: Sized {
+    fn get_sql_query() -> String;
+
+    fn from_sql_row(row: &Row) -> Self;
+
+    fn from_sql_reader(reader: &TdfSqlReader) -> rusqlite::Result<Vec<Self>> {
+        let mut stmt = reader.connection.prepare(&Self::get_sql_query())?;
+        let rows = stmt.query_map([], |row| Ok(Self::from_sql_row(row)))?;
+        rows.collect()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SqlFrameInfo {
+    pub id: usize,
+    pub rt: f64,
+    pub msms_type: u8,
+    pub num_scans: usize,
+}
+
+impl ReadableSqlTable for SqlFrameInfo {
+    fn get_sql_query() -> String {
+        "SELECT Id, Time, MsMsType, NumScans FROM Frames".to_string()
+    }
+
+    fn from_sql_row(row: &Row) -> Self {
+        SqlFrameInfo {
+            id: row.parse_default(0),
+            rt: row.parse_default(1),
+            msms_type: row.parse_default(2),
+            num_scans: row.parse_default(3),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SqlDiaFrame {
+    pub frame: usize,
+    pub window_group: usize,
+}
+
+impl ReadableSqlTable for SqlDiaFrame {
+    fn get_sql_query() -> String {
+        "SELECT Frame, WindowGroup FROM DiaFrameMsMsInfo".to_string()
+    }
+
+    fn from_sql_row(row: &Row) -> Self {
+        SqlDiaFrame {
+            frame: row.parse_default(0),
+            window_group: row.parse_default(1),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SqlDiaWindow {
+    pub window_group: usize,
+    pub scan_start: usize,
+    pub scan_end: usize,
+    pub isolation_mz: f64,
+    pub isolation_width: f64,
+    pub collision_energy: f64,
+}
+
+impl ReadableSqlTable for SqlDiaWindow {
+    fn get_sql_query() -> String {
+        "SELECT WindowGroup, ScanNumBegin, ScanNumEnd, IsolationMz, IsolationWidth, CollisionEnergy FROM DiaFrameMsMsWindows".to_string()
+    }
+
+    fn from_sql_row(row: &Row) -> Self {
+        SqlDiaWindow {
+            window_group: row.parse_default(0),
+            scan_start: row.parse_default(1),
+            scan_end: row.parse_default(2),
+            isolation_mz: row.parse_default(3),
+            isolation_width: row.parse_default(4),
+            collision_energy: row.parse_default(5),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SqlPasefFrameMsMs {
+    pub frame: usize,
+    pub scan_start: usize,
+    pub scan_end: usize,
+    pub isolation_mz: f64,
+    pub isolation_width: f64,
+    pub collision_energy: f64,
+    pub precursor: usize,
+}
+
+impl ReadableSqlTable for SqlPasefFrameMsMs {
+    fn get_sql_query() -> String {
+        "SELECT Frame, ScanNumBegin, ScanNumEnd, IsolationMz, IsolationWidth, CollisionEnergy, Precursor FROM PasefFrameMsMsInfo".to_string()
+    }
+
+    fn from_sql_row(row: &Row) -> Self {
+        SqlPasefFrameMsMs {
+            frame: row.parse_default(0),
+            scan_start: row.parse_default(1),
+            scan_end: row.parse_default(2),
+            isolation_mz: row.parse_default(3),
+            isolation_width: row.parse_default(4),
+            collision_energy: row.parse_default(5),
+            precursor: row.parse_default(6),
+        }
+    }
+}
+
+/// One quadrupole isolation of a prm-PASEF frame, targeting a single entry
+/// of the PrmTargets table.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SqlPrmFrameMsMs {
+    pub frame: usize,
+    pub scan_start: usize,
+    pub scan_end: usize,
+    pub isolation_mz: f64,
+    pub isolation_width: f64,
+    pub collision_energy: f64,
+    pub target: usize,
+}
+
+impl ReadableSqlTable for SqlPrmFrameMsMs {
+    fn get_sql_query() -> String {
+        "SELECT Frame, ScanNumBegin, ScanNumEnd, IsolationMz, IsolationWidth, CollisionEnergy, Target FROM PrmFrameMsMsInfo ORDER BY Frame, ScanNumBegin".to_string()
+    }
+
+    fn from_sql_row(row: &Row) -> Self {
+        SqlPrmFrameMsMs {
+            frame: row.parse_default(0),
+            scan_start: row.parse_default(1),
+            scan_end: row.parse_default(2),
+            isolation_mz: row.parse_default(3),
+            isolation_width: row.parse_default(4),
+            collision_energy: row.parse_default(5),
+            target: row.parse_default(6),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SqlPrmTarget {
+    pub id: usize,
+    pub external_id: Option<String>,
+    pub rt: f64,
+    pub im: f64,
+    pub monoisotopic_mz: f64,
+    pub charge: Option<usize>,
+    pub description: Option<String>,
+}
+
+impl ReadableSqlTable for SqlPrmTarget {
+    fn get_sql_query() -> String {
+        "SELECT Id, ExternalId, Time, OneOverK0, MonoisotopicMz, Charge, Description FROM PrmTargets ORDER BY Id".to_string()
+    }
+
+    fn from_sql_row(row: &Row) -> Self {
+        SqlPrmTarget {
+            id: row.parse_default(0),
+            external_id: row.parse_optional("ExternalId"),
+            rt: row.parse_default(2),
+            im: row.parse_default(3),
+            monoisotopic_mz: row.parse_default(4),
+            charge: row.parse_optional("Charge"),
+            description: row.parse_optional("Description"),
+        }
+    }
+}
+
+/// Spot position and laser settings of a MALDI frame.
+///
+/// Columns that are not present in every schema version are optional.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SqlMaldiFrame {
+    pub frame: usize,
+    pub chip: Option<usize>,
+    pub spot_name: String,
+    pub region: Option<usize>,
+    pub x: i64,
+    pub y: i64,
+    pub motor_x: Option<f64>,
+    pub motor_y: Option<f64>,
+    pub motor_z: Option<f64>,
+    pub laser_info: Option<String>,
+    pub laser_power: Option<f64>,
+    pub laser_rep_rate: Option<f64>,
+    pub laser_shots: Option<usize>,
+    pub laser_beam_scan_size_x: Option<f64>,
+    pub laser_beam_scan_size_y: Option<f64>,
+}
+
+impl ReadableSqlTable for SqlMaldiFrame {
+    fn get_sql_query() -> String {
+        "SELECT * FROM MaldiFrameInfo ORDER BY Frame".to_string()
+    }
+
+    fn from_sql_row(row: &Row) -> Self {
+        SqlMaldiFrame {
+            frame: row.parse_optional("Frame").unwrap_or_default(),
+            chip: row.parse_optional("Chip"),
+            spot_name: row.parse_optional("SpotName").unwrap_or_default(),
+            region: row.parse_optional("RegionNumber"),
+            x: row.parse_optional("XIndexPos").unwrap_or_default(),
+            y: row.parse_optional("YIndexPos").unwrap_or_default(),
+            motor_x: row.parse_optional("MotorPositionX"),
+            motor_y: row.parse_optional("MotorPositionY"),
+            motor_z: row.parse_optional("MotorPositionZ"),
+            laser_info: row.parse_optional("LaserInfo"),
+            laser_power: row.parse_optional("LaserPower"),
+            laser_rep_rate: row.parse_optional("LaserRepRate"),
+            laser_shots: row.parse_optional("LaserBurstCount"),
+            laser_beam_scan_size_x: row.parse_optional("LaserBeamScanSizeX"),
+            laser_beam_scan_size_y: row.parse_optional("LaserBeamScanSizeY"),
+        }
+    }
+}
+
+/// All per-frame columns of the Frames table.
+///
+/// Columns that are not present in every schema version are optional.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SqlFrameMetadata {
+    pub id: usize,
+    pub rt: f64,
+    pub polarity: Option<String>,
+    pub scan_mode: Option<u8>,
+    pub msms_type: u8,
+    pub tims_id: Option<u64>,
+    pub max_intensity: Option<u64>,
+    pub summed_intensities: Option<u64>,
+    pub num_scans: Option<usize>,
+    pub num_peaks: Option<usize>,
+    pub mz_calibration: Option<usize>,
+    pub t1: Option<f64>,
+    pub t2: Option<f64>,
+    pub tims_calibration: Option<usize>,
+    pub property_group: Option<usize>,
+    pub accumulation_time: Option<f64>,
+    pub ramp_time: Option<f64>,
+    pub pressure: Option<f64>,
+}
+
+impl ReadableSqlTable for SqlFrameMetadata {
+    fn get_sql_query() -> String {
+        "SELECT * FROM Frames".to_string()
+    }
+
+    fn from_sql_row(row: &Row) -> Self {
+        SqlFrameMetadata {
+            id: row.parse_optional("Id").unwrap_or_default(),
+            rt: row.parse_optional("Time").unwrap_or_default(),
+            polarity: row.parse_optional("Polarity"),
+            scan_mode: row.parse_optional("ScanMode"),
+            msms_type: row.parse_optional("MsMsType").unwrap_or_default(),
+            tims_id: row.parse_optional("TimsId"),
+            max_intensity: row.parse_optional("MaxIntensity"),
+            summed_intensities: row.parse_optional("SummedIntensities"),
+            num_scans: row.parse_optional("NumScans"),
+            num_peaks: row.parse_optional("NumPeaks"),
+            mz_calibration: row.parse_optional("MzCalibration"),
+            t1: row.parse_optional("T1"),
+            t2: row.parse_optional("T2"),
+            tims_calibration: row.parse_optional("TimsCalibration"),
+            property_group: row.parse_optional("PropertyGroup"),
+            accumulation_time: row.parse_optional("AccumulationTime"),
+            ramp_time: row.parse_optional("RampTime"),
+            pressure: row.parse_optional("Pressure"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SqlPrecursorCharge {
+    pub id: usize,
+    pub charge: Option<usize>,
+}
+
+impl ReadableSqlTable for SqlPrecursorCharge {
+    fn get_sql_query() -> String {
+        "SELECT Id, Charge FROM Precursors".to_string()
+    }
+
+    fn from_sql_row(row: &Row) -> Self {
+        SqlPrecursorCharge {
+            id: row.parse_default(0),
+            charge: row.parse_default(1),
+        }
+    }
+}
+
+fn py_to_sql_value(value: &Bound<'_, PyAny>) -> PyResult<Value> {
+    if value.is_none() {
+        Ok(Value::Null)
+    } else if let Ok(x) = value.extract::<i64>() {
+        Ok(Value::Integer(x))
+    } else if let Ok(x) = value.extract::<f64>() {
+        Ok(Value::Real(x))
+    } else if let Ok(x) = value.extract::<String>() {
+        Ok(Value::Text(x))
+    } else if let Ok(x) = value.extract::<Vec<u8>>() {
+        Ok(Value::Blob(x))
+    } else {
+        Err(PyValueError::new_err(
+            "Query parameters must be None, int, float, str or bytes",
+        ))
+    }
+}
+
+/// A `TdfSqlReader` opened on first use and kept, so repeated queries from
+/// the same python object share one connection.
+#[derive(Debug)]
+pub struct SharedTdfSqlReader {
+    path: PathBuf,
+    reader: Mutex<Option<TdfSqlReader>>,
+}
+
+impl SharedTdfSqlReader {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        SharedTdfSqlReader {
+            path: find_tdf(path),
+            reader: Mutex::new(None),
+        }
+    }
+
+    fn query(&self, sql: &str, params: &[Value]) -> PyResult<Vec<(String, Vec<Value>)>> {
+        let mut reader = self
+            .reader
+            .lock()
+            .map_err(|e| PyIOError::new_err(e.to_string()))?;
+        if reader.is_none() {
+            let opened =
+                TdfSqlReader::open(&self.path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+            *reader = Some(opened);
+        }
+        reader
+            .as_ref()
+            .unwrap()
+            .query(sql, params)
+            .map_err(PyValueError::new_err)
+    }
+}
+
+impl From<TdfSqlReader> for SharedTdfSqlReader {
+    fn from(reader: TdfSqlReader) -> Self {
+        SharedTdfSqlReader {
+            path: reader.get_path(),
+            reader: Mutex::new(Some(reader)),
+        }
+    }
+}
+
+/// SQLite values are typed per cell: integer and real columns become Arrow
+/// numbers, anything mixed with text (e.g. `Properties.Value`) becomes text.
+fn sql_column(values: Vec<Value>) -> Column {
+    let all = |f: fn(&Value) -> bool| values.iter().all(|x| *x == Value::Null || f(x));
+    if all(|x| matches!(x, Value::Integer(_))) {
+        Column::OptionI64(
+            values
+                .into_iter()
+                .map(|x| match x {
+                    Value::Integer(x) => Some(x),
+                    _ => None,
+                })
+                .collect(),
+        )
+    } else if all(|x| matches!(x, Value::Integer(_) | Value::Real(_))) {
+        Column::OptionF64(
+            values
+                .into_iter()
+                .map(|x| match x {
+                    Value::Integer(x) => Some(x as f64),
+                    Value::Real(x) => Some(x),
+                    _ => None,
+                })
+                .collect(),
+        )
+    } else if all(|x| matches!(x, Value::Blob(_))) {
+        Column::OptionBytes(
+            values
+                .into_iter()
+                .map(|x| match x {
+                    Value::Blob(x) => Some(x),
+                    _ => None,
+                })
+                .collect(),
+        )
+    } else {
+        Column::OptionStr(
+            values
+                .into_iter()
+                .map(|x| match x {
+                    Value::Null => None,
+                    Value::Integer(x) => Some(x.to_string()),
+                    Value::Real(x) => Some(x.to_string()),
+                    Value::Text(x) => Some(x),
+                    Value::Blob(x) => Some(String::from_utf8_lossy(&x).into_owned()),
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Runs a read-only query on `analysis.tdf` and returns its columns as an
+/// Arrow table.
+pub fn query_to_arrow(
+    py: Python<'_>,
+    reader: &SharedTdfSqlReader,
+    sql: &str,
+    params: Option<&Bound<'_, PyList>>,
+) -> PyResult<PyArrowTable> {
+    let params: Vec<Value> = match params {
+        Some(params) => params
+            .iter()
+            .map(|x| py_to_sql_value(&x))
+            .collect::<PyResult<_>>()?,
+        None => vec![],
+    };
+    let columns = py.allow_threads(|| reader.query(sql, &params))?;
+    let mut table = ColumnTable::default();
+    for (name, values) in columns {
+        table.push(name, sql_column(values));
+    }
+    table.into_arrow()
+}
+
+/// Runs a read-only SQL query on the analysis.tdf of a `.d` folder.
+///
+/// Returns an `ArrowTable` with one column per selected column, `params`
+/// are bound to the `?` placeholders of the query.
+#[pyfunction]
+#[pyo3(signature = (path, sql, params=None))]
+pub fn query_tdf(
+    py: Python<'_>,
+    path: PathBuf,
+    sql: &str,
+    params: Option<&Bound<'_, PyList>>,
+) -> PyResult<PyArrowTable> {
+    query_to_arrow(py, &SharedTdfSqlReader::new(path), sql, params)
+}
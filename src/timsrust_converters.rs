@@ -4,6 +4,8 @@ use timsrust::converters::{
 
 use pyo3::prelude::*;
 
+use crate::ccs::{ccs_to_im, im_to_ccs, map_ccs, PyDriftGas};
+
 #[derive(Clone)]
 #[pyclass(name = "Frame2RtConverter")]
 pub struct PyFrame2RtConverter {
@@ -60,6 +62,41 @@ impl From<&Tof2MzConverter> for PyTof2MzConverter {
     }
 }
 
+#[pymethods]
+impl PyScan2ImConverter {
+    /// Collision cross sections in Å² of (fractional) scan indices, given
+    /// the m/z and charge of each ion, see `im_to_ccs`.
+    #[pyo3(signature = (scans, mzs, charges, gas=PyDriftGas::N2, temperature=305.0))]
+    pub fn scans_to_ccs(
+        &self,
+        scans: Vec<f64>,
+        mzs: Vec<f64>,
+        charges: Vec<usize>,
+        gas: PyDriftGas,
+        temperature: f64,
+    ) -> PyResult<Vec<f64>> {
+        map_ccs(&scans, &mzs, &charges, |scan, mz, charge| {
+            im_to_ccs(self.convert(scan), mz, charge, gas, temperature)
+        })
+    }
+
+    /// Fractional scan indices of collision cross sections in Å², the
+    /// inverse of `scans_to_ccs`.
+    #[pyo3(signature = (ccs, mzs, charges, gas=PyDriftGas::N2, temperature=305.0))]
+    pub fn ccs_to_scans(
+        &self,
+        ccs: Vec<f64>,
+        mzs: Vec<f64>,
+        charges: Vec<usize>,
+        gas: PyDriftGas,
+        temperature: f64,
+    ) -> PyResult<Vec<f64>> {
+        map_ccs(&ccs, &mzs, &charges, |ccs, mz, charge| {
+            self.invert(ccs_to_im(ccs, mz, charge, gas, temperature))
+        })
+    }
+}
+
 impl ConvertableDomain for PyTof2MzConverter {
     fn convert<T: Into<f64> + Copy>(&self, x: T) -> f64 {
         self.converter.convert(x)
@@ -68,3 +105,17 @@ impl ConvertableDomain for PyTof2MzConverter {
         self.converter.invert(value)
     }
 }
+
+/// Scans `start..end` with a 1/K0 within `lower_im..=upper_im`, clamped to
+/// the `num_scans` of a frame. Higher scans have lower mobility, so the
+/// upper 1/K0 gives the first scan.
+pub fn scan_range(
+    im_converter: &Scan2ImConverter,
+    lower_im: f64,
+    upper_im: f64,
+    num_scans: usize,
+) -> (usize, usize) {
+    let end = ((im_converter.invert(lower_im).floor() + 1.0).max(0.0) as usize).min(num_scans);
+    let start = (im_converter.invert(upper_im).ceil().max(0.0) as usize).min(end);
+    (start, end)
+}
@@ -13,6 +13,8 @@ pub enum PyAcquisitionType {
     DIAPASEF,
     #[pyo3(name = "DiagonalDIAPASEF")]
     DiagonalDIAPASEF,
+    #[pyo3(name = "PRMPASEF")]
+    PRMPASEF,
     #[pyo3(name = "Unknown")]
     Unknown,
 }
@@ -26,12 +28,30 @@ impl Display for PyAcquisitionType {
                 PyAcquisitionType::DDAPASEF => "DDAPASEF",
                 PyAcquisitionType::DIAPASEF => "DIAPASEF",
                 PyAcquisitionType::DiagonalDIAPASEF => "DiagonalDIAPASEF",
+                PyAcquisitionType::PRMPASEF => "PRMPASEF",
                 PyAcquisitionType::Unknown => "Unknown",
             }
         )
     }
 }
 
+impl PyAcquisitionType {
+    /// Acquisition type of a run from the MsMsType column of its Frames
+    /// table. timsrust has no prm-PASEF type, so those runs are detected here.
+    pub fn from_msms_types(msms_types: impl IntoIterator<Item = u8>) -> Self {
+        let msms_types: Vec<u8> = msms_types.into_iter().collect();
+        if msms_types.contains(&8) {
+            PyAcquisitionType::DDAPASEF
+        } else if msms_types.contains(&9) {
+            PyAcquisitionType::DIAPASEF
+        } else if msms_types.contains(&10) {
+            PyAcquisitionType::PRMPASEF
+        } else {
+            PyAcquisitionType::Unknown
+        }
+    }
+}
+
 impl From<&AcquisitionType> for PyAcquisitionType {
     fn from(x: &AcquisitionType) -> Self {
         match x {
@@ -54,6 +74,16 @@ pub enum PyMSLevel {
     Unknown,
 }
 
+impl PyMSLevel {
+    /// As `MSLevel::read_from_msms_type`, but with prm-PASEF frames as MS2.
+    pub fn from_msms_type(msms_type: u8) -> Self {
+        match msms_type {
+            10 => PyMSLevel::MS2,
+            x => PyMSLevel::from(&MSLevel::read_from_msms_type(x)),
+        }
+    }
+}
+
 impl From<&MSLevel> for PyMSLevel {
     fn from(x: &MSLevel) -> Self {
         match x {
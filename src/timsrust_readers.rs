@@ -1,42 +1,172 @@
+use std::fmt::Display;
+use std::ops::Range;
 use std::path::Path;
 
-use pyo3::exceptions::PyIOError;
+use pyo3::exceptions::{PyIOError, PyIndexError, PyValueError};
 use pyo3::prelude::*;
-use rayon::iter::ParallelIterator;
-use timsrust::readers::FrameReader;
-use timsrust::{AcquisitionType, MSLevel};
+use pyo3::types::{PyDict, PyList};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use timsrust::readers::{FrameReader, MetadataReader};
+use timsrust::{AcquisitionType, Frame, MSLevel, Metadata, Spectrum};
 
+use crate::binning::{BinningParams, PyBinnedSpectra, PyBinnedSpectraIterator};
+use crate::cycles::PyCycleIterator;
+use crate::dataframes::{
+    frames_table, read_frame_metadata, spectra_table, ColumnTable, PyArrowTable,
+};
+use crate::dia_extraction::{extract_dia_targets, PyDiaTarget, PyDiaTraces};
+use crate::features::{add_mobilograms, find_features, FeatureFinderParams, PyFeature};
+use crate::frame_grids::{GridParams, PyFrameGrid};
+use crate::library::{search_params, PyLibraryHit, PySpectralLibrary};
+use crate::maldi::{MaldiLayout, PyIonImage, PyMaldiFrameInfo};
+use crate::method::PyPrmTarget;
+use crate::peak_picking::{pick_peaks, PeakPickingParams, PyPickedPeaks};
+use crate::precursor_envelopes::{
+    annotate_precursor, detect_envelopes, EnvelopeParams, PyIsotopeEnvelope,
+};
+use crate::prm::{is_prm_run, PrmLayout, PyPrmTransitions};
+use crate::spectrum_processing::{
+    process_spectrum, DeisotopingParams, MassMode, Normalization, ProcessingStep,
+};
+use crate::spectrum_sources::{
+    interleave_spectra, ms1_spectrum_sources, SpectrumEntry, SpectrumSource, SpectrumSourceError,
+    SpectrumSourceReader,
+};
+use crate::tdf_sql::{
+    find_tdf, query_to_arrow, ReadableSqlTable, SharedTdfSqlReader, SqlFrameInfo, TdfSqlReader,
+};
+use crate::timsrust_enums::PyMSLevel;
 use crate::timsrust_structs::PyFrame;
-use crate::timsrust_structs::PySpectrum;
+use crate::timsrust_structs::{PyPrecursor, PySpectrum};
 use std::sync::Arc;
 use timsrust::readers::SpectrumReader;
 use timsrust::readers::{
     FrameWindowSplittingConfiguration, QuadWindowExpansionStrategy, SpectrumProcessingParams,
-    SpectrumReaderBuilder, SpectrumReaderConfig,
+    SpectrumReaderConfig,
 };
 
 #[pyclass(name = "FrameReader")]
 pub struct PyFrameReader {
     pub reader: FrameReader,
+    /// Only set for prm-PASEF runs, which timsrust does not label.
+    pub prm: Option<PrmLayout>,
+    /// Only set for MALDI runs.
+    pub maldi: Option<MaldiLayout>,
+    /// The analysis.tdf connection used for `query`.
+    pub sql: SharedTdfSqlReader,
     pub i: usize,
 }
 
+impl PyFrameReader {
+    /// The frames are dropped before the table is handed to Arrow.
+    fn all_frames_table(&self) -> PyResult<ColumnTable> {
+        let frames = self.read_all_frames()?;
+        Ok(frames_table(&frames.iter().collect::<Vec<_>>()))
+    }
+
+    fn to_py_frame(&self, frame: Frame) -> PyFrame {
+        let mut frame = PyFrame::from(frame);
+        if let Some(prm) = &self.prm {
+            prm.label_frame(&mut frame);
+        }
+        if let Some(maldi) = &self.maldi {
+            frame.maldi_info = maldi.spot(frame.index).map(PyMaldiFrameInfo::from);
+        }
+        frame
+    }
+
+    /// MS level of a frame, with prm-PASEF isolations as MS2.
+    fn ms_level(&self, frame: &Frame) -> PyMSLevel {
+        match &self.prm {
+            Some(prm) if !prm.frame_isolations(frame.index).is_empty() => PyMSLevel::MS2,
+            _ => PyMSLevel::from(&frame.ms_level),
+        }
+    }
+
+    fn read_metadata(&self) -> PyResult<Metadata> {
+        MetadataReader::new(find_tdf(self.reader.get_path()))
+            .map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    fn picked_peaks(
+        &self,
+        ms_level: Option<PyMSLevel>,
+        metadata: &Metadata,
+        params: &PeakPickingParams,
+    ) -> PyResult<Vec<PyPickedPeaks>> {
+        self.reader
+            .parallel_filter(|x| ms_level.is_none_or(|level| self.ms_level(x) == level))
+            .map(|x| match x {
+                Ok(x) => Ok(pick_peaks(
+                    &self.to_py_frame(x),
+                    &metadata.mz_converter,
+                    &metadata.im_converter,
+                    params,
+                )),
+                Err(_) => Err(PyIOError::new_err("Could not read frame, Corrupt frame")),
+            })
+            .collect()
+    }
+
+    fn envelopes(
+        &self,
+        precursors: &[PyPrecursor],
+        params: &EnvelopeParams,
+    ) -> PyResult<Vec<Option<PyIsotopeEnvelope>>> {
+        let metadata = self.read_metadata()?;
+        let frames = TdfSqlReader::open(self.reader.get_path())
+            .and_then(|x| SqlFrameInfo::from_sql_reader(&x))
+            .map_err(|e| PyIOError::new_err(e.to_string()))?;
+        detect_envelopes(
+            &self.reader,
+            &frames,
+            precursors,
+            &metadata.mz_converter,
+            &metadata.im_converter,
+            params,
+        )
+        .map_err(|_| PyIOError::new_err("Could not read frame, Corrupt frame"))
+    }
+
+    fn maldi_layout(&self) -> PyResult<&MaldiLayout> {
+        self.maldi
+            .as_ref()
+            .ok_or_else(|| PyValueError::new_err("Not a MALDI run"))
+    }
+
+    fn prm_layout(&self) -> PyResult<&PrmLayout> {
+        self.prm
+            .as_ref()
+            .ok_or_else(|| PyValueError::new_err("Not a prm-PASEF run"))
+    }
+}
+
 #[pymethods]
 impl PyFrameReader {
     #[new]
-    fn new(path: &str) -> PyResult<Self> {
+    pub fn new(path: &str) -> PyResult<Self> {
+        let reader = match FrameReader::new(Path::new(path)) {
+            Ok(x) => x,
+            Err(_) => return Err(PyIOError::new_err("Could not open file")),
+        };
+        let tdf_sql_reader =
+            TdfSqlReader::open(reader.get_path()).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let prm =
+            PrmLayout::read(&tdf_sql_reader).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let maldi =
+            MaldiLayout::read(&tdf_sql_reader).map_err(|e| PyIOError::new_err(e.to_string()))?;
         Ok(PyFrameReader {
-            reader: match FrameReader::new(Path::new(path)) {
-                Ok(x) => x,
-                Err(_) => return Err(PyIOError::new_err("Could not open file")),
-            },
+            reader,
+            prm,
+            maldi,
+            sql: SharedTdfSqlReader::from(tdf_sql_reader),
             i: 0,
         })
     }
 
     pub fn read_frame(&self, index: usize) -> PyResult<PyFrame> {
         match self.reader.get(index) {
-            Ok(x) => Ok(PyFrame::from(x)),
+            Ok(x) => Ok(self.to_py_frame(x)),
             Err(_) => Err(PyIOError::new_err("Could not read frame, Corrupt frame")),
         }
     }
@@ -46,7 +176,7 @@ impl PyFrameReader {
             .get_all()
             .into_iter()
             .map(|x| match x {
-                Ok(x) => Ok(PyFrame::from(x)),
+                Ok(x) => Ok(self.to_py_frame(x)),
                 Err(_) => Err(PyIOError::new_err("Could not read frame, Corrupt frame")),
             })
             .collect()
@@ -58,7 +188,7 @@ impl PyFrameReader {
                 (x.acquisition_type == AcquisitionType::DIAPASEF) && (x.ms_level == MSLevel::MS2)
             })
             .map(|x| match x {
-                Ok(x) => Ok(PyFrame::from(x)),
+                Ok(x) => Ok(self.to_py_frame(x)),
                 Err(_) => Err(PyIOError::new_err("Could not read frame, Corrupt frame")),
             })
             .collect()
@@ -68,12 +198,359 @@ impl PyFrameReader {
         self.reader
             .parallel_filter(|x| x.ms_level == MSLevel::MS1)
             .map(|x| match x {
-                Ok(x) => Ok(PyFrame::from(x)),
+                Ok(x) => Ok(self.to_py_frame(x)),
                 Err(_) => Err(PyIOError::new_err("Could not read frame, Corrupt frame")),
             })
             .collect()
     }
 
+    /// All prm-PASEF MS2 frames (empty for other acquisitions).
+    pub fn read_prm_frames(&self) -> PyResult<Vec<PyFrame>> {
+        let Some(prm) = &self.prm else {
+            return Ok(vec![]);
+        };
+        self.reader
+            .parallel_filter(|x| !prm.frame_isolations(x.index).is_empty())
+            .map(|x| match x {
+                Ok(x) => Ok(self.to_py_frame(x)),
+                Err(_) => Err(PyIOError::new_err("Could not read frame, Corrupt frame")),
+            })
+            .collect()
+    }
+
+    /// The PrmTargets table, with the isolation settings used for each target.
+    pub fn read_prm_targets(&self) -> PyResult<Vec<PyPrmTarget>> {
+        let prm = self.prm_layout()?;
+        let metadata = self.read_metadata()?;
+        Ok(prm.py_targets(&metadata.im_converter))
+    }
+
+    /// Fragment ion chromatograms of a prm-PASEF target, summing all peaks
+    /// within `tolerance_ppm` of each fragment m/z in the isolated scans.
+    #[pyo3(signature = (target, fragment_mzs, tolerance_ppm=20.0))]
+    pub fn extract_prm_transitions(
+        &self,
+        target: usize,
+        fragment_mzs: Vec<f64>,
+        tolerance_ppm: f64,
+    ) -> PyResult<PyPrmTransitions> {
+        let prm = self.prm_layout()?;
+        if !prm.isolations.iter().any(|x| x.target == target) {
+            return Err(PyValueError::new_err(format!(
+                "Target {} was not isolated in this run",
+                target
+            )));
+        }
+        let metadata = self.read_metadata()?;
+        prm.extract_transitions(
+            &self.reader,
+            &metadata.mz_converter,
+            target,
+            &fragment_mzs,
+            tolerance_ppm,
+        )
+        .map_err(|_| PyIOError::new_err("Could not read frame, Corrupt frame"))
+    }
+
+    /// Fragment and precursor chromatograms of diaPASEF `targets`, one
+    /// `DiaTraces` per target in the same order. Fragments are extracted
+    /// from the scans of the quadrupole windows that isolated the precursor
+    /// within the target's mobility window, precursors from the MS1 frames.
+    /// All targets share a single pass over the frames of their rt windows.
+    /// Raises a ValueError for runs that are not diaPASEF.
+    pub fn extract_dia_targets(
+        &self,
+        py: Python<'_>,
+        targets: Vec<PyDiaTarget>,
+    ) -> PyResult<Vec<PyDiaTraces>> {
+        if self.reader.get_acquisition() != AcquisitionType::DIAPASEF {
+            return Err(PyValueError::new_err("Not a diaPASEF run"));
+        }
+        let metadata = self.read_metadata()?;
+        py.allow_threads(|| {
+            extract_dia_targets(
+                &self.reader,
+                &metadata.mz_converter,
+                &metadata.im_converter,
+                &targets,
+            )
+        })
+        .map_err(|_| PyIOError::new_err("Could not read frame, Corrupt frame"))
+    }
+
+    /// Centroided peaks of all frames (or only those of `ms_level`), see
+    /// `Frame.pick_peaks`.
+    #[pyo3(signature = (ms_level=None, min_intensity=0.0, tof_tolerance=3, scan_tolerance=5))]
+    pub fn pick_peaks(
+        &self,
+        py: Python<'_>,
+        ms_level: Option<PyMSLevel>,
+        min_intensity: f64,
+        tof_tolerance: u32,
+        scan_tolerance: usize,
+    ) -> PyResult<Vec<PyPickedPeaks>> {
+        let metadata = self.read_metadata()?;
+        let params = PeakPickingParams {
+            min_intensity,
+            tof_tolerance,
+            scan_tolerance,
+        };
+        py.allow_threads(|| self.picked_peaks(ms_level, &metadata, &params))
+    }
+
+    /// MS1 features (isotope envelopes traced over frames and mobility),
+    /// sorted by m/z. Peaks are picked per frame as in `pick_peaks`, then
+    /// linked across frames within `mz_tolerance_ppm` and `im_tolerance`,
+    /// allowing `max_gap` missing frames. `with_traces` adds the XIC and the
+    /// apex frame mobilogram of every feature.
+    #[pyo3(signature = (
+        mz_tolerance_ppm=10.0,
+        im_tolerance=0.02,
+        max_gap=1,
+        min_frames=3,
+        max_charge=4,
+        min_isotopes=2,
+        min_intensity=0.0,
+        tof_tolerance=3,
+        scan_tolerance=5,
+        with_traces=false,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn find_features(
+        &self,
+        py: Python<'_>,
+        mz_tolerance_ppm: f64,
+        im_tolerance: f64,
+        max_gap: usize,
+        min_frames: usize,
+        max_charge: usize,
+        min_isotopes: usize,
+        min_intensity: f64,
+        tof_tolerance: u32,
+        scan_tolerance: usize,
+        with_traces: bool,
+    ) -> PyResult<Vec<PyFeature>> {
+        let metadata = self.read_metadata()?;
+        let params = FeatureFinderParams {
+            peak_picking: PeakPickingParams {
+                min_intensity,
+                tof_tolerance,
+                scan_tolerance,
+            },
+            mz_tolerance_ppm,
+            im_tolerance,
+            max_gap,
+            min_frames,
+            max_charge,
+            min_isotopes,
+            with_traces,
+        };
+        py.allow_threads(|| {
+            let frames =
+                self.picked_peaks(Some(PyMSLevel::MS1), &metadata, &params.peak_picking)?;
+            let mut features = find_features(&frames, &params);
+            if with_traces {
+                add_mobilograms(
+                    &mut features,
+                    &self.reader,
+                    &metadata.mz_converter,
+                    &metadata.im_converter,
+                    &params,
+                )
+                .map_err(|_| PyIOError::new_err("Could not read frame, Corrupt frame"))?;
+            }
+            Ok(features)
+        })
+    }
+
+    /// Isotope envelope of a precursor in the MS1 frame closest to its rt,
+    /// within `im_tolerance` of its 1/K0. None when no isotope is found
+    /// within `mz_tolerance_ppm` of its m/z.
+    #[pyo3(signature = (precursor, mz_tolerance_ppm=10.0, im_tolerance=0.05, max_charge=4))]
+    pub fn detect_isotope_envelope(
+        &self,
+        precursor: PyPrecursor,
+        mz_tolerance_ppm: f64,
+        im_tolerance: f64,
+        max_charge: usize,
+    ) -> PyResult<Option<PyIsotopeEnvelope>> {
+        let params = EnvelopeParams {
+            mz_tolerance_ppm,
+            im_tolerance,
+            max_charge,
+        };
+        Ok(self.envelopes(&[precursor], &params)?.remove(0))
+    }
+
+    /// Copies of `precursors` with missing charges (and monoisotopic m/z)
+    /// and intensities filled in from their isotope envelopes, see
+    /// `detect_isotope_envelope`. With `overwrite` all precursors with an
+    /// envelope are updated.
+    #[pyo3(signature = (precursors, overwrite=false, mz_tolerance_ppm=10.0, im_tolerance=0.05, max_charge=4))]
+    pub fn annotate_precursors(
+        &self,
+        precursors: Vec<PyPrecursor>,
+        overwrite: bool,
+        mz_tolerance_ppm: f64,
+        im_tolerance: f64,
+        max_charge: usize,
+    ) -> PyResult<Vec<PyPrecursor>> {
+        let params = EnvelopeParams {
+            mz_tolerance_ppm,
+            im_tolerance,
+            max_charge,
+        };
+        let envelopes = self.envelopes(&precursors, &params)?;
+        Ok(precursors
+            .iter()
+            .zip(envelopes.iter())
+            .map(|(precursor, envelope)| {
+                annotate_precursor(precursor, envelope.as_ref(), overwrite)
+            })
+            .collect())
+    }
+
+    /// As `annotate_precursors`, for the precursors of spectra.
+    #[pyo3(signature = (spectra, overwrite=false, mz_tolerance_ppm=10.0, im_tolerance=0.05, max_charge=4))]
+    pub fn annotate_spectra(
+        &self,
+        mut spectra: Vec<PySpectrum>,
+        overwrite: bool,
+        mz_tolerance_ppm: f64,
+        im_tolerance: f64,
+        max_charge: usize,
+    ) -> PyResult<Vec<PySpectrum>> {
+        let params = EnvelopeParams {
+            mz_tolerance_ppm,
+            im_tolerance,
+            max_charge,
+        };
+        let precursors: Vec<PyPrecursor> =
+            spectra.iter().filter_map(|x| x.precursor.clone()).collect();
+        let mut envelopes = self.envelopes(&precursors, &params)?.into_iter();
+        for spectrum in spectra.iter_mut() {
+            if let Some(precursor) = &spectrum.precursor {
+                let envelope = envelopes.next().flatten();
+                spectrum.precursor =
+                    Some(annotate_precursor(precursor, envelope.as_ref(), overwrite));
+            }
+        }
+        Ok(spectra)
+    }
+
+    /// Spot and laser settings of every MALDI frame, in frame order.
+    pub fn read_maldi_info(&self) -> PyResult<Vec<PyMaldiFrameInfo>> {
+        let maldi = self.maldi_layout()?;
+        Ok(maldi.spots.iter().map(PyMaldiFrameInfo::from).collect())
+    }
+
+    /// Ion image of a MALDI run: the summed intensity within `tolerance_ppm`
+    /// of `mz` (and within `im_range=(lower, upper)` 1/K0 if given) per spot.
+    #[pyo3(signature = (mz, tolerance_ppm=10.0, im_range=None))]
+    pub fn ion_image(
+        &self,
+        mz: f64,
+        tolerance_ppm: f64,
+        im_range: Option<(f64, f64)>,
+    ) -> PyResult<PyIonImage> {
+        let maldi = self.maldi_layout()?;
+        let metadata = self.read_metadata()?;
+        maldi
+            .ion_image(
+                &self.reader,
+                &metadata.mz_converter,
+                &metadata.im_converter,
+                mz,
+                tolerance_ppm,
+                im_range,
+            )
+            .map_err(|_| PyIOError::new_err("Could not read frame, Corrupt frame"))
+    }
+
+    /// Frames within `rt_range=(start, end)` seconds (all frames by
+    /// default), optionally only those of `ms_level`, on a (frame,
+    /// mobility, m/z) grid. Columns are `bin_width` m/z bins over `mz_range`,
+    /// rows are scans or, with `im_bin_width`, 1/K0 bins over `im_range`.
+    /// Both ranges default to the acquisition range.
+    #[pyo3(signature = (rt_range=None, ms_level=None, bin_width=1.0, mz_range=None, im_bin_width=None, im_range=None))]
+    pub fn to_grid(
+        &self,
+        rt_range: Option<(f64, f64)>,
+        ms_level: Option<PyMSLevel>,
+        bin_width: f64,
+        mz_range: Option<(f64, f64)>,
+        im_bin_width: Option<f64>,
+        im_range: Option<(f64, f64)>,
+    ) -> PyResult<PyFrameGrid> {
+        let metadata = self.read_metadata()?;
+        let frames = self
+            .reader
+            .parallel_filter(|x| {
+                rt_range.is_none_or(|(start, end)| (start..=end).contains(&x.rt))
+                    && ms_level.is_none_or(|level| self.ms_level(x) == level)
+            })
+            .map(|x| match x {
+                Ok(x) => Ok(self.to_py_frame(x)),
+                Err(_) => Err(PyIOError::new_err("Could not read frame, Corrupt frame")),
+            })
+            .collect::<PyResult<Vec<PyFrame>>>()?;
+        let num_scans = frames
+            .iter()
+            .map(|x| x.scan_offsets.len().saturating_sub(1))
+            .max()
+            .unwrap_or(0);
+        let params = GridParams::new(
+            bin_width,
+            mz_range.unwrap_or((metadata.lower_mz, metadata.upper_mz)),
+            im_bin_width,
+            im_range.unwrap_or((metadata.lower_im, metadata.upper_im)),
+            num_scans,
+        )?;
+        Ok(PyFrameGrid::from_frames(
+            &frames,
+            &metadata.mz_converter,
+            &metadata.im_converter,
+            &params,
+        ))
+    }
+
+    /// Read-only SQL query on the analysis.tdf of this run, see `query_tdf`.
+    #[pyo3(signature = (sql, params=None))]
+    pub fn query(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        params: Option<&Bound<'_, PyList>>,
+    ) -> PyResult<PyArrowTable> {
+        query_to_arrow(py, &self.sql, sql, params)
+    }
+
+    /// Per-frame columns of the Frames table as a dict of lists, without
+    /// decompressing any frame.
+    pub fn read_frame_metadata<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        read_frame_metadata(self.reader.get_path())?.into_dict(py)
+    }
+
+    /// All frames as an Arrow table, one row per frame.
+    pub fn to_arrow(&self) -> PyResult<PyArrowTable> {
+        self.all_frames_table()?.into_arrow()
+    }
+
+    /// All frames as a polars DataFrame, one row per frame.
+    pub fn to_polars<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.all_frames_table()?.into_polars(py)
+    }
+
+    /// All frames as a pandas DataFrame, one row per frame.
+    pub fn to_pandas<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.all_frames_table()?.into_pandas(py)
+    }
+
+    /// Iterates over acquisition cycles: each MS1 frame with the MS2 frames that follow it.
+    pub fn iter_cycles(slf: &Bound<'_, Self>) -> PyResult<PyCycleIterator> {
+        PyCycleIterator::new(slf)
+    }
+
     pub fn __len__(&self) -> usize {
         self.reader.len()
     }
@@ -88,7 +565,7 @@ impl PyFrameReader {
             let x = slf.reader.get(slf.i);
             slf.i += 1;
             match x {
-                Ok(x) => Ok(Some(PyFrame::from(x))),
+                Ok(x) => Ok(Some(slf.to_py_frame(x))),
                 Err(_) => Err(PyIOError::new_err(
                     "Could not read spectrum, Corrupt spectrum",
                 )),
@@ -101,29 +578,329 @@ impl PyFrameReader {
 
 #[pyclass(name = "SpectrumReader")]
 pub struct PySpectrumReader {
-    pub reader: Arc<SpectrumReader>,
+    /// None for prm-PASEF runs, whose spectra are built from the source reader.
+    pub reader: Option<Arc<SpectrumReader>>,
+    /// Only set for .d folders, where spectra can be traced back to frames.
+    pub source_reader: Option<Arc<SpectrumSourceReader>>,
+    pub with_mobility: bool,
+    /// Only set when MS1 spectra are interleaved with the MS2 ones.
+    pub entries: Option<Vec<SpectrumEntry>>,
+    processing_params: SpectrumProcessingParams,
+    /// Applied in order to MS2 spectra only.
+    processing: Vec<ProcessingStep>,
     i: usize, // Using here so I can implement __iter__  and __next__
 }
 
+/// Errors of the spectrum reader, kept free of python so the converter
+/// binary can share the reading code.
+#[derive(Debug)]
+pub enum SpectrumReadError {
+    Open(String),
+    InvalidConfig(&'static str),
+    Source(SpectrumSourceError),
+    CorruptSpectrum(usize),
+    OutOfRange(usize),
+}
+
+impl Display for SpectrumReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpectrumReadError::Open(e) => write!(f, "{}", e),
+            SpectrumReadError::InvalidConfig(e) => write!(f, "{}", e),
+            SpectrumReadError::Source(e) => write!(f, "{}", e),
+            SpectrumReadError::CorruptSpectrum(index) => {
+                write!(f, "Could not read spectrum {}, Corrupt spectrum", index)
+            }
+            SpectrumReadError::OutOfRange(index) => {
+                write!(f, "Spectrum index {} out of range", index)
+            }
+        }
+    }
+}
+
+impl From<SpectrumSourceError> for SpectrumReadError {
+    fn from(e: SpectrumSourceError) -> Self {
+        SpectrumReadError::Source(e)
+    }
+}
+
+impl From<SpectrumReadError> for PyErr {
+    fn from(e: SpectrumReadError) -> Self {
+        match e {
+            SpectrumReadError::InvalidConfig(_) => PyValueError::new_err(e.to_string()),
+            SpectrumReadError::OutOfRange(_) => PyIndexError::new_err(e.to_string()),
+            _ => PyIOError::new_err(e.to_string()),
+        }
+    }
+}
+
+impl PySpectrumReader {
+    /// The spectra are dropped before the table is handed to Arrow.
+    fn all_spectra_table(&self) -> PyResult<ColumnTable> {
+        let spectra = self.read_all_spectra()?;
+        Ok(spectra_table(&spectra.iter().collect::<Vec<_>>()))
+    }
+
+    pub fn from_config(
+        path: &str,
+        config: SpectrumReaderConfig,
+        with_mobility: bool,
+        include_ms1: bool,
+        ms1_mobility_span_step: Option<(f64, f64)>,
+    ) -> Result<Self, SpectrumReadError> {
+        let is_tdf = Path::new(path).extension().and_then(|e| e.to_str()) == Some("d");
+        let is_prm = is_tdf && is_prm_run(path);
+        let reader = if is_prm {
+            None
+        } else {
+            let reader = SpectrumReader::build()
+                .with_path(path)
+                .with_config(config)
+                .finalize()
+                .map_err(|e| SpectrumReadError::Open(e.to_string()))?;
+            Some(Arc::new(reader))
+        };
+        if (with_mobility || include_ms1) && !is_tdf {
+            return Err(SpectrumReadError::InvalidConfig(
+                "Resolving mobility and MS1 spectra require a bruker .d folder",
+            ));
+        }
+        if let Some((span, step)) = ms1_mobility_span_step {
+            // The mobility slices would never advance otherwise
+            let valid = |x: f64| x.is_finite() && x > 0.0;
+            if !valid(span) || !valid(step) {
+                return Err(SpectrumReadError::InvalidConfig(
+                    "The MS1 mobility span and step need to be finite and above 0",
+                ));
+            }
+        }
+        // Peaks are matched back to tof indices with the uncalibrated converter
+        if with_mobility && config.spectrum_processing_params.calibrate {
+            return Err(SpectrumReadError::InvalidConfig(
+                "Resolving mobility is not supported for calibrated spectra",
+            ));
+        }
+        let source_reader = match (is_tdf, config.frame_splitting_params) {
+            // Isolations are not split, so any strategy works
+            (true, _) if is_prm => Some(Arc::new(SpectrumSourceReader::new(
+                path,
+                &QuadWindowExpansionStrategy::None,
+            )?)),
+            (true, FrameWindowSplittingConfiguration::Quadrupole(strategy)) => {
+                Some(Arc::new(SpectrumSourceReader::new(path, &strategy)?))
+            }
+            (true, _) if with_mobility || include_ms1 => {
+                return Err(SpectrumReadError::InvalidConfig(
+                    "Resolving mobility and MS1 spectra require a quadrupole splitting strategy",
+                ))
+            }
+            _ => None,
+        };
+        let entries = match (&source_reader, include_ms1) {
+            (Some(source_reader), true) => {
+                let ms1_sources = ms1_spectrum_sources(
+                    &source_reader.frames,
+                    ms1_mobility_span_step,
+                    &source_reader.im_converter,
+                );
+                Some(interleave_spectra(&source_reader.sources, ms1_sources))
+            }
+            _ => None,
+        };
+        Ok(PySpectrumReader {
+            reader,
+            source_reader,
+            with_mobility,
+            entries,
+            processing_params: config.spectrum_processing_params,
+            processing: vec![],
+            i: 0,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        match (&self.entries, &self.reader, &self.source_reader) {
+            (Some(entries), _, _) => entries.len(),
+            (None, Some(reader), _) => reader.len(),
+            (None, None, Some(source_reader)) => source_reader.sources.len(),
+            (None, None, None) => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get_spectrum(&self, index: usize) -> Result<PySpectrum, SpectrumReadError> {
+        let Some(entries) = &self.entries else {
+            return self.get_processed_ms2_spectrum(index);
+        };
+        match entries.get(index) {
+            Some(SpectrumEntry::Ms2(ms2_index)) => {
+                let mut spectrum = self.get_processed_ms2_spectrum(*ms2_index)?;
+                spectrum.index = index;
+                Ok(spectrum)
+            }
+            Some(SpectrumEntry::Ms1(source)) => self.get_ms1_spectrum(index, source),
+            None => Err(SpectrumReadError::OutOfRange(index)),
+        }
+    }
+
+    /// Reads a range of spectra in parallel.
+    pub fn read_spectra(
+        &self,
+        indices: Range<usize>,
+    ) -> Result<Vec<PySpectrum>, SpectrumReadError> {
+        let mut spectra: Vec<PySpectrum> = indices
+            .into_par_iter()
+            .map(|index| self.get_spectrum(index))
+            .collect::<Result<Vec<PySpectrum>, SpectrumReadError>>()?;
+        // Same ordering as `timsrust::readers::SpectrumReader::get_all`,
+        // interleaved and prm-PASEF spectra are already in acquisition order.
+        if self.entries.is_none() && self.reader.is_some() {
+            spectra.sort_by_key(|x| match &x.precursor {
+                Some(precursor) => precursor.index,
+                None => x.index,
+            });
+        }
+        Ok(spectra)
+    }
+
+    fn get_processed_ms2_spectrum(&self, index: usize) -> Result<PySpectrum, SpectrumReadError> {
+        let mut spectrum = self.get_ms2_spectrum(index)?;
+        process_spectrum(&mut spectrum, &self.processing);
+        Ok(spectrum)
+    }
+
+    fn get_ms2_spectrum(&self, index: usize) -> Result<PySpectrum, SpectrumReadError> {
+        let Some(reader) = &self.reader else {
+            return self.get_prm_spectrum(index);
+        };
+        let spectrum = reader
+            .get(index)
+            .map_err(|_| SpectrumReadError::CorruptSpectrum(index))?;
+        let mut spectrum = PySpectrum::from(spectrum);
+        let Some(source_reader) = &self.source_reader else {
+            return Ok(spectrum);
+        };
+        if let Some(source) = source_reader.sources.get(index) {
+            self.add_source_fields(source_reader, source, &mut spectrum)?;
+        }
+        Ok(spectrum)
+    }
+
+    /// One spectrum per isolation of a prm-PASEF frame, with its target as precursor.
+    fn get_prm_spectrum(&self, index: usize) -> Result<PySpectrum, SpectrumReadError> {
+        let Some(source_reader) = &self.source_reader else {
+            return Err(SpectrumReadError::OutOfRange(index));
+        };
+        let (Some(prm), Some(source)) = (&source_reader.prm, source_reader.sources.get(index))
+        else {
+            return Err(SpectrumReadError::OutOfRange(index));
+        };
+        let isolation = &prm.isolations[index];
+        let (mz_values, intensities) = source_reader.build_spectrum(
+            source,
+            self.processing_params.smoothing_window,
+            self.processing_params.centroiding_window,
+        )?;
+        let mut spectrum = PySpectrum::from(Spectrum {
+            mz_values,
+            intensities,
+            precursor: Some(prm.precursor(isolation)),
+            index,
+            collision_energy: isolation.collision_energy,
+            isolation_mz: isolation.isolation_mz,
+            isolation_width: isolation.isolation_width,
+        });
+        spectrum.prm_target = Some(isolation.target);
+        self.add_source_fields(source_reader, source, &mut spectrum)?;
+        Ok(spectrum)
+    }
+
+    fn get_ms1_spectrum(
+        &self,
+        index: usize,
+        source: &SpectrumSource,
+    ) -> Result<PySpectrum, SpectrumReadError> {
+        let Some(source_reader) = &self.source_reader else {
+            return Err(SpectrumReadError::InvalidConfig(
+                "MS1 spectra require a bruker .d folder",
+            ));
+        };
+        let (mz_values, intensities) = source_reader.build_spectrum(
+            source,
+            self.processing_params.smoothing_window,
+            self.processing_params.centroiding_window,
+        )?;
+        let mut spectrum = PySpectrum::from(Spectrum {
+            mz_values,
+            intensities,
+            index,
+            ..Default::default()
+        });
+        spectrum.ms_level = PyMSLevel::MS1;
+        self.add_source_fields(source_reader, source, &mut spectrum)?;
+        Ok(spectrum)
+    }
+
+    fn add_source_fields(
+        &self,
+        source_reader: &SpectrumSourceReader,
+        source: &SpectrumSource,
+        spectrum: &mut PySpectrum,
+    ) -> Result<(), SpectrumReadError> {
+        if let Some(info) = source_reader.source_info(source) {
+            // Otherwise keep the precursor rt
+            if let Some(rt) = info.rt {
+                spectrum.rt = rt;
+            }
+            spectrum.frame_indices = info.frame_indices;
+            spectrum.lower_im = info.lower_im;
+            spectrum.upper_im = info.upper_im;
+        }
+        if self.with_mobility {
+            let (mobility_values, scan_indices) =
+                source_reader.resolve_mobility(source, &spectrum.mz_values)?;
+            spectrum.mobility_values = Some(mobility_values);
+            spectrum.scan_indices = Some(scan_indices);
+        }
+        Ok(())
+    }
+}
+
 #[pymethods]
 impl PySpectrumReader {
+    /// When `include_ms1` is set, MS1 spectra (one per MS1 frame, or one per
+    /// `ms1_mobility_span_step=(span, step)` slice in 1/K0) are interleaved
+    /// with the MS2 spectra in acquisition order, and spectrum indices refer
+    /// to that combined order.
     #[new]
-    pub fn new(path: &str) -> PyResult<Self> {
-        match SpectrumReader::build().with_path(path).finalize() {
-            Ok(x) => Ok(PySpectrumReader {
-                reader: Arc::new(x),
-                i: 0,
-            }),
-            Err(e) => Err(PyIOError::new_err(e.to_string())),
-        }
+    #[pyo3(signature = (path, with_mobility=false, include_ms1=false, ms1_mobility_span_step=None))]
+    pub fn new(
+        path: &str,
+        with_mobility: bool,
+        include_ms1: bool,
+        ms1_mobility_span_step: Option<(f64, f64)>,
+    ) -> PyResult<Self> {
+        Ok(Self::from_config(
+            path,
+            SpectrumReaderConfig::default(),
+            with_mobility,
+            include_ms1,
+            ms1_mobility_span_step,
+        )?)
     }
 
     #[staticmethod]
+    #[pyo3(signature = (path, mobility_span, mobility_step, with_mobility=false, include_ms1=false, ms1_mobility_span_step=None))]
     fn new_with_span_step(
-        py: Python<'_>,
         path: &str,
         mobility_span: f64,
         mobility_step: f64,
+        with_mobility: bool,
+        include_ms1: bool,
+        ms1_mobility_span_step: Option<(f64, f64)>,
     ) -> PyResult<Self> {
         let params = SpectrumReaderConfig {
             frame_splitting_params: FrameWindowSplittingConfiguration::Quadrupole(
@@ -131,31 +908,190 @@ impl PySpectrumReader {
             ),
             spectrum_processing_params: SpectrumProcessingParams::default(),
         };
+        Ok(Self::from_config(
+            path,
+            params,
+            with_mobility,
+            include_ms1,
+            ms1_mobility_span_step,
+        )?)
+    }
+
+    // The `with_*` methods below add a step to the processing pipeline of
+    // MS2 spectra, applied in the order they were called.
 
-        let builder = SpectrumReader::build()
-            .with_path(path)
-            .with_config(params)
-            .finalize();
-        match builder {
-            Ok(x) => Ok(PySpectrumReader {
-                reader: Arc::new(x),
-                i: 0,
-            }),
-            Err(e) => Err(PyIOError::new_err(e.to_string())),
+    /// Deisotopes every MS2 spectrum, see `Spectrum.deisotope`.
+    #[pyo3(signature = (tolerance_ppm=10.0, max_charge=3, mass_mode=None))]
+    pub fn with_deisotoping<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        tolerance_ppm: f64,
+        max_charge: usize,
+        mass_mode: Option<&str>,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        slf.processing
+            .push(ProcessingStep::Deisotope(DeisotopingParams {
+                tolerance_ppm,
+                max_charge,
+                mass_mode: MassMode::parse(mass_mode)?,
+            }));
+        Ok(slf)
+    }
+
+    /// See `Spectrum.filter_intensity`.
+    #[pyo3(signature = (min_intensity=0.0, min_relative_intensity=0.0))]
+    pub fn with_intensity_filter(
+        mut slf: PyRefMut<'_, Self>,
+        min_intensity: f64,
+        min_relative_intensity: f64,
+    ) -> PyRefMut<'_, Self> {
+        slf.processing.push(ProcessingStep::IntensityFilter {
+            min_intensity,
+            min_relative_intensity,
+        });
+        slf
+    }
+
+    /// See `Spectrum.top_n`.
+    #[pyo3(signature = (n, mz_window=None))]
+    pub fn with_top_n<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        n: usize,
+        mz_window: Option<f64>,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        if mz_window.is_some_and(|x| x <= 0.0) {
+            return Err(PyValueError::new_err("mz_window must be positive"));
         }
+        slf.processing.push(ProcessingStep::TopN { n, mz_window });
+        Ok(slf)
+    }
+
+    /// See `Spectrum.remove_precursor`.
+    #[pyo3(signature = (tolerance_ppm=20.0, isotopes=0))]
+    pub fn with_precursor_removal(
+        mut slf: PyRefMut<'_, Self>,
+        tolerance_ppm: f64,
+        isotopes: usize,
+    ) -> PyRefMut<'_, Self> {
+        slf.processing.push(ProcessingStep::PrecursorRemoval {
+            tolerance_ppm,
+            isotopes,
+        });
+        slf
+    }
+
+    /// See `Spectrum.crop_mz`.
+    pub fn with_mz_range(
+        mut slf: PyRefMut<'_, Self>,
+        lower: f64,
+        upper: f64,
+    ) -> PyRefMut<'_, Self> {
+        slf.processing
+            .push(ProcessingStep::MzRange { lower, upper });
+        slf
+    }
+
+    /// See `Spectrum.normalize`.
+    pub fn with_normalization<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        method: &str,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        slf.processing
+            .push(ProcessingStep::Normalize(Normalization::parse(method)?));
+        Ok(slf)
     }
 
     pub fn __len__(&self) -> usize {
-        self.reader.len()
+        self.len()
+    }
+
+    pub fn read_all_spectra(&self) -> PyResult<Vec<PySpectrum>> {
+        Ok(self.read_spectra(0..self.len())?)
+    }
+
+    /// All spectra as an Arrow table, one row per spectrum.
+    pub fn to_arrow(&self) -> PyResult<PyArrowTable> {
+        self.all_spectra_table()?.into_arrow()
+    }
+
+    /// All spectra as a polars DataFrame, one row per spectrum.
+    pub fn to_polars<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.all_spectra_table()?.into_polars(py)
+    }
+
+    /// All spectra as a pandas DataFrame, one row per spectrum.
+    pub fn to_pandas<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.all_spectra_table()?.into_pandas(py)
     }
 
     pub fn get(&self, index: usize) -> PyResult<PySpectrum> {
-        match self.reader.get(index) {
-            Ok(x) => Ok(PySpectrum::from(x)),
-            Err(_) => Err(PyIOError::new_err(
-                "Could not read spectrum, Corrupt spectrum",
-            )),
+        Ok(self.get_spectrum(index)?)
+    }
+
+    /// Library hits of all spectra, after processing, see
+    /// `SpectralLibrary.search`.
+    #[pyo3(signature = (
+        library,
+        precursor_tolerance=20.0,
+        precursor_unit="ppm",
+        fragment_tolerance=20.0,
+        fragment_unit="ppm",
+        metric="cosine",
+        top_n=5,
+        min_matched_peaks=1
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn search_library(
+        &self,
+        py: Python<'_>,
+        library: &PySpectralLibrary,
+        precursor_tolerance: f64,
+        precursor_unit: &str,
+        fragment_tolerance: f64,
+        fragment_unit: &str,
+        metric: &str,
+        top_n: usize,
+        min_matched_peaks: usize,
+    ) -> PyResult<Vec<Vec<PyLibraryHit>>> {
+        let params = search_params(
+            precursor_tolerance,
+            precursor_unit,
+            fragment_tolerance,
+            fragment_unit,
+            metric,
+            top_n,
+            min_matched_peaks,
+        )?;
+        let spectra = self.read_all_spectra()?;
+        Ok(py.allow_threads(|| library.search_spectra(&spectra, &params)))
+    }
+
+    /// All spectra, after processing, in fixed width m/z bins from `min_mz`
+    /// to `max_mz`, as a sparse matrix with one row per spectrum.
+    #[pyo3(signature = (bin_width=1.0, min_mz=0.0, max_mz=2000.0))]
+    pub fn to_binned(&self, bin_width: f64, min_mz: f64, max_mz: f64) -> PyResult<PyBinnedSpectra> {
+        let params = BinningParams::new(bin_width, min_mz, max_mz)?;
+        let spectra = self.read_all_spectra()?;
+        Ok(PyBinnedSpectra::from_spectra(&spectra, &params))
+    }
+
+    /// Same as `to_binned`, but yields `batch_size` spectra at a time.
+    #[pyo3(signature = (batch_size=1024, bin_width=1.0, min_mz=0.0, max_mz=2000.0))]
+    pub fn iter_binned(
+        slf: &Bound<'_, Self>,
+        batch_size: usize,
+        bin_width: f64,
+        min_mz: f64,
+        max_mz: f64,
+    ) -> PyResult<PyBinnedSpectraIterator> {
+        if batch_size == 0 {
+            return Err(PyValueError::new_err("batch_size must be positive"));
         }
+        Ok(PyBinnedSpectraIterator {
+            reader: slf.clone().unbind(),
+            params: BinningParams::new(bin_width, min_mz, max_mz)?,
+            batch_size,
+            i: 0,
+        })
     }
 
     pub fn __iter__(mut slf: PyRefMut<'_, Self>) -> PyRefMut<'_, Self> {
@@ -164,15 +1100,10 @@ impl PySpectrumReader {
     }
 
     pub fn __next__(mut slf: PyRefMut<'_, Self>) -> PyResult<Option<PySpectrum>> {
-        if slf.i < slf.reader.len() {
-            let x = slf.reader.get(slf.i);
+        if slf.i < slf.len() {
+            let index = slf.i;
             slf.i += 1;
-            match x {
-                Ok(x) => Ok(Some(PySpectrum::from(x))),
-                Err(_) => Err(PyIOError::new_err(
-                    "Could not read spectrum, Corrupt spectrum",
-                )),
-            }
+            Ok(Some(slf.get_spectrum(index)?))
         } else {
             Ok(None)
         }
@@ -1,9 +1,20 @@
 use std::sync::Arc;
 
-use pyo3::exceptions::PyIOError;
-use pyo3::types::PyString;
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::types::{PyDict, PyList, PyString};
 use std::path::PathBuf;
 
+use crate::binning::{BinningParams, PyBinnedSpectra};
+use crate::ccs::{im_to_ccs, PyDriftGas};
+use crate::dataframes::{read_frame_metadata, PyArrowTable};
+use crate::fragments::{annotate, FragmentParams, Peptide, PyFragmentAnnotation};
+use crate::frame_grids::{GridParams, PyFrameGrid};
+use crate::global_metadata::PyGlobalMetadata;
+use crate::maldi::PyMaldiFrameInfo;
+use crate::peak_picking::{pick_peaks, PeakPickingParams, PyPickedPeaks};
+use crate::similarity::{one_vs_many, similarity, SimilarityMetric, Tolerance};
+use crate::spectrum_processing::{DeisotopingParams, MassMode, Normalization, ProcessingStep};
+use crate::tdf_sql::{query_to_arrow, SharedTdfSqlReader};
 use crate::timsrust_converters::{PyFrame2RtConverter, PyScan2ImConverter, PyTof2MzConverter};
 use crate::timsrust_enums::{PyAcquisitionType, PyMSLevel};
 use pyo3::prelude::*;
@@ -88,6 +99,7 @@ impl From<Arc<QuadrupoleSettings>> for PyQuadrupoleSettings {
     }
 }
 
+#[derive(Clone)]
 #[pyclass(name = "Frame")]
 pub struct PyFrame {
     #[pyo3(get)]
@@ -108,6 +120,14 @@ pub struct PyFrame {
     pub quadrupole_settings: PyQuadrupoleSettings,
     #[pyo3(get)]
     pub intensity_correction_factor: f64,
+    #[pyo3(get)]
+    pub window_group: u8,
+    /// Ids of the PrmTargets isolated in this frame, in scan order (prm-PASEF only).
+    #[pyo3(get)]
+    pub prm_targets: Vec<usize>,
+    /// Spot and laser settings (MALDI only).
+    #[pyo3(get)]
+    pub maldi_info: Option<PyMaldiFrameInfo>,
 }
 
 impl From<Frame> for PyFrame {
@@ -125,6 +145,9 @@ impl From<Frame> for PyFrame {
             ms_level,
             quadrupole_settings,
             intensity_correction_factor: frame.intensity_correction_factor,
+            window_group: frame.window_group,
+            prm_targets: vec![],
+            maldi_info: None,
         }
     }
 }
@@ -133,11 +156,12 @@ impl From<Frame> for PyFrame {
 impl PyFrame {
     pub fn __repr__(&self) -> String {
         let start_section = format!(
-            "index={}, rt={}, acquisition_type={}, ms_level={}, quadrupole_settings={}, intensity_correction_factor={}",
+            "index={}, rt={}, acquisition_type={}, ms_level={}, window_group={}, quadrupole_settings={}, intensity_correction_factor={}",
             self.index,
             self.rt,
             self.acquisition_type,
             self.ms_level,
+            self.window_group,
             self.quadrupole_settings,
             self.intensity_correction_factor,
         );
@@ -156,8 +180,57 @@ impl PyFrame {
             .map(|x| *x as f64 * self.intensity_correction_factor)
             .collect()
     }
+
+    /// This frame on a (1, mobility, m/z) grid, see `FrameReader.to_grid`.
+    #[pyo3(signature = (metadata, bin_width=1.0, mz_range=None, im_bin_width=None, im_range=None))]
+    pub fn to_grid(
+        &self,
+        metadata: &PyMetadata,
+        bin_width: f64,
+        mz_range: Option<(f64, f64)>,
+        im_bin_width: Option<f64>,
+        im_range: Option<(f64, f64)>,
+    ) -> PyResult<PyFrameGrid> {
+        let params = GridParams::new(
+            bin_width,
+            mz_range.unwrap_or((metadata.lower_mz, metadata.upper_mz)),
+            im_bin_width,
+            im_range.unwrap_or((metadata.lower_im, metadata.upper_im)),
+            self.scan_offsets.len().saturating_sub(1),
+        )?;
+        Ok(PyFrameGrid::from_frames(
+            std::slice::from_ref(self),
+            &metadata.mz_converter.converter,
+            &metadata.im_converter.converter,
+            &params,
+        ))
+    }
+
+    /// Centroids the raw events of this frame in the tof and scan dimension,
+    /// using the converters of `metadata` for m/z and 1/K0.
+    #[pyo3(signature = (metadata, min_intensity=0.0, tof_tolerance=3, scan_tolerance=5))]
+    pub fn pick_peaks(
+        &self,
+        metadata: &PyMetadata,
+        min_intensity: f64,
+        tof_tolerance: u32,
+        scan_tolerance: usize,
+    ) -> PyPickedPeaks {
+        let params = PeakPickingParams {
+            min_intensity,
+            tof_tolerance,
+            scan_tolerance,
+        };
+        pick_peaks(
+            self,
+            &metadata.mz_converter.converter,
+            &metadata.im_converter.converter,
+            &params,
+        )
+    }
 }
 
+#[derive(Clone)]
 #[pyclass(name = "Spectrum")]
 pub struct PySpectrum {
     #[pyo3(get)]
@@ -174,21 +247,57 @@ pub struct PySpectrum {
     pub isolation_mz: f64,
     #[pyo3(get)]
     pub isolation_width: f64,
+    #[pyo3(get)]
+    pub rt: f64,
+    #[pyo3(get)]
+    pub ms_level: PyMSLevel,
+    /// Ids of the frames the spectrum was built from.
+    #[pyo3(get)]
+    pub frame_indices: Vec<usize>,
+    /// 1/K0 span the spectrum was extracted over.
+    #[pyo3(get)]
+    pub lower_im: f64,
+    #[pyo3(get)]
+    pub upper_im: f64,
+    /// Per peak 1/K0, only present when the reader resolves mobility.
+    #[pyo3(get)]
+    pub mobility_values: Option<Vec<f64>>,
+    /// Per peak scan index, only present when the reader resolves mobility.
+    #[pyo3(get)]
+    pub scan_indices: Option<Vec<usize>>,
+    /// Id of the PrmTargets entry (prm-PASEF only).
+    #[pyo3(get)]
+    pub prm_target: Option<usize>,
+    /// Per peak charge (0 when unknown), only present after deisotoping.
+    #[pyo3(get)]
+    pub charges: Option<Vec<usize>>,
 }
 
 impl From<Spectrum> for PySpectrum {
     fn from(spectrum: Spectrum) -> Self {
+        // Without access to the frames (e.g. minitdf) the precursor is the
+        // best estimate of where the spectrum was acquired.
+        let (rt, im) = match &spectrum.precursor {
+            Some(x) => (x.rt, x.im),
+            None => (f64::NAN, f64::NAN),
+        };
         PySpectrum {
             mz_values: spectrum.mz_values,
             intensities: spectrum.intensities,
-            precursor: match &spectrum.precursor {
-                Some(x) => Some(PyPrecursor::new(x)),
-                None => None,
-            },
+            precursor: spectrum.precursor.as_ref().map(PyPrecursor::new),
             index: spectrum.index,
             collision_energy: spectrum.collision_energy,
             isolation_mz: spectrum.isolation_mz,
             isolation_width: spectrum.isolation_width,
+            rt,
+            ms_level: PyMSLevel::MS2,
+            frame_indices: vec![],
+            lower_im: im,
+            upper_im: im,
+            mobility_values: None,
+            scan_indices: None,
+            prm_target: None,
+            charges: None,
         }
     }
 }
@@ -198,11 +307,20 @@ impl PySpectrum {
     pub fn __repr__(slf: &Bound<'_, Self>) -> PyResult<String> {
         let class_name: Bound<'_, PyString> = slf.get_type().qualname()?;
         Ok(format!(
-            "{}(\n index={},\n mz_values={},\n intensities={},\n precursor={},\n collision_energy={}, isolation_mz={}, isolation_width={})",
+            "{}(\n index={}, rt={}, ms_level={}, frame_indices={}, lower_im={}, upper_im={},\n mz_values={},\n intensities={},{}\n precursor={},\n collision_energy={}, isolation_mz={}, isolation_width={})",
             class_name,
             slf.borrow().index,
+            slf.borrow().rt,
+            slf.borrow().ms_level,
+            format_slice(&slf.borrow().frame_indices),
+            slf.borrow().lower_im,
+            slf.borrow().upper_im,
             format_slice(&slf.borrow().mz_values),
             format_slice(&slf.borrow().intensities),
+            match &slf.borrow().mobility_values {
+                Some(x) => format!("\n mobility_values={},", format_slice(x)),
+                None => "".to_string(),
+            },
             match &slf.borrow().precursor {
                 Some(x) => format!("{}", x),
                 None => "None".to_string(),
@@ -212,6 +330,151 @@ impl PySpectrum {
             slf.borrow().isolation_width,
         ))
     }
+
+    /// A copy of this spectrum with every isotope cluster collapsed to its
+    /// monoisotopic peak, and `charges` set per peak. `mass_mode` is 'mz'
+    /// (default), 'singly_charged' or 'neutral'.
+    #[pyo3(signature = (tolerance_ppm=10.0, max_charge=3, mass_mode=None))]
+    pub fn deisotope(
+        &self,
+        tolerance_ppm: f64,
+        max_charge: usize,
+        mass_mode: Option<&str>,
+    ) -> PyResult<PySpectrum> {
+        Ok(self.processed(ProcessingStep::Deisotope(DeisotopingParams {
+            tolerance_ppm,
+            max_charge,
+            mass_mode: MassMode::parse(mass_mode)?,
+        })))
+    }
+
+    /// A copy of this spectrum without the peaks below `min_intensity` or
+    /// below `min_relative_intensity` times the base peak intensity.
+    #[pyo3(signature = (min_intensity=0.0, min_relative_intensity=0.0))]
+    pub fn filter_intensity(&self, min_intensity: f64, min_relative_intensity: f64) -> PySpectrum {
+        self.processed(ProcessingStep::IntensityFilter {
+            min_intensity,
+            min_relative_intensity,
+        })
+    }
+
+    /// A copy of this spectrum with only its `n` most intense peaks, or the
+    /// `n` most intense ones per `mz_window` wide m/z window.
+    #[pyo3(signature = (n, mz_window=None))]
+    pub fn top_n(&self, n: usize, mz_window: Option<f64>) -> PyResult<PySpectrum> {
+        if mz_window.is_some_and(|x| x <= 0.0) {
+            return Err(PyValueError::new_err("mz_window must be positive"));
+        }
+        Ok(self.processed(ProcessingStep::TopN { n, mz_window }))
+    }
+
+    /// A copy of this spectrum without the peaks at the precursor m/z and its
+    /// first `isotopes` isotopes.
+    #[pyo3(signature = (tolerance_ppm=20.0, isotopes=0))]
+    pub fn remove_precursor(&self, tolerance_ppm: f64, isotopes: usize) -> PySpectrum {
+        self.processed(ProcessingStep::PrecursorRemoval {
+            tolerance_ppm,
+            isotopes,
+        })
+    }
+
+    /// A copy of this spectrum with only the peaks from `lower` to `upper` m/z.
+    pub fn crop_mz(&self, lower: f64, upper: f64) -> PySpectrum {
+        self.processed(ProcessingStep::MzRange { lower, upper })
+    }
+
+    /// Similarity to `other`, see `pairwise_similarity` for the metrics.
+    #[pyo3(signature = (other, metric="cosine", tolerance=20.0, unit="ppm"))]
+    pub fn similarity(
+        &self,
+        other: &PySpectrum,
+        metric: &str,
+        tolerance: f64,
+        unit: &str,
+    ) -> PyResult<f64> {
+        Ok(similarity(
+            self,
+            other,
+            SimilarityMetric::parse(metric)?,
+            &Tolerance::parse(tolerance, unit)?,
+        ))
+    }
+
+    /// Similarities to each of `others`, computed in parallel.
+    #[pyo3(signature = (others, metric="cosine", tolerance=20.0, unit="ppm"))]
+    pub fn similarities(
+        &self,
+        py: Python<'_>,
+        others: Vec<PySpectrum>,
+        metric: &str,
+        tolerance: f64,
+        unit: &str,
+    ) -> PyResult<Vec<f64>> {
+        let metric = SimilarityMetric::parse(metric)?;
+        let tolerance = Tolerance::parse(tolerance, unit)?;
+        Ok(py.allow_threads(|| one_vs_many(self, &others, metric, &tolerance)))
+    }
+
+    /// b/y fragment annotation of the peaks for `peptide`, see
+    /// `fragment_ions`. `charge` defaults to the precursor charge and
+    /// `max_fragment_charge` to one less than `charge`.
+    #[pyo3(signature = (
+        peptide,
+        charge=None,
+        tolerance=20.0,
+        unit="ppm",
+        max_fragment_charge=None,
+        a_ions=false,
+        internal=false,
+        neutral_losses=false
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn annotate(
+        &self,
+        peptide: &str,
+        charge: Option<usize>,
+        tolerance: f64,
+        unit: &str,
+        max_fragment_charge: Option<usize>,
+        a_ions: bool,
+        internal: bool,
+        neutral_losses: bool,
+    ) -> PyResult<PyFragmentAnnotation> {
+        let charge = charge
+            .or(self.precursor.as_ref().and_then(|x| x.charge))
+            .filter(|x| *x > 0)
+            .ok_or_else(|| PyValueError::new_err("The precursor charge is unknown"))?;
+        let params = FragmentParams {
+            a_ions,
+            internal,
+            neutral_losses,
+            max_charge: max_fragment_charge.unwrap_or(charge.saturating_sub(1).max(1)),
+        };
+        Ok(annotate(
+            self,
+            peptide,
+            &Peptide::parse(peptide)?,
+            charge,
+            &params,
+            &Tolerance::parse(tolerance, unit)?,
+        ))
+    }
+
+    /// This spectrum in fixed width m/z bins, see `SpectrumReader.to_binned`.
+    #[pyo3(signature = (bin_width=1.0, min_mz=0.0, max_mz=2000.0))]
+    pub fn to_binned(&self, bin_width: f64, min_mz: f64, max_mz: f64) -> PyResult<PyBinnedSpectra> {
+        let params = BinningParams::new(bin_width, min_mz, max_mz)?;
+        Ok(PyBinnedSpectra::from_spectra(
+            std::slice::from_ref(self),
+            &params,
+        ))
+    }
+
+    /// A copy of this spectrum with intensities scaled by `method`, one of
+    /// 'sqrt', 'log' (ln(1 + x)) or 'tic'.
+    pub fn normalize(&self, method: &str) -> PyResult<PySpectrum> {
+        Ok(self.processed(ProcessingStep::Normalize(Normalization::parse(method)?)))
+    }
 }
 
 #[pyclass(name = "Metadata")]
@@ -238,6 +501,8 @@ pub struct PyMetadata {
     pub lower_mz: f64,
     #[pyo3(get)]
     pub upper_mz: f64,
+    /// The analysis.tdf connection used for `query`, opened on first use.
+    pub sql: SharedTdfSqlReader,
 }
 
 impl From<&Metadata> for PyMetadata {
@@ -254,6 +519,7 @@ impl From<&Metadata> for PyMetadata {
             upper_im: x.upper_im,
             lower_mz: x.lower_mz,
             upper_mz: x.upper_mz,
+            sql: SharedTdfSqlReader::new(&x.path),
         }
     }
 }
@@ -270,6 +536,26 @@ impl PyMetadata {
         format!("Metadata(path='{}')", self.path.to_str().unwrap_or("None"))
     }
 
+    /// Read-only SQL query on this analysis.tdf, see `query_tdf`.
+    #[pyo3(signature = (sql, params=None))]
+    pub fn query(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        params: Option<&Bound<'_, PyList>>,
+    ) -> PyResult<PyArrowTable> {
+        query_to_arrow(py, &self.sql, sql, params)
+    }
+
+    pub fn read_global_metadata(&self) -> PyResult<PyGlobalMetadata> {
+        PyGlobalMetadata::new(self.path.clone())
+    }
+
+    /// Per-frame columns of the Frames table as a dict of lists.
+    pub fn read_frame_metadata<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        read_frame_metadata(&self.path)?.into_dict(py)
+    }
+
     fn resolve_mzs(&self, tofs: Vec<u32>) -> Vec<f64> {
         tofs.iter().map(|x| self.mz_converter.convert(*x)).collect()
     }
@@ -290,6 +576,50 @@ impl PyMetadata {
             .collect()
     }
 
+    #[pyo3(signature = (scans, mzs, charges, gas=PyDriftGas::N2, temperature=305.0))]
+    fn resolve_ccs(
+        &self,
+        scans: Vec<u32>,
+        mzs: Vec<f64>,
+        charges: Vec<usize>,
+        gas: PyDriftGas,
+        temperature: f64,
+    ) -> PyResult<Vec<f64>> {
+        let scans: Vec<f64> = scans.iter().map(|x| *x as f64).collect();
+        self.im_converter
+            .scans_to_ccs(scans, mzs, charges, gas, temperature)
+    }
+
+    #[pyo3(signature = (ccs, mzs, charges, gas=PyDriftGas::N2, temperature=305.0))]
+    fn invert_ccs(
+        &self,
+        ccs: Vec<f64>,
+        mzs: Vec<f64>,
+        charges: Vec<usize>,
+        gas: PyDriftGas,
+        temperature: f64,
+    ) -> PyResult<Vec<u32>> {
+        let scans = self
+            .im_converter
+            .ccs_to_scans(ccs, mzs, charges, gas, temperature)?;
+        // The converter spans scan 0 (upper_im) to the last scan (lower_im)
+        let max_scan = self.im_converter.invert(self.lower_im).round();
+        scans
+            .iter()
+            .map(|x| {
+                let scan = x.round();
+                if scan.is_finite() && (0.0..=max_scan).contains(&scan) {
+                    Ok(scan as u32)
+                } else {
+                    Err(PyValueError::new_err(format!(
+                        "Scan {} is outside of 0..={}",
+                        x, max_scan
+                    )))
+                }
+            })
+            .collect()
+    }
+
     fn resolve_frames(&self, rts: Vec<u32>) -> Vec<f64> {
         rts.iter().map(|x| self.rt_converter.convert(*x)).collect()
     }
@@ -374,6 +704,18 @@ impl PyPrecursor {
             },
         ))
     }
+
+    /// Collision cross section in Å² of this precursor, None when its charge
+    /// or mobility is unknown.
+    #[pyo3(signature = (gas=PyDriftGas::N2, temperature=305.0))]
+    pub fn ccs(&self, gas: PyDriftGas, temperature: f64) -> Option<f64> {
+        match self.charge {
+            Some(charge) if charge > 0 && self.im.is_finite() => {
+                Some(im_to_ccs(self.im, self.mz, charge, gas, temperature))
+            }
+            _ => None,
+        }
+    }
 }
 
 fn format_slice<T>(slc: &[T]) -> String
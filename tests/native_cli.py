@@ -0,0 +1,37 @@
+"""Runs the native `timsrust-convert` binary, without going through python.
+
+The binary installed by the `timsrust-convert` package is used when it is on
+the PATH, otherwise it is built and run from this checkout with cargo.
+"""
+
+import shutil
+import subprocess
+from pathlib import Path
+
+import pytest
+
+MANIFEST = Path(__file__).parent.parent / "Cargo.toml"
+
+
+def convert_command():
+    installed = shutil.which("timsrust-convert")
+    if installed is not None:
+        return [installed]
+    if shutil.which("cargo") is None:
+        pytest.skip("timsrust-convert is not installed and cargo is not available")
+    return [
+        "cargo",
+        "run",
+        "--quiet",
+        "--manifest-path",
+        str(MANIFEST),
+        "--bin",
+        "timsrust-convert",
+        "--",
+    ]
+
+
+def timsrust_convert(*args):
+    """Runs the converter quietly and returns its exit code."""
+    command = [*convert_command(), "--quiet", *map(str, args)]
+    return subprocess.run(command, capture_output=True).returncode
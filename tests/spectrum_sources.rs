@@ -0,0 +1,153 @@
+//! The spectrum sources rebuild how timsrust groups frames into spectra,
+//! these tests check they still describe the spectra timsrust returns.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rusqlite::Connection;
+use timsrust::readers::{
+    FrameWindowSplittingConfiguration, QuadWindowExpansionStrategy, SpectrumReader,
+    SpectrumReaderConfig,
+};
+use timsrust_pyo3::spectrum_sources::{SpectrumSlice, SpectrumSource, SpectrumSourceReader};
+
+/// A copy of the dda test data with `sql` applied to its analysis.tdf.
+fn dataset(name: &str, sql: &str) -> PathBuf {
+    let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/dda_test.d");
+    let path = std::env::temp_dir()
+        .join(format!("timsrust_pyo3_{}", std::process::id()))
+        .join(format!("{}.d", name));
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(&path).unwrap();
+    for file in ["analysis.tdf", "analysis.tdf_bin"] {
+        fs::copy(source.join(file), path.join(file)).unwrap();
+    }
+    Connection::open(path.join("analysis.tdf"))
+        .unwrap()
+        .execute_batch(sql)
+        .unwrap();
+    path
+}
+
+const DIA_SQL: &str = "
+    UPDATE Frames SET ScanMode = 9;
+    UPDATE Frames SET MsMsType = 9 WHERE MsMsType = 8;
+    CREATE TABLE DiaFrameMsMsInfo (Frame INTEGER PRIMARY KEY, WindowGroup INTEGER NOT NULL);
+    INSERT INTO DiaFrameMsMsInfo SELECT Id, Id / 2 FROM Frames WHERE MsMsType = 9;
+    CREATE TABLE DiaFrameMsMsWindows (
+        WindowGroup INTEGER, ScanNumBegin INTEGER, ScanNumEnd INTEGER,
+        IsolationMz REAL, IsolationWidth REAL, CollisionEnergy REAL
+    );
+    INSERT INTO DiaFrameMsMsWindows VALUES (1, 0, 4, 500.5, 2.0, 30.0), (2, 0, 4, 501.5, 2.0, 30.0);
+";
+
+/// Every isolation as its own precursor, numbered by frame and scan.
+const SPLIT_PRECURSORS_SQL: &str = "
+    UPDATE PasefFrameMsMsInfo SET Precursor = (
+        SELECT COUNT(*) FROM PasefFrameMsMsInfo AS other
+        WHERE (other.Frame, other.ScanNumBegin)
+            <= (PasefFrameMsMsInfo.Frame, PasefFrameMsMsInfo.ScanNumBegin)
+    );
+    DELETE FROM Precursors;
+    INSERT INTO Precursors
+        SELECT Precursor, IsolationMz, IsolationMz, IsolationMz, 2, ScanNumBegin, 10, Frame - 1
+        FROM PasefFrameMsMsInfo;
+";
+
+const PRM_SQL: &str = "
+    UPDATE Frames SET ScanMode = 10;
+    UPDATE Frames SET MsMsType = 10 WHERE MsMsType = 8;
+    CREATE TABLE PrmFrameMsMsInfo (
+        Frame INTEGER, ScanNumBegin INTEGER, ScanNumEnd INTEGER, IsolationMz REAL,
+        IsolationWidth REAL, CollisionEnergy REAL, Target INTEGER
+    );
+    INSERT INTO PrmFrameMsMsInfo
+        SELECT Frame, ScanNumBegin, ScanNumEnd, IsolationMz, IsolationWidth, CollisionEnergy,
+            Precursor
+        FROM PasefFrameMsMsInfo;
+    DROP TABLE PasefFrameMsMsInfo;
+";
+
+/// Sources of `path` against the spectra timsrust reads from `reference`:
+/// same count and order, same precursor, and the same peaks when rebuilt.
+fn assert_sources_match(path: &Path, reference: &Path, strategy: QuadWindowExpansionStrategy) {
+    let config = SpectrumReaderConfig {
+        frame_splitting_params: FrameWindowSplittingConfiguration::Quadrupole(strategy),
+        ..Default::default()
+    };
+    let sources = SpectrumSourceReader::new(path, &strategy).unwrap();
+    let reader = SpectrumReader::build()
+        .with_path(reference)
+        .with_config(config)
+        .finalize()
+        .unwrap();
+    assert_eq!(sources.sources.len(), reader.len());
+    let mut num_peaks = 0;
+    for (index, source) in sources.sources.iter().enumerate() {
+        let spectrum = reader.get(index).unwrap();
+        let precursor = spectrum.precursor.unwrap();
+        match source.precursor {
+            Some(id) => assert_eq!(precursor.index, id),
+            None => assert_eq!(precursor.frame_index, source.slices[0].frame + 1),
+        }
+        let (mz_values, intensities) = sources
+            .build_spectrum(
+                source,
+                config.spectrum_processing_params.smoothing_window,
+                config.spectrum_processing_params.centroiding_window,
+            )
+            .unwrap();
+        assert_eq!(mz_values, spectrum.mz_values, "spectrum {}", index);
+        assert_eq!(intensities, spectrum.intensities, "spectrum {}", index);
+        num_peaks += mz_values.len();
+    }
+    assert!(num_peaks > 0);
+}
+
+#[test]
+fn dda_sources_match_spectra() {
+    let path = dataset("dda", "");
+    assert_sources_match(&path, &path, QuadWindowExpansionStrategy::Even(1));
+}
+
+#[test]
+fn dia_sources_match_spectra() {
+    let path = dataset("dia", DIA_SQL);
+    for strategy in [
+        QuadWindowExpansionStrategy::None,
+        QuadWindowExpansionStrategy::Even(1),
+        QuadWindowExpansionStrategy::Even(2),
+        QuadWindowExpansionStrategy::UniformScan((1, 1)),
+        QuadWindowExpansionStrategy::UniformMobility((0.5, 0.25), None),
+    ] {
+        assert_sources_match(&path, &path, strategy);
+    }
+}
+
+#[test]
+fn prm_sources_match_spectra() {
+    // timsrust can not read prm-PASEF spectra, but builds the same ones for
+    // a DDA run that isolates every precursor once
+    let reference = dataset("prm_reference", SPLIT_PRECURSORS_SQL);
+    let path = dataset("prm", &format!("{}{}", SPLIT_PRECURSORS_SQL, PRM_SQL));
+    assert_sources_match(&path, &reference, QuadWindowExpansionStrategy::None);
+}
+
+#[test]
+fn source_info_without_known_frames() {
+    let path = dataset("source_info", "");
+    let reader = SpectrumSourceReader::new(&path, &QuadWindowExpansionStrategy::None).unwrap();
+    let info = reader.source_info(&reader.sources[0]).unwrap();
+    assert_eq!(info.rt, Some(0.2));
+    let unknown_frame = SpectrumSource {
+        slices: vec![SpectrumSlice {
+            frame: 10,
+            scan_start: 0,
+            scan_end: 2,
+        }],
+        precursor: None,
+    };
+    let info = reader.source_info(&unknown_frame).unwrap();
+    assert_eq!(info.rt, None);
+    assert!(info.frame_indices.is_empty());
+}
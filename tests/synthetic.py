@@ -0,0 +1,155 @@
+"""Writes small synthetic .d folders for tests that need realistic frames.
+
+Frames are stored as uncompressed (raw block) zstd frames, so no zstd
+package is needed. The m/z and 1/K0 converters follow the boundaries in
+GlobalMetadata, as timsrust builds them.
+"""
+
+import math
+import shutil
+import sqlite3
+import struct
+
+MZ_RANGE = (100.0, 1700.0)
+IM_RANGE = (0.6, 1.6)
+NUM_TOF = 400_000
+NUM_SCANS = 100
+ISOTOPE_SPACING = 1.003354835
+
+
+def mz_to_tof(mz):
+    slope = (math.sqrt(MZ_RANGE[1]) - math.sqrt(MZ_RANGE[0])) / NUM_TOF
+    return round((math.sqrt(mz) - math.sqrt(MZ_RANGE[0])) / slope)
+
+
+def im_to_scan(im):
+    slope = (IM_RANGE[0] - IM_RANGE[1]) / NUM_SCANS
+    return round((im - IM_RANGE[1]) / slope)
+
+
+def isotope_events(mz, charge, im, intensity, ratios):
+    """(scan, tof, intensity) events of an isotope envelope, spread over
+    five scans around `im`."""
+    events = []
+    for isotope, ratio in enumerate(ratios):
+        tof = mz_to_tof(mz + isotope * ISOTOPE_SPACING / charge)
+        for offset in range(-2, 3):
+            value = int(intensity * ratio * math.exp(-(offset**2) / 2))
+            if value >= 5:
+                events.append((im_to_scan(im) + offset, tof, value))
+    return events
+
+
+def fragment_events(peaks, im=1.0):
+    """Events of singly charged fragments without isotopes, from (mz,
+    intensity) pairs."""
+    events = []
+    for mz, intensity in peaks:
+        events += isotope_events(mz, 1, im, intensity, [1.0])
+    return events
+
+
+def _zstd_raw(data):
+    header = struct.pack("<IBI", 0xFD2FB528, 0xA0, len(data))
+    blocks = []
+    block_size = 1 << 17
+    chunks = [data[i : i + block_size] for i in range(0, len(data), block_size)] or [b""]
+    for i, chunk in enumerate(chunks):
+        last = i == len(chunks) - 1
+        blocks.append(struct.pack("<I", (len(chunk) << 3) | last)[:3] + chunk)
+    return header + b"".join(blocks)
+
+
+def _frame_blob(scans):
+    """`scans` is a list of NUM_SCANS lists with (tof, intensity) events."""
+    values = [len(scans)]
+    values += [2 * len(events) for events in scans[:-1]]
+    for events in scans:
+        previous = -1
+        for tof, intensity in sorted(events):
+            values += [tof - previous, intensity]
+            previous = tof
+    # Bytes are stored transposed: all first bytes, all second bytes, ...
+    data = b"".join(bytes((x >> (8 * i)) & 0xFF for x in values) for i in range(4))
+    compressed = _zstd_raw(data)
+    return struct.pack("<II", len(compressed) + 8, len(scans)) + compressed
+
+
+def write_dataset(source, path, frames):
+    """Copies the `source` .d folder to `path`, replacing its frames.
+
+    `frames` is a list of (rt, msms_type, events) with events as
+    (scan, tof, intensity) tuples.
+    """
+    shutil.copytree(source, path)
+    offsets = []
+    with open(path / "analysis.tdf_bin", "wb") as tdf_bin:
+        for _, _, events in frames:
+            scans = [[] for _ in range(NUM_SCANS)]
+            for scan, tof, intensity in events:
+                scans[scan].append((tof, intensity))
+            offsets.append(tdf_bin.tell())
+            tdf_bin.write(_frame_blob(scans))
+    connection = sqlite3.connect(path / "analysis.tdf")
+    connection.execute("DELETE FROM Frames")
+    connection.execute("DELETE FROM PasefFrameMsMsInfo")
+    connection.execute("DELETE FROM Precursors")
+    for i, ((rt, msms_type, events), offset) in enumerate(zip(frames, offsets)):
+        intensities = [x[2] for x in events]
+        connection.execute(
+            "INSERT INTO Frames VALUES (?, ?, '+', ?, ?, ?, ?, ?, ?, ?, 100, 100)",
+            (
+                i + 1,
+                rt,
+                0 if msms_type == 0 else 8,
+                msms_type,
+                offset,
+                max(intensities, default=0),
+                sum(intensities),
+                NUM_SCANS,
+                len(events),
+            ),
+        )
+    for key, value in [
+        ("MzAcqRangeLower", MZ_RANGE[0]),
+        ("MzAcqRangeUpper", MZ_RANGE[1]),
+        ("OneOverK0AcqRangeLower", IM_RANGE[0]),
+        ("OneOverK0AcqRangeUpper", IM_RANGE[1]),
+        ("DigitizerNumSamples", NUM_TOF),
+    ]:
+        connection.execute(
+            "UPDATE GlobalMetadata SET Value = ? WHERE Key = ?", (str(value), key)
+        )
+    connection.commit()
+    connection.close()
+    return str(path)
+
+
+def write_dda_dataset(
+    source, path, frames, precursors, scans=(0, 100), intensity=100.0
+):
+    """As `write_dataset`, with one PASEF isolation per precursor.
+
+    `precursors` is a list of (frame, mz, charge) with the index of the MS2
+    frame that isolated it, optionally followed by the 1/K0 it was picked at
+    (1.0) and its monoisotopic m/z (`mz`). Each precursor has its parent in
+    the last MS1 frame before `frame` and `intensity`, and is isolated over
+    `scans` with a 2 Th window.
+    """
+    path = write_dataset(source, path, frames)
+    connection = sqlite3.connect(path + "/analysis.tdf")
+    for i, (frame, mz, charge, *optional) in enumerate(precursors):
+        defaults = (1.0, mz)
+        im, monoisotopic_mz = (*optional, *defaults[len(optional) :])
+        parent = max(j + 1 for j in range(frame - 1) if frames[j][1] == 0)
+        connection.execute(
+            "INSERT INTO Precursors VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            (i + 1, mz, mz, monoisotopic_mz, charge, im_to_scan(im), intensity, parent),
+        )
+        connection.execute(
+            "INSERT INTO PasefFrameMsMsInfo VALUES (?, ?, ?, ?, 2.0, 30.0, ?)",
+            (frame, *scans, mz, i + 1),
+        )
+    connection.commit()
+    connection.close()
+    return path
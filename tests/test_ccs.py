@@ -0,0 +1,68 @@
+import math
+
+import pytest
+import timsrust_pyo3
+from timsrust_pyo3 import DriftGas
+
+
+def mason_schamp(im, mz, charge, gas_mass=28.006148, temperature=305.0):
+    ion_mass = mz * charge
+    reduced_mass = ion_mass * gas_mass / (ion_mass + gas_mass) * 1.6605390666e-27
+    return (
+        3 * 1.602176634e-19 * charge / (16 * 2.686780111e25)
+        * math.sqrt(2 * math.pi / (reduced_mass * 1.380649e-23 * temperature))
+        * im * 1e4 * 1e20
+    )
+
+
+def test_im_to_ccs():
+    ccs = timsrust_pyo3.im_to_ccs([0.9, 1.2], [500.0, 800.0], [2, 3])
+    assert ccs == pytest.approx(
+        [mason_schamp(0.9, 500.0, 2), mason_schamp(1.2, 800.0, 3)]
+    )
+    assert 350 < ccs[0] < 400
+    helium = timsrust_pyo3.im_to_ccs(
+        [0.9], [500.0], [2], gas=DriftGas.He, temperature=298.0
+    )
+    assert helium == pytest.approx(
+        [mason_schamp(0.9, 500.0, 2, DriftGas.He.mass, 298.0)]
+    )
+    ims = timsrust_pyo3.ccs_to_im(ccs, [500.0, 800.0], [2, 3])
+    assert ims == pytest.approx([0.9, 1.2])
+    with pytest.raises(ValueError):
+        timsrust_pyo3.im_to_ccs([0.9], [500.0, 800.0], [2])
+    with pytest.raises(ValueError):
+        timsrust_pyo3.im_to_ccs([0.9], [500.0], [0])
+
+
+def test_ccs_converters(shared_datadir):
+    metadata = timsrust_pyo3.Metadata(str(shared_datadir / "dda_test.d" / "analysis.tdf"))
+    scans = [100, 200]
+    ims = metadata.resolve_scans(scans)
+    expected = timsrust_pyo3.im_to_ccs(ims, [500.0, 600.0], [2, 2])
+    assert metadata.resolve_ccs(scans, [500.0, 600.0], [2, 2]) == pytest.approx(
+        expected
+    )
+    converted = metadata.im_converter.scans_to_ccs([100.0, 200.0], [500.0, 600.0], [2, 2])
+    assert converted == pytest.approx(expected)
+    back = metadata.im_converter.ccs_to_scans(converted, [500.0, 600.0], [2, 2])
+    assert back == pytest.approx([100.0, 200.0])
+
+    # Scans are rounded to the nearest one and have to exist (0..=4 here)
+    mzs, charges = [500.0, 600.0], [2, 2]
+    ccs = metadata.im_converter.scans_to_ccs([0.6, 3.4], mzs, charges)
+    assert metadata.invert_ccs(ccs, mzs, charges) == [1, 3]
+    ccs = metadata.im_converter.scans_to_ccs([-0.6, 4.6], [500.0, 500.0], charges)
+    for x in [*ccs, float("nan"), float("inf")]:
+        with pytest.raises(ValueError):
+            metadata.invert_ccs([x], [500.0], [2])
+
+
+def test_precursor_ccs(shared_datadir):
+    reader = timsrust_pyo3.SpectrumReader(str(shared_datadir / "dda_test.d"))
+    precursor = reader.read_all_spectra()[0].precursor
+    ccs = precursor.ccs()
+    assert ccs == pytest.approx(
+        mason_schamp(precursor.im, precursor.mz, precursor.charge)
+    )
+    assert precursor.ccs(DriftGas.He) > ccs
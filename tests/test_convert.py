@@ -0,0 +1,59 @@
+import json
+import shutil
+
+import pytest
+import timsrust_pyo3
+from native_cli import timsrust_convert as convert
+
+
+def test_convert_mgf(shared_datadir, tmp_path):
+    output = tmp_path / "out.mgf"
+    assert convert(str(shared_datadir / "dda_test.d"), "-o", str(output)) == 0
+
+    text = output.read_text()
+    assert text.count("BEGIN IONS") == 3
+    assert "PEPMASS=500 10" in text
+    assert "CHARGE=2+" in text
+
+
+def test_convert_json(shared_datadir, tmp_path):
+    datafile = str(shared_datadir / "dda_test.d")
+    output = tmp_path / "out.json"
+    assert convert(datafile, "-o", str(output), "--include-ms1") == 0
+
+    records = json.loads(output.read_text())
+    specs = timsrust_pyo3.SpectrumReader(datafile, include_ms1=True).read_all_spectra()
+    assert [r["ms_level"] for r in records] == ["MS1", "MS2", "MS2", "MS1", "MS2"]
+    assert records[1]["mz_values"] == pytest.approx(specs[1].mz_values)
+
+    output = tmp_path / "frames.json"
+    assert convert(datafile, "--frames", "-f", "json", "-o", str(output)) == 0
+    assert len(json.loads(output.read_text())) == 4
+
+
+def test_convert_mzml(shared_datadir, tmp_path):
+    datafile = str(shared_datadir / "test.ms2")
+    output = tmp_path / "out.mzML"
+    assert convert(datafile, "-o", str(output)) == 0
+
+    specs = timsrust_pyo3.read_all_spectra(datafile)
+    assert output.read_text().count("<spectrum ") == len(specs)
+
+
+def test_convert_exit_codes(shared_datadir, tmp_path):
+    datafile = str(shared_datadir / "dda_test.d")
+    # invalid arguments
+    assert convert(datafile, "-o", str(tmp_path / "out.txt")) == 2
+    assert convert(datafile, "--frames", "-f", "mgf") == 2
+    assert convert(datafile, "-f", "mgf", "--calibrate", "--with-mobility") == 2
+    span_step = ["--ms1-mobility-span-step", "0", "0.1"]
+    assert convert(datafile, "-f", "json", "--include-ms1", *span_step) == 2
+    # input can not be opened
+    assert convert(str(tmp_path / "missing.d"), "-f", "mgf") == 3
+    # output can not be written
+    assert convert(datafile, "-o", str(tmp_path / "no" / "out.mgf")) == 5
+    # frames can not be read
+    corrupt = tmp_path / "corrupt.d"
+    shutil.copytree(datafile, corrupt)
+    (corrupt / "analysis.tdf_bin").write_bytes(b"")
+    assert convert(corrupt, "--frames", "-o", str(tmp_path / "out.json")) == 4
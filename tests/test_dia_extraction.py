@@ -0,0 +1,96 @@
+import sqlite3
+
+import pytest
+import timsrust_pyo3
+from synthetic import fragment_events, isotope_events, write_dataset
+
+PROFILE = [100, 500, 1000, 500, 100]
+FRAGMENTS = [300.15, 450.2, 650.3]
+
+
+def expected(intensity):
+    # fragment_events spreads every peak over five scans
+    return sum(x[2] for x in fragment_events([(500.0, intensity)]))
+
+
+@pytest.fixture
+def dia_file(shared_datadir, tmp_path):
+    # Window group 1 isolates 400-425 at 1/K0 0.8-1.2, group 2 425-450
+    frames = []
+    for cycle, intensity in enumerate(PROFILE):
+        fragments = [(mz, intensity) for mz in FRAGMENTS]
+        ms1 = isotope_events(410.0, 1, 1.0, intensity, [1.0]) + isotope_events(700.0, 1, 1.0, 50, [1.0])
+        frames.append((cycle, 0, ms1))
+        # The same fragments far outside the target mobility must be ignored
+        frames.append((cycle + 0.2, 9, fragment_events(fragments) + fragment_events(fragments, im=1.4)))
+        frames.append((cycle + 0.4, 9, fragment_events([(mz, 7) for mz in FRAGMENTS])))
+    path = write_dataset(shared_datadir / "dda_test.d", tmp_path / "dia.d", frames)
+    connection = sqlite3.connect(path + "/analysis.tdf")
+    connection.executescript(
+        """
+        CREATE TABLE IF NOT EXISTS DiaFrameMsMsInfo (Frame INTEGER PRIMARY KEY, WindowGroup INTEGER NOT NULL);
+        CREATE TABLE IF NOT EXISTS DiaFrameMsMsWindows (
+            WindowGroup INTEGER NOT NULL,
+            ScanNumBegin INTEGER NOT NULL,
+            ScanNumEnd INTEGER NOT NULL,
+            IsolationMz REAL NOT NULL,
+            IsolationWidth REAL NOT NULL,
+            CollisionEnergy REAL NOT NULL
+        );
+        DELETE FROM DiaFrameMsMsInfo;
+        DELETE FROM DiaFrameMsMsWindows;
+        INSERT INTO DiaFrameMsMsWindows VALUES
+            (1, 0, 40, 612.5, 25.0, 30.0), (1, 40, 80, 412.5, 25.0, 30.0),
+            (2, 0, 40, 637.5, 25.0, 30.0), (2, 40, 80, 437.5, 25.0, 30.0);
+        INSERT INTO DiaFrameMsMsInfo SELECT Id, 1 + (Id % 3 = 0) FROM Frames WHERE MsMsType = 9;
+        """
+    )
+    connection.commit()
+    connection.close()
+    return path
+
+
+def test_extract_dia_targets(dia_file):
+    reader = timsrust_pyo3.FrameReader(dia_file)
+    targets = [
+        timsrust_pyo3.DiaTarget(410.0, FRAGMENTS, 2.0, 1.0, rt_tolerance=10.0, name="first"),
+        timsrust_pyo3.DiaTarget(437.0, FRAGMENTS[:2], 2.0, 1.0, rt_tolerance=10.0),
+        timsrust_pyo3.DiaTarget(700.0, FRAGMENTS, 2.0, 1.0, rt_tolerance=10.0),
+    ]
+    first, second, unisolated = reader.extract_dia_targets(targets)
+    assert first.target.name == "first"
+    assert first.frame_indices == [2, 5, 8, 11, 14]
+    assert first.rt == pytest.approx([0.2, 1.2, 2.2, 3.2, 4.2])
+    assert len(first) == 5
+    assert len(first.fragment_intensities) == 3
+    for trace in first.fragment_intensities:
+        assert trace == [expected(x) for x in PROFILE]
+    assert first.total_fragment_intensities() == [3 * expected(x) for x in PROFILE]
+    assert first.ms1_frame_indices == [1, 4, 7, 10, 13]
+    assert first.precursor_intensities == [expected(x) for x in PROFILE]
+    assert second.frame_indices == [3, 6, 9, 12, 15]
+    assert second.fragment_intensities == [[expected(7)] * 5] * 2
+    assert second.precursor_intensities == [0.0] * 5
+    assert len(unisolated) == 0
+    assert unisolated.fragment_intensities == [[], [], []]
+    assert unisolated.precursor_intensities == [expected(50)] * 5
+
+
+def test_extract_dia_targets_windows(dia_file):
+    reader = timsrust_pyo3.FrameReader(dia_file)
+    narrow_rt = timsrust_pyo3.DiaTarget(410.0, FRAGMENTS, 2.0, 1.0, rt_tolerance=0.5)
+    other_im = timsrust_pyo3.DiaTarget(410.0, FRAGMENTS, 2.0, 1.4, rt_tolerance=10.0)
+    narrow_rt, other_im = reader.extract_dia_targets([narrow_rt, other_im])
+    assert narrow_rt.frame_indices == [8]
+    assert narrow_rt.ms1_frame_indices == [7]
+    # 1/K0 1.4 is outside the scans isolating 400-425
+    assert len(other_im) == 0
+    assert reader.extract_dia_targets([]) == []
+
+
+def test_extract_dia_targets_errors(dia_file, shared_datadir):
+    with pytest.raises(ValueError):
+        timsrust_pyo3.DiaTarget(410.0, FRAGMENTS, 2.0, 1.0, im_tolerance=-0.1)
+    reader = timsrust_pyo3.FrameReader(str(shared_datadir / "dda_test.d"))
+    with pytest.raises(ValueError):
+        reader.extract_dia_targets([timsrust_pyo3.DiaTarget(410.0, FRAGMENTS, 2.0, 1.0)])
@@ -0,0 +1,67 @@
+import math
+
+import pytest
+import timsrust_pyo3
+from synthetic import ISOTOPE_SPACING, isotope_events, mz_to_tof, write_dataset
+
+# mz, charge, apex frame, 1/K0, apex intensity, isotope ratios
+IONS = [
+    (600.3, 2, 6, 1.0, 10000, [1.0, 0.8, 0.4, 0.15]),
+    (450.2, 1, 4, 0.8, 5000, [1.0, 0.5, 0.15]),
+]
+
+
+def ms1_events(frame):
+    events = []
+    for mz, charge, apex, im, intensity, ratios in IONS:
+        elution = math.exp(-((frame - apex) ** 2) / (2 * 1.5**2))
+        events += isotope_events(mz, charge, im, intensity * elution, ratios)
+    if frame == 2:
+        events.append((30, mz_to_tof(900.0), 3000))
+    return events
+
+
+@pytest.fixture
+def ms1_file(shared_datadir, tmp_path):
+    frames = [(float(i), 0, ms1_events(i)) for i in range(12)]
+    return write_dataset(shared_datadir / "dda_test.d", tmp_path / "ms1_test.d", frames)
+
+
+def test_find_features(ms1_file):
+    reader = timsrust_pyo3.FrameReader(ms1_file)
+    features = reader.find_features()
+    assert [f.charge for f in features] == [1, 2]
+    low, high = features
+    assert high.mz == pytest.approx(600.3, abs=0.005)
+    assert len(high.isotope_mzs) == 4
+    assert high.isotope_mzs[1] - high.isotope_mzs[0] == pytest.approx(
+        ISOTOPE_SPACING / 2, abs=0.005
+    )
+    assert high.rt == 6.0
+    assert high.apex_frame_index == 7
+    assert high.im == pytest.approx(1.0, abs=0.01)
+    assert 0.0 < high.rt_width <= 5.0
+    assert high.rt_start < high.rt < high.rt_end
+    assert high.intensity == pytest.approx(sum(high.isotope_intensities))
+    assert high.isotope_intensities[0] > high.isotope_intensities[2]
+    assert low.mz == pytest.approx(450.2, abs=0.005)
+    assert low.rt == 4.0
+    assert low.im == pytest.approx(0.8, abs=0.01)
+    assert high.xic_rt is None and high.mobilogram_im is None
+
+
+def test_find_features_options(ms1_file):
+    reader = timsrust_pyo3.FrameReader(ms1_file)
+    singles = reader.find_features(min_isotopes=1, min_frames=1)
+    assert any(f.charge is None and f.mz == pytest.approx(900.0, abs=0.01) for f in singles)
+    assert all(f.charge == 1 for f in reader.find_features(max_charge=1))
+    features = reader.find_features(with_traces=True)
+    feature = features[1]
+    assert len(feature.xic_rt) == len(feature.xic_intensities)
+    assert max(feature.xic_intensities) == feature.apex_intensity
+    assert feature.xic_rt[feature.xic_intensities.index(feature.apex_intensity)] == feature.rt
+    apex = feature.mobilogram_intensities.index(max(feature.mobilogram_intensities))
+    assert feature.mobilogram_im[apex] == pytest.approx(1.0, abs=0.01)
+    columns = timsrust_pyo3.to_columns(features)
+    assert columns["charge"] == [1, 2]
+    assert columns["mz"] == [f.mz for f in features]
@@ -0,0 +1,76 @@
+import pytest
+import timsrust_pyo3
+from synthetic import fragment_events, write_dda_dataset
+
+PEPTIDE = "PEPTIDE"
+MATCHED = ["y1", "b2", "y2", "b3", "y3"]
+
+
+@pytest.fixture
+def spectrum(shared_datadir, tmp_path):
+    ions = {x.label: x.mz for x in timsrust_pyo3.fragment_ions(PEPTIDE)}
+    peaks = [(ions[x], 1000) for x in MATCHED] + [(1000.0, 5000)]
+    frames = [(1.0, 0, fragment_events([(400.69, 1000)])), (1.1, 8, fragment_events(peaks))]
+    path = write_dda_dataset(
+        shared_datadir / "dda_test.d",
+        tmp_path / "dda.d",
+        frames,
+        precursors=[(2, 400.69, 2)],
+    )
+    return timsrust_pyo3.SpectrumReader(path).get(0)
+
+
+def test_fragment_ions():
+    ions = timsrust_pyo3.fragment_ions(PEPTIDE)
+    by_label = {x.label: x for x in ions}
+    assert len(ions) == 12
+    assert [x.mz for x in ions] == sorted(x.mz for x in ions)
+    assert by_label["y1"].mz == pytest.approx(148.060434, abs=1e-5)
+    assert by_label["b2"].mz == pytest.approx(227.102633, abs=1e-5)
+    assert by_label["b2"].ion_type == "b"
+    assert by_label["b2"].length == 2
+    doubly = timsrust_pyo3.fragment_ions(PEPTIDE, max_fragment_charge=2, neutral_losses=True, a_ions=True)
+    labels = {x.label for x in doubly}
+    assert {"y3^2", "b4-H2O", "y2-NH3", "a2"} <= labels
+    internal = timsrust_pyo3.fragment_ions(PEPTIDE, internal=True)
+    assert "int-EPT" in {x.label for x in internal}
+    assert "int-PEP" not in {x.label for x in internal}
+
+
+def test_modifications():
+    mz = lambda peptide, label: {x.label: x.mz for x in timsrust_pyo3.fragment_ions(peptide)}[label]
+    oxidized = mz("PEPM[Oxidation]K", "y2") - mz("PEPMK", "y2")
+    assert oxidized == pytest.approx(15.994915, abs=1e-5)
+    for peptide in ["PEPM[UNIMOD:35]K", "PEPM(ox)K", "PEPM[+15.994915]K", "_PEPM(UniMod:35)K_"]:
+        assert mz(peptide, "y2") == pytest.approx(mz("PEPM[Oxidation]K", "y2"))
+    acetyl = mz("[Acetyl]-PEPMK", "b1") - mz("PEPMK", "b1")
+    assert acetyl == pytest.approx(42.010565, abs=1e-5)
+    assert mz("[Acetyl]-PEPMK", "y4") == pytest.approx(mz("PEPMK", "y4"))
+    for invalid in ["PEPXK", "PEP[Foo]K", ""]:
+        with pytest.raises(ValueError):
+            timsrust_pyo3.fragment_ions(invalid)
+
+
+def test_annotate(spectrum):
+    annotation = spectrum.annotate(PEPTIDE)
+    assert annotation.charge == 2
+    assert annotation.precursor_mz == pytest.approx(400.687258, abs=1e-5)
+    labels = [x for x in annotation.labels if x is not None]
+    assert sorted(labels) == sorted(MATCHED)
+    assert len(annotation.labels) == len(spectrum.mz_values)
+    assert annotation.num_matched_peaks == 5
+    assert annotation.num_matched_fragments == 5
+    assert annotation.matched_intensity_fraction == pytest.approx(0.5, abs=0.05)
+    assert annotation.sequence_coverage == pytest.approx(5 / 6)
+    assert all(abs(x) < 20 for x in annotation.mass_errors_ppm if x is not None)
+    unmatched = annotation.labels.index(None)
+    assert spectrum.mz_values[unmatched] == pytest.approx(1000.0, abs=0.01)
+    assert annotation.fragment_mzs[unmatched] is None
+
+
+def test_annotate_options(spectrum):
+    assert spectrum.annotate(PEPTIDE, charge=1).num_fragments == 12
+    assert spectrum.annotate(PEPTIDE).num_fragments == 12
+    assert spectrum.annotate(PEPTIDE, max_fragment_charge=2).num_fragments == 24
+    assert spectrum.annotate(PEPTIDE, tolerance=0.0001, unit="da").num_matched_peaks < 5
+    assert spectrum.annotate("PEPTIDEK").num_matched_peaks < 5
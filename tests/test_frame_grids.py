@@ -0,0 +1,66 @@
+import pytest
+import timsrust_pyo3
+from synthetic import NUM_SCANS, im_to_scan, mz_to_tof, write_dataset
+
+
+@pytest.fixture
+def grid_file(shared_datadir, tmp_path):
+    frames = [
+        (1.0, 0, [(im_to_scan(1.0), mz_to_tof(500.2), 100), (im_to_scan(1.0), mz_to_tof(500.4), 50)]),
+        (1.1, 8, [(10, mz_to_tof(300.5), 7)]),
+        (2.0, 0, [(im_to_scan(0.8), mz_to_tof(800.5), 30)]),
+        (3.0, 0, [(im_to_scan(1.2), mz_to_tof(600.5), 20)]),
+    ]
+    return write_dataset(shared_datadir / "dda_test.d", tmp_path / "grid.d", frames)
+
+
+def test_frame_to_grid(grid_file):
+    reader = timsrust_pyo3.FrameReader(grid_file)
+    metadata = timsrust_pyo3.Metadata(grid_file + "/analysis.tdf")
+    grid = reader.read_frame(0).to_grid(metadata, bin_width=1.0, mz_range=(0.0, 1000.0))
+    assert grid.shape == (1, NUM_SCANS, 1000)
+    assert grid.by_scan
+    assert grid.frame_indices == [1]
+    # Both events fall in the same cell and are summed
+    assert grid.rows == [im_to_scan(1.0)]
+    assert grid.columns == [500]
+    assert grid.values == [150.0]
+    assert grid.mz_values[500] == pytest.approx(500.5)
+    assert grid.mobility_values[im_to_scan(1.0)] == pytest.approx(1.0, abs=0.01)
+
+
+def test_frame_to_grid_im_bins(grid_file):
+    reader = timsrust_pyo3.FrameReader(grid_file)
+    metadata = timsrust_pyo3.Metadata(grid_file + "/analysis.tdf")
+    grid = reader.read_frame(0).to_grid(metadata, bin_width=0.1, im_bin_width=0.1)
+    assert not grid.by_scan
+    assert grid.shape[1] == 10
+    assert grid.mobility_values[0] == pytest.approx(0.65)
+    assert grid.rows == [4, 4]
+    assert grid.columns == [4002, 4003]
+    with pytest.raises(ValueError):
+        reader.read_frame(0).to_grid(metadata, im_bin_width=-0.1)
+
+
+def test_reader_to_grid(grid_file):
+    reader = timsrust_pyo3.FrameReader(grid_file)
+    grid = reader.to_grid(rt_range=(0.5, 2.5), ms_level=timsrust_pyo3.MSLevel.MS1)
+    assert grid.frame_indices == [1, 3]
+    assert grid.rt_values == [1.0, 2.0]
+    assert grid.frame_positions == [0, 1]
+    assert len(grid.mz_values) == 1600
+    assert len(reader.to_grid().frame_indices) == 4
+
+
+def test_grid_to_numpy_and_scipy(grid_file):
+    np = pytest.importorskip("numpy")
+    pytest.importorskip("scipy.sparse")
+    reader = timsrust_pyo3.FrameReader(grid_file)
+    grid = reader.to_grid(ms_level=timsrust_pyo3.MSLevel.MS1, mz_range=(0.0, 1000.0))
+    dense = grid.to_numpy()
+    assert dense.shape == (3, NUM_SCANS, 1000)
+    assert dense[0, im_to_scan(1.0), 500] == 150.0
+    assert dense.sum() == 200.0
+    matrix = grid.to_scipy()
+    assert matrix.shape == (3 * NUM_SCANS, 1000)
+    assert np.allclose(matrix.toarray().reshape(dense.shape), dense)
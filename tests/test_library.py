@@ -0,0 +1,123 @@
+import pytest
+import timsrust_pyo3
+from native_cli import timsrust_convert
+from synthetic import fragment_events, write_dda_dataset
+
+PEAKS_A = [(200.0, 1000), (300.0, 500), (400.0, 250)]
+PEAKS_B = [(200.0, 1000), (500.0, 800), (600.0, 600)]
+
+MSP = """Name: PEPA/1
+MW: 799.0
+PrecursorMZ: 800.0
+Num peaks: 3
+200.0 1000 "y1"
+300.0 500
+400.0\t250
+
+Name: PEPB/1
+Comment: Parent=800.001 Mods=0
+Num peaks: 3
+200.0 100; 500.0 80; 600.0 60
+
+Name: PEPA/2
+PrecursorMZ: 800.0
+Num peaks: 3
+200.0 1000
+300.0 500
+400.0 250
+
+Name: FAR
+PrecursorMZ: 900.0
+Charge: 1+
+Num peaks: 3
+200.0 1000
+300.0 500
+400.0 250
+"""
+
+
+@pytest.fixture
+def dda_file(shared_datadir, tmp_path):
+    frames = [(1.0, 0, fragment_events([(800.0, 1000)]))]
+    frames += [(1.1, 8, fragment_events(PEAKS_A)), (1.2, 8, fragment_events(PEAKS_B))]
+    return write_dda_dataset(
+        shared_datadir / "dda_test.d",
+        tmp_path / "dda.d",
+        frames,
+        precursors=[(2, 800.0, 1), (3, 800.0, 1)],
+    )
+
+
+@pytest.fixture
+def msp_file(tmp_path):
+    path = tmp_path / "library.msp"
+    path.write_text(MSP)
+    return str(path)
+
+
+def test_read_msp(msp_file):
+    library = timsrust_pyo3.SpectralLibrary(msp_file)
+    assert len(library) == 4
+    entries = [library.get(i) for i in range(len(library))]
+    assert [x.precursor_mz for x in entries] == sorted(x.precursor_mz for x in entries)
+    by_name = {x.name: x for x in entries}
+    assert by_name["PEPA/1"].charge == 1
+    assert by_name["PEPA/1"].intensities == [1000.0, 500.0, 250.0]
+    assert by_name["PEPB/1"].precursor_mz == 800.001
+    assert by_name["PEPB/1"].mz_values == [200.0, 500.0, 600.0]
+    assert by_name["PEPA/2"].charge == 2
+    assert by_name["FAR"].charge == 1
+
+
+def test_invalid_library(tmp_path):
+    with pytest.raises(ValueError):
+        timsrust_pyo3.SpectralLibrary(str(tmp_path / "library.txt"))
+    with pytest.raises(OSError):
+        timsrust_pyo3.SpectralLibrary(str(tmp_path / "missing.msp"))
+    path = tmp_path / "broken.msp"
+    path.write_text("Name: X\nPrecursorMZ: 500\n100.0 abc\n")
+    with pytest.raises(ValueError):
+        timsrust_pyo3.SpectralLibrary(str(path))
+
+
+def test_search(dda_file, msp_file):
+    library = timsrust_pyo3.SpectralLibrary(msp_file)
+    spectra = timsrust_pyo3.SpectrumReader(dda_file).read_all_spectra()
+    hits = library.search(spectra[0])
+    # PEPA/2 has another charge and FAR another precursor m/z
+    assert [x.name for x in hits] == ["PEPA/1", "PEPB/1"]
+    assert [x.rank for x in hits] == [1, 2]
+    assert hits[0].score == pytest.approx(1.0)
+    assert hits[0].matched_peaks == 3
+    assert hits[1].matched_peaks == 1
+    assert hits[1].precursor_error_ppm == pytest.approx(-1.25, abs=0.01)
+    assert [x.name for x in library.search(spectra[0], precursor_tolerance=0.5)] == ["PEPA/1"]
+    assert len(library.search(spectra[0], top_n=1)) == 1
+    assert len(library.search(spectra[0], min_matched_peaks=2)) == 1
+    wide = library.search(spectra[0], precursor_tolerance=150.0, precursor_unit="da")
+    assert {x.name for x in wide} == {"PEPA/1", "PEPB/1", "FAR"}
+
+
+def test_search_reader(dda_file, msp_file):
+    library = timsrust_pyo3.SpectralLibrary(msp_file)
+    reader = timsrust_pyo3.SpectrumReader(dda_file)
+    results = reader.search_library(library, metric="entropy", top_n=1)
+    assert [[x.name for x in hits] for hits in results] == [["PEPA/1"], ["PEPB/1"]]
+    assert [hits[0].spectrum_index for hits in results] == [0, 1]
+    searched = library.search_all(reader.read_all_spectra(), metric="entropy", top_n=1)
+    assert [[x.score for x in hits] for hits in searched] == [
+        [x.score for x in hits] for hits in results
+    ]
+
+
+def test_parquet_library(dda_file, tmp_path):
+    output = tmp_path / "library.parquet"
+    assert timsrust_convert(dda_file, "-o", output) == 0
+    library = timsrust_pyo3.SpectralLibrary(str(output))
+    assert len(library) == 2
+    assert library.get(0).charge == 1
+    spectra = timsrust_pyo3.SpectrumReader(dda_file).read_all_spectra()
+    for spectrum in spectra:
+        hits = library.search(spectrum)
+        assert hits[0].score == pytest.approx(1.0)
+        assert library.get(hits[0].library_index).mz_values == spectrum.mz_values
@@ -0,0 +1,87 @@
+import shutil
+import sqlite3
+
+import pytest
+import timsrust_pyo3
+
+
+@pytest.fixture
+def maldi_file(shared_datadir, tmp_path):
+    # Lays out the frames of the dda test data as a 2x2 MALDI raster
+    path = tmp_path / "maldi_test.d"
+    shutil.copytree(shared_datadir / "dda_test.d", path)
+    connection = sqlite3.connect(path / "analysis.tdf")
+    connection.executescript(
+        """
+        CREATE TABLE MaldiFrameInfo (
+            Frame INTEGER PRIMARY KEY,
+            Chip INTEGER NOT NULL,
+            SpotName TEXT NOT NULL,
+            RegionNumber INTEGER NOT NULL,
+            XIndexPos INTEGER NOT NULL,
+            YIndexPos INTEGER NOT NULL,
+            MotorPositionX REAL NOT NULL,
+            MotorPositionY REAL NOT NULL,
+            MotorPositionZ REAL,
+            LaserInfo TEXT,
+            LaserPower REAL NOT NULL,
+            LaserRepRate REAL NOT NULL,
+            LaserApplied INTEGER NOT NULL,
+            LaserBurstCount INTEGER NOT NULL,
+            LaserBeamScanSizeX REAL,
+            LaserBeamScanSizeY REAL
+        );
+        INSERT INTO MaldiFrameInfo
+            SELECT Id, 0, 'R00X' || (10 + (Id - 1) % 2) || 'Y' || (20 + (Id - 1) / 2),
+                0, 10 + (Id - 1) % 2, 20 + (Id - 1) / 2,
+                100.0 * Id, 200.0 * Id, NULL, 'Smartbeam', 55.0, 10000.0, 1, 200,
+                20.0, 20.0
+            FROM Frames;
+        """
+    )
+    connection.commit()
+    connection.close()
+    return str(path)
+
+
+def test_maldi_info(maldi_file):
+    reader = timsrust_pyo3.FrameReader(maldi_file)
+    infos = reader.read_maldi_info()
+    assert [(i.frame, i.x, i.y) for i in infos] == [
+        (1, 10, 20),
+        (2, 11, 20),
+        (3, 10, 21),
+        (4, 11, 21),
+    ]
+    info = infos[1]
+    assert info.spot_name == "R00X11Y20"
+    assert info.motor_position == (200.0, 400.0, None)
+    assert info.laser_power == 55.0
+    assert info.laser_shots == 200
+    assert info.laser_beam_scan_size == (20.0, 20.0)
+    frame = reader.read_frame(1)
+    assert frame.maldi_info.spot_name == info.spot_name
+
+
+def test_maldi_not_maldi(shared_datadir):
+    reader = timsrust_pyo3.FrameReader(str(shared_datadir / "dda_test.d"))
+    assert reader.read_frame(0).maldi_info is None
+    with pytest.raises(ValueError):
+        reader.read_maldi_info()
+    with pytest.raises(ValueError):
+        reader.ion_image(500.0)
+
+
+def test_ion_image(maldi_file):
+    reader = timsrust_pyo3.FrameReader(maldi_file)
+    frames = reader.read_all_frames()
+    # Wide enough to cover every peak
+    image = reader.ion_image(1000.0, tolerance_ppm=1e6)
+    assert len(image) == 4
+    assert image.frame_indices == [1, 2, 3, 4]
+    assert image.intensities == [float(sum(f.intensities)) for f in frames]
+    assert image.bounds == (10, 20, 11, 21)
+    grid = image.to_grid()
+    assert grid == [image.intensities[:2], image.intensities[2:]]
+    empty = reader.ion_image(1000.0, tolerance_ppm=1e6, im_range=(10.0, 20.0))
+    assert empty.intensities == [0.0] * 4
@@ -29,6 +29,29 @@ def test_dunder_iter_spectrum(shared_datadir):
     assert len(all_spectra) == len(accum)
 
     for a, b in zip(all_spectra, accum):
-        # RN timsrust exports only ms2 ... so it has no ms level
-        # assert a.ms_level == b.ms_level
+        assert a.ms_level == b.ms_level
+        assert a.rt == b.rt
         assert a.collision_energy == b.collision_energy
+
+
+def test_iter_cycles(shared_datadir):
+    file = str(shared_datadir / "230711_idleflow_400-1000mz_25mz_diaPasef_10sec.d")
+    reader = timsrust_pyo3.FrameReader(file)
+
+    cycles = list(reader.iter_cycles())
+    assert len(cycles) == 15
+    assert sum(len(c) for c in cycles) == len(reader)
+    assert cycles[0].window_groups == list(range(1, 9))
+    assert cycles[0].frames[0].ms_level == timsrust_pyo3.MSLevel.MS1
+    assert all(f.ms_level == timsrust_pyo3.MSLevel.MS2 for f in cycles[0].frames[1:])
+    assert [f.window_group for f in cycles[0].frames[1:]] == list(range(1, 9))
+    assert all(a.rt < b.rt for a, b in zip(cycles, cycles[1:]))
+
+
+def test_iter_cycles_dda(shared_datadir):
+    reader = timsrust_pyo3.FrameReader(str(shared_datadir / "dda_test.d"))
+
+    cycles = list(reader.iter_cycles())
+    assert [c.frame_indices for c in cycles] == [[1, 2], [3, 4]]
+    assert all(c.window_groups == [] for c in cycles)
+    assert cycles[1].rt == cycles[1].frames[0].rt
@@ -0,0 +1,45 @@
+import pytest
+import timsrust_pyo3
+from timsrust_pyo3 import MSLevel
+
+
+def test_frame_pick_peaks(shared_datadir):
+    file = str(shared_datadir / "dda_test.d")
+    metadata = timsrust_pyo3.Metadata(file + "/analysis.tdf")
+    frame = timsrust_pyo3.FrameReader(file).read_frame(0)
+    peaks = frame.pick_peaks(metadata)
+    assert len(peaks) == 3
+    assert peaks.frame_index == frame.index
+    assert peaks.apex_intensities == [20.0, 12.0, 4.0]
+    assert peaks.summed_intensities == [68.0, 36.0, 6.0]
+    assert peaks.num_events == [4, 4, 2]
+    assert peaks.tof_indices[0] == pytest.approx((6 * 14 + 7 * 16 + 8 * 18 + 9 * 20) / 68)
+    assert peaks.mz_values[0] == pytest.approx(
+        sum(m * i for m, i in zip(metadata.resolve_mzs([6, 7, 8, 9]), [14, 16, 18, 20])) / 68,
+        rel=1e-3,
+    )
+    assert peaks.mobility_values[0] == pytest.approx(metadata.resolve_scans([3])[0])
+    assert peaks.mobility_widths[0] == 0.0
+    assert peaks.mobility_widths[1] > 0.0
+    assert peaks.mz_widths[0] == pytest.approx(
+        metadata.resolve_mzs([9])[0] - metadata.resolve_mzs([6])[0]
+    )
+    single = frame.pick_peaks(metadata, tof_tolerance=0, scan_tolerance=0)
+    assert len(single) == len(frame.tof_indices)
+    assert single.summed_intensities == single.apex_intensities
+    filtered = frame.pick_peaks(metadata, min_intensity=10)
+    assert filtered.apex_intensities == [20.0, 12.0]
+
+
+def test_reader_pick_peaks(shared_datadir):
+    file = str(shared_datadir / "dda_test.d")
+    metadata = timsrust_pyo3.Metadata(file + "/analysis.tdf")
+    reader = timsrust_pyo3.FrameReader(file)
+    all_peaks = reader.pick_peaks(min_intensity=5)
+    assert [p.frame_index for p in all_peaks] == [1, 2, 3, 4]
+    for peaks, frame in zip(all_peaks, reader.read_all_frames()):
+        expected = frame.pick_peaks(metadata, min_intensity=5)
+        assert peaks.mz_values == expected.mz_values
+        assert peaks.summed_intensities == expected.summed_intensities
+    ms1_peaks = reader.pick_peaks(ms_level=MSLevel.MS1)
+    assert [p.frame_index for p in ms1_peaks] == [1, 3]
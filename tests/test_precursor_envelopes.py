@@ -0,0 +1,59 @@
+import pytest
+import timsrust_pyo3
+from synthetic import ISOTOPE_SPACING, isotope_events, write_dda_dataset
+
+
+@pytest.fixture
+def dda_file(shared_datadir, tmp_path):
+    ms1 = isotope_events(600.3, 2, 1.0, 10000, [0.6, 1.0, 0.7, 0.3])
+    ms1 += isotope_events(450.2, 1, 0.8, 5000, [1.0, 0.5, 0.15])
+    ms1 += isotope_events(700.0, 1, 1.2, 3000, [1.0])
+    frames = [(1.0, 0, ms1), (1.1, 8, []), (2.0, 0, ms1), (2.1, 8, [])]
+    # The first precursor was picked at its most intense isotope, without charge
+    return write_dda_dataset(
+        shared_datadir / "dda_test.d",
+        tmp_path / "dda.d",
+        frames,
+        precursors=[
+            (2, 600.8, None, 1.0, 600.3 + ISOTOPE_SPACING / 2),
+            (4, 450.2, 1, 0.8),
+            (4, 700.0, None, 1.2),
+        ],
+        scans=(40, 60),
+        intensity=123.0,
+    )
+
+
+def test_detect_isotope_envelope(dda_file):
+    spectra = timsrust_pyo3.SpectrumReader(dda_file).read_all_spectra()
+    reader = timsrust_pyo3.FrameReader(dda_file)
+    envelope = reader.detect_isotope_envelope(spectra[0].precursor)
+    assert envelope.charge == 2
+    assert envelope.monoisotopic_mz == pytest.approx(600.3, abs=0.005)
+    assert len(envelope) == 4
+    assert envelope.intensities[1] > envelope.intensities[0]
+    assert envelope.intensity == pytest.approx(sum(envelope.intensities))
+    assert envelope.frame_index == 1
+    second = reader.detect_isotope_envelope(spectra[1].precursor)
+    assert second.charge == 1
+    assert second.frame_index == 3
+    assert reader.detect_isotope_envelope(spectra[2].precursor) is None
+
+
+def test_annotate_precursors(dda_file):
+    spectra = timsrust_pyo3.SpectrumReader(dda_file).read_all_spectra()
+    reader = timsrust_pyo3.FrameReader(dda_file)
+    precursors = [s.precursor for s in spectra]
+    annotated = reader.annotate_precursors(precursors)
+    assert annotated[0].charge == 2
+    assert annotated[0].mz == pytest.approx(600.3, abs=0.005)
+    assert annotated[0].intensity > 0
+    # Known values are kept unless overwritten
+    assert annotated[1].charge == 1
+    assert annotated[1].intensity == 123.0
+    assert annotated[2].mz == precursors[2].mz
+    overwritten = reader.annotate_precursors(precursors, overwrite=True)
+    assert overwritten[1].intensity != 123.0
+    annotated_spectra = reader.annotate_spectra(spectra)
+    assert [s.precursor.charge for s in annotated_spectra] == [a.charge for a in annotated]
+    assert annotated_spectra[0].mz_values == spectra[0].mz_values
@@ -0,0 +1,116 @@
+import shutil
+import sqlite3
+
+import pytest
+import timsrust_pyo3
+from timsrust_pyo3 import AcquisitionType, MSLevel
+
+
+@pytest.fixture
+def prm_file(shared_datadir, tmp_path):
+    # Turns the dda test data into a prm-PASEF run targeting its precursors
+    path = tmp_path / "prm_test.d"
+    shutil.copytree(shared_datadir / "dda_test.d", path)
+    connection = sqlite3.connect(path / "analysis.tdf")
+    connection.executescript(
+        """
+        UPDATE Frames SET ScanMode = 10 WHERE MsMsType = 8;
+        UPDATE Frames SET MsMsType = 10 WHERE MsMsType = 8;
+        CREATE TABLE PrmTargets (
+            Id INTEGER PRIMARY KEY,
+            ExternalId TEXT,
+            Time REAL NOT NULL,
+            OneOverK0 REAL NOT NULL,
+            MonoisotopicMz REAL NOT NULL,
+            Charge INTEGER,
+            Description TEXT
+        );
+        INSERT INTO PrmTargets
+            SELECT Id, 'target_' || Id, 0.3, 1.0, MonoisotopicMz, Charge, NULL
+            FROM Precursors;
+        CREATE TABLE PrmFrameMsMsInfo (
+            Frame INTEGER NOT NULL,
+            ScanNumBegin INTEGER NOT NULL,
+            ScanNumEnd INTEGER NOT NULL,
+            IsolationMz REAL NOT NULL,
+            IsolationWidth REAL NOT NULL,
+            CollisionEnergy REAL NOT NULL,
+            Target INTEGER NOT NULL
+        );
+        INSERT INTO PrmFrameMsMsInfo
+            SELECT Frame, ScanNumBegin, ScanNumEnd, IsolationMz, IsolationWidth,
+                CollisionEnergy, Precursor
+            FROM PasefFrameMsMsInfo;
+        DROP TABLE PasefFrameMsMsInfo;
+        """
+    )
+    connection.commit()
+    connection.close()
+    return str(path)
+
+
+def test_prm_frames(prm_file):
+    reader = timsrust_pyo3.FrameReader(prm_file)
+    frames = reader.read_all_frames()
+    assert all(f.acquisition_type == AcquisitionType.PRMPASEF for f in frames)
+    assert [f.ms_level for f in frames] == [MSLevel.MS1, MSLevel.MS2] * 2
+    assert frames[0].prm_targets == []
+    assert frames[1].prm_targets == [2, 1]
+    assert frames[1].quadrupole_settings.scan_starts == [1, 2]
+    assert frames[1].quadrupole_settings.isolation_mz == [501.5, 500.5]
+    assert [f.index for f in reader.read_prm_frames()] == [2, 4]
+    assert timsrust_pyo3.GlobalMetadata(prm_file).acquisition_type == AcquisitionType.PRMPASEF
+
+
+def test_prm_targets(prm_file):
+    targets = timsrust_pyo3.FrameReader(prm_file).read_prm_targets()
+    assert [t.id for t in targets] == [1, 2, 3]
+    assert [t.external_id for t in targets] == ["target_1", "target_2", "target_3"]
+    assert targets[1].monoisotopic_mz == 501.0
+    assert targets[1].charge == 3
+    assert targets[1].isolation_mz == 501.5
+    assert targets[1].lower_im < targets[1].upper_im
+
+
+
+def test_prm_targets_not_prm(shared_datadir):
+    reader = timsrust_pyo3.FrameReader(str(shared_datadir / "dda_test.d"))
+    assert reader.read_prm_frames() == []
+    with pytest.raises(ValueError):
+        reader.read_prm_targets()
+
+
+def test_prm_spectra(prm_file):
+    spectra = timsrust_pyo3.read_all_spectra(prm_file)
+    assert len(spectra) == 4
+    assert [s.prm_target for s in spectra] == [2, 1, 2, 3]
+    assert [s.frame_indices for s in spectra] == [[2], [2], [4], [4]]
+    assert spectra[0].precursor.mz == 501.0
+    assert spectra[0].precursor.charge == 3
+    assert spectra[0].isolation_mz == 501.5
+    assert all(s.ms_level == MSLevel.MS2 for s in spectra)
+
+    reader = timsrust_pyo3.SpectrumReader(prm_file, include_ms1=True)
+    assert [s.ms_level for s in reader.read_all_spectra()] == [
+        MSLevel.MS1,
+        MSLevel.MS2,
+        MSLevel.MS2,
+        MSLevel.MS1,
+        MSLevel.MS2,
+        MSLevel.MS2,
+    ]
+
+
+def test_prm_transitions(prm_file):
+    reader = timsrust_pyo3.FrameReader(prm_file)
+    spectrum = timsrust_pyo3.read_all_spectra(prm_file)[0]
+    fragment_mz = spectrum.mz_values[0]
+    transitions = reader.extract_prm_transitions(2, [fragment_mz, 5000.0])
+    assert transitions.target == 2
+    assert transitions.frame_indices == [2, 4]
+    assert len(transitions) == 2
+    assert transitions.intensities[0][0] > 0
+    assert transitions.intensities[1] == [0.0, 0.0]
+
+    with pytest.raises(ValueError):
+        reader.extract_prm_transitions(42, [fragment_mz])
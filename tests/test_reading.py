@@ -1,3 +1,5 @@
+import shutil
+import sqlite3
 from dataclasses import dataclass
 
 import pytest
@@ -179,3 +181,284 @@ def test_flattening(shared_datadir):
         assert all(isinstance(m, float) for m in f.mzs), "Not all mzs are floats"
         assert all(isinstance(i, float) for i in f.imss), "Not all imss are floats"
         assert all(i >= 0 for i in f.intensities)
+
+
+def test_spectrum_mobility(shared_datadir):
+    datafile = str(shared_datadir / "dda_test.d")
+    reader = timsrust_pyo3.SpectrumReader(datafile, with_mobility=True)
+    plain_reader = timsrust_pyo3.SpectrumReader(datafile)
+
+    for spec, plain_spec in zip(reader, plain_reader):
+        assert spec.mz_values == plain_spec.mz_values
+        assert len(spec.mobility_values) == len(spec.mz_values)
+        assert len(spec.scan_indices) == len(spec.mz_values)
+        assert all(0.5 <= im <= 1.5 for im in spec.mobility_values)
+        assert plain_spec.mobility_values is None
+
+    assert reader.get(0).scan_indices == [2]
+    assert reader.get(0).mobility_values == [1.0]
+
+    with pytest.raises(ValueError):
+        timsrust_pyo3.SpectrumReader(str(shared_datadir / "test.ms2"), with_mobility=True)
+
+
+def test_spectrum_acquisition_fields(shared_datadir):
+    specs = timsrust_pyo3.read_all_spectra(str(shared_datadir / "dda_test.d"))
+
+    assert all(s.ms_level == MSLevel.MS2 for s in specs)
+    assert [s.frame_indices for s in specs] == [[2], [2, 4], [4]]
+    assert specs[0].rt == pytest.approx(0.2)
+    assert specs[1].rt == pytest.approx(0.3)
+    assert all(s.lower_im <= s.upper_im for s in specs)
+
+    # minitdf files have no frames, so the precursor is used instead
+    specs = timsrust_pyo3.read_all_spectra(str(shared_datadir / "test.ms2"))
+    assert specs[0].frame_indices == []
+    assert specs[0].rt == specs[0].precursor.rt
+    assert specs[0].lower_im == specs[0].upper_im == specs[0].precursor.im
+
+
+def test_spectrum_reader_ms1(shared_datadir):
+    datafile = str(shared_datadir / "dda_test.d")
+    reader = timsrust_pyo3.SpectrumReader(datafile, include_ms1=True)
+    specs = reader.read_all_spectra()
+
+    assert len(reader) == len(specs) == 5
+    assert [s.ms_level for s in specs] == [
+        MSLevel.MS1,
+        MSLevel.MS2,
+        MSLevel.MS2,
+        MSLevel.MS1,
+        MSLevel.MS2,
+    ]
+    assert [s.index for s in specs] == list(range(5))
+    first_frames = [s.frame_indices[0] for s in specs]
+    assert first_frames == sorted(first_frames)
+    assert all(s.precursor is None for s in specs if s.ms_level == MSLevel.MS1)
+
+    sliced = timsrust_pyo3.SpectrumReader(
+        datafile, include_ms1=True, ms1_mobility_span_step=(0.5, 0.5)
+    )
+    ms1_specs = [s for s in sliced if s.ms_level == MSLevel.MS1]
+    assert len(ms1_specs) == 4
+    assert ms1_specs[0].frame_indices == ms1_specs[1].frame_indices == [1]
+    assert ms1_specs[0].lower_im >= ms1_specs[1].upper_im
+
+    nan, inf = float("nan"), float("inf")
+    for span_step in [(0.0, 0.5), (0.5, 0.0), (0.5, -0.1), (nan, 0.5), (0.5, inf)]:
+        with pytest.raises(ValueError):
+            timsrust_pyo3.SpectrumReader(
+                datafile, include_ms1=True, ms1_mobility_span_step=span_step
+            )
+
+
+def test_to_columns(shared_datadir):
+    datafile = str(shared_datadir / "dda_test.d")
+    specs = timsrust_pyo3.read_all_spectra(datafile)
+    columns = timsrust_pyo3.to_columns(specs)
+
+    assert columns["index"] == [s.index for s in specs]
+    assert columns["ms_level"] == ["MS2"] * len(specs)
+    assert columns["precursor_mz"] == [s.precursor.mz for s in specs]
+    assert columns["mz_values"] == [s.mz_values for s in specs]
+    assert columns["mobility_values"] == [None] * len(specs)
+
+    precursors = timsrust_pyo3.to_columns([s.precursor for s in specs])
+    assert precursors["precursor_index"] == [s.precursor.index for s in specs]
+
+    frames = timsrust_pyo3.FrameReader(datafile).read_all_frames()
+    columns = timsrust_pyo3.to_columns(frames)
+    assert columns["ms_level"] == ["MS1", "MS2", "MS1", "MS2"]
+    assert columns["tof_indices"] == [f.tof_indices for f in frames]
+
+    with pytest.raises(TypeError):
+        timsrust_pyo3.to_columns([frames[0], specs[0]])
+
+
+def test_to_polars(shared_datadir):
+    pl = pytest.importorskip("polars")
+    datafile = str(shared_datadir / "dda_test.d")
+    reader = timsrust_pyo3.SpectrumReader(datafile, include_ms1=True)
+    df = reader.to_polars()
+
+    assert df.height == len(reader)
+    assert df.schema["mz_values"] == pl.List(pl.Float64)
+    assert df["precursor_mz"].null_count() == 2
+
+    frames_df = timsrust_pyo3.FrameReader(datafile).to_polars()
+    assert frames_df.height == 4
+    assert frames_df.schema["tof_indices"] == pl.List(pl.UInt32)
+
+
+def test_to_arrow(shared_datadir):
+    datafile = str(shared_datadir / "dda_test.d")
+    specs = timsrust_pyo3.read_all_spectra(datafile)
+    table = timsrust_pyo3.to_arrow(specs)
+
+    assert len(table) == len(specs)
+    assert table.column_names[:3] == ["index", "ms_level", "rt"]
+    assert "arrow_array_stream" in repr(table.__arrow_c_stream__())
+
+    pa = pytest.importorskip("pyarrow")
+    frames = pa.table(timsrust_pyo3.FrameReader(datafile).to_arrow())
+    assert frames.schema.field("tof_indices").type == pa.list_(pa.uint32())
+    assert frames.column("ms_level").to_pylist() == ["MS1", "MS2", "MS1", "MS2"]
+
+
+def test_to_pandas(shared_datadir):
+    pytest.importorskip("pandas")
+    pytest.importorskip("pyarrow")
+    datafile = str(shared_datadir / "dda_test.d")
+    specs = timsrust_pyo3.read_all_spectra(datafile)
+    df = timsrust_pyo3.to_pandas(specs)
+
+    assert len(df) == len(specs)
+    assert str(df["precursor_charge"].dtype) == "uint64[pyarrow]"
+    assert list(df["index"]) == [s.index for s in specs]
+
+    frames_df = timsrust_pyo3.FrameReader(datafile).to_pandas()
+    assert list(frames_df["ms_level"]) == ["MS1", "MS2", "MS1", "MS2"]
+
+
+def test_frame_metadata(shared_datadir, tmp_path):
+    datafile = shared_datadir / "230711_idleflow_400-1000mz_25mz_diaPasef_10sec.d"
+    columns = timsrust_pyo3.Metadata(str(datafile / "analysis.tdf")).read_frame_metadata()
+
+    assert len(columns["index"]) == 131
+    assert columns["ms_level"].count("MS1") == 15
+    assert columns["num_peaks"][0] == 242412
+    assert all(x == 75.048 for x in columns["accumulation_time"])
+    assert all(x is not None for x in columns["pressure"])
+
+    # Older schemas lack some columns, which are then all None
+    columns = timsrust_pyo3.FrameReader(str(shared_datadir / "dda_test.d")).read_frame_metadata()
+    assert columns["index"] == [1, 2, 3, 4]
+    assert columns["msms_type"] == [0, 8, 0, 8]
+    assert columns["t1"] == [None] * 4
+
+    # prm-PASEF frames are MS2 as well, although timsrust does not know them
+    prm_file = tmp_path / "prm_test.d"
+    shutil.copytree(shared_datadir / "dda_test.d", prm_file)
+    with sqlite3.connect(prm_file / "analysis.tdf") as connection:
+        connection.execute("UPDATE Frames SET MsMsType = 10 WHERE MsMsType = 8")
+    columns = timsrust_pyo3.Metadata(str(prm_file / "analysis.tdf")).read_frame_metadata()
+    assert columns["ms_level"] == ["MS1", "MS2", "MS1", "MS2"]
+
+
+def test_global_metadata(shared_datadir):
+    datafile = shared_datadir / "230711_idleflow_400-1000mz_25mz_diaPasef_10sec.d"
+    metadata = timsrust_pyo3.GlobalMetadata(str(datafile))
+
+    assert metadata.acquisition_type == timsrust_pyo3.AcquisitionType.DIAPASEF
+    assert metadata.instrument_name == "timsTOF SCP"
+    assert metadata.instrument_serial_number == "1885933.00075"
+    assert metadata.acquisition_software_version == "4.1.12"
+    assert metadata.acquisition_date_time == "2023-07-11T12:28:01.167-08:00"
+    assert metadata.method_name == "diaPASEF_TALUS_400_1000_0.7cycle.m"
+    assert metadata.schema_version == (3, 7)
+    assert metadata.mz_acq_range == (100.0, 1600.0)
+    assert metadata.closed_properly
+    assert len(metadata.to_dict()) == len(metadata) == 35
+
+    metadata = timsrust_pyo3.Metadata(
+        str(shared_datadir / "dda_test.d" / "analysis.tdf")
+    ).read_global_metadata()
+    assert metadata.sample_name == "test"
+    assert metadata.instrument_name is None
+    assert metadata.get("MaxNumPeaksPerScan") == "58"
+
+
+def test_global_metadata_minitdf(shared_datadir):
+    metadata = timsrust_pyo3.GlobalMetadata(str(shared_datadir / "test.ms2"))
+
+    assert metadata.acquisition_type == timsrust_pyo3.AcquisitionType.Unknown
+    assert metadata.schema_type == "miniTSF"
+    assert metadata.schema_version == (2, 0)
+    assert metadata.instrument_vendor == "Bruker"
+    assert metadata.to_dict()["AnalysisId"] == "TODO_MIDIA"
+
+
+def test_query_tdf(shared_datadir):
+    file = str(shared_datadir / "dda_test.d")
+    table = timsrust_pyo3.query_tdf(file, "SELECT * FROM PasefFrameMsMsInfo")
+    assert table.column_names == [
+        "Frame",
+        "ScanNumBegin",
+        "ScanNumEnd",
+        "IsolationMz",
+        "IsolationWidth",
+        "CollisionEnergy",
+        "Precursor",
+    ]
+    assert len(table) == 4
+    assert table.to_pydict()["Frame"] == [2, 2, 4, 4]
+
+    reader = timsrust_pyo3.FrameReader(file)
+    table = reader.query("SELECT Id, Time FROM Frames WHERE MsMsType = ?", [8])
+    assert table.to_pydict() == {"Id": [2, 4], "Time": [0.2, 0.4]}
+    # The connection of the reader is kept between queries
+    table = reader.query("SELECT Id, NULL AS Missing, 'x' AS Text FROM Frames")
+    assert table.to_pydict()["Missing"] == [None] * 4
+    assert table.to_pydict()["Text"] == ["x"] * 4
+    mixed = reader.query("SELECT 1 AS Value UNION ALL SELECT 'a' ORDER BY 1")
+    assert mixed.to_pydict() == {"Value": ["1", "a"]}
+
+    metadata = timsrust_pyo3.Metadata(file + "/analysis.tdf")
+    table = metadata.query("SELECT Id FROM Frames WHERE Id > 10")
+    assert table.to_pydict() == {"Id": []}
+
+    with pytest.raises(ValueError):
+        timsrust_pyo3.query_tdf(file, "DELETE FROM Frames")
+    with pytest.raises(ValueError):
+        reader.query("SELECT * FROM NotATable")
+
+    pa = pytest.importorskip("pyarrow")
+    frames = pa.table(metadata.query("SELECT Id, Time FROM Frames"))
+    assert frames.schema.field("Id").type == pa.int64()
+    assert frames.schema.field("Time").type == pa.float64()
+
+
+def test_method_reader(shared_datadir):
+    file = shared_datadir / "230711_idleflow_400-1000mz_25mz_diaPasef_10sec.d"
+    method = timsrust_pyo3.MethodReader(str(file))
+    assert method.name == "diaPASEF_TALUS_400_1000_0.7cycle.m"
+    assert ("HyStar_LC", ["hystar.method"]) in method.submethods
+
+    assert method.dia_settings["SchemaType"] == "DIA"
+    assert len(method.dia_windows) == 24
+    assert len({w.window_group for w in method.dia_windows}) == 8
+    first = method.dia_windows[0]
+    assert (first.window_group, first.isolation_mz, first.isolation_width) == (1, 812.5, 25.0)
+    assert (first.lower_im, first.upper_im) == (1.01, 1.37)
+
+    # The DIA scheme of the method is the one used for the acquisition
+    windows = timsrust_pyo3.query_tdf(
+        str(file), "SELECT IsolationMz FROM DiaFrameMsMsWindows ORDER BY WindowGroup"
+    )
+    isolation_mzs = windows.to_pydict()["IsolationMz"]
+    assert sorted(isolation_mzs) == sorted(w.isolation_mz for w in method.dia_windows)
+
+    assert method.prm_settings["SchemaType"] == "PRM"
+    assert method.prm_targets == []
+    # The settings databases are opened immutable and never written to
+    method_folder = file / "diaPASEF_TALUS_400_1000_0.7cycle.m"
+    assert not list(method_folder.glob("*-shm")) + list(method_folder.glob("*-wal"))
+
+    (lc,) = method.lc_modules
+    assert lc.devices == ["Evosep One"]
+    assert lc.run_time == 21.0
+    assert lc.get("Method Name") == "60 samples per day"
+
+    acquisition = method.acquisition
+    assert acquisition.scan_mode == 9
+    assert acquisition.polarity == "positive"
+    assert acquisition.mz_range == (100.0, 1600.0)
+    assert acquisition.im_range == (0.64, 1.37)
+    assert acquisition.ramp_time == 75.0
+    assert acquisition.get("MSMS_Pasef_NumRampsPerCycle") == 4
+    assert "Mode_ScanMode" in acquisition.to_dict()
+
+
+def test_method_reader_missing(shared_datadir):
+    with pytest.raises(OSError):
+        timsrust_pyo3.MethodReader(str(shared_datadir / "dda_test.d"))
@@ -0,0 +1,69 @@
+import math
+
+import pytest
+import timsrust_pyo3
+from synthetic import fragment_events, write_dda_dataset
+
+
+@pytest.fixture
+def spectra(shared_datadir, tmp_path):
+    a = [(200.0, 1000), (300.0, 500), (400.0, 250)]
+    shifted = [(mz + 0.01, 2 * intensity) for mz, intensity in a]
+    b = [(200.0, 1000), (500.0, 800), (600.0, 600)]
+    frames = [(1.0, 0, fragment_events([(800.0, 1000)]))]
+    frames += [(1.0 + 0.1 * (i + 1), 8, fragment_events(x)) for i, x in enumerate([a, shifted, b])]
+    path = write_dda_dataset(
+        shared_datadir / "dda_test.d",
+        tmp_path / "dda.d",
+        frames,
+        precursors=[(i + 2, 800.0, 1) for i in range(3)],
+    )
+    return timsrust_pyo3.SpectrumReader(path).read_all_spectra()
+
+
+@pytest.mark.parametrize(
+    "metric", ["cosine", "contrast_angle", "entropy", "unweighted_entropy"]
+)
+def test_self_similarity(spectra, metric):
+    assert spectra[0].similarity(spectra[0], metric=metric) == pytest.approx(1.0)
+
+
+def test_tolerance_units(spectra):
+    a, shifted, _ = spectra
+    assert a.similarity(shifted, metric="matched_peaks") == 0
+    assert a.similarity(shifted, metric="cosine") == 0.0
+    assert a.similarity(shifted, metric="matched_peaks", tolerance=0.02, unit="da") == 3
+    assert a.similarity(shifted, tolerance=0.02, unit="da") == pytest.approx(1.0)
+    assert a.similarity(shifted, metric="entropy", tolerance=0.02, unit="da") == pytest.approx(1.0)
+    with pytest.raises(ValueError):
+        a.similarity(shifted, unit="mmu")
+    with pytest.raises(ValueError):
+        a.similarity(shifted, metric="dot")
+
+
+def test_partial_match(spectra):
+    a, _, b = spectra
+    assert a.similarity(b, metric="matched_peaks") == 1
+    norm = lambda x: math.sqrt(sum(v * v for v in x.intensities))
+    expected = a.intensities[0] * b.intensities[0] / (norm(a) * norm(b))
+    assert a.similarity(b) == pytest.approx(expected)
+    assert a.similarity(b, metric="contrast_angle") == pytest.approx(
+        1 - 2 * math.acos(expected) / math.pi
+    )
+    for metric in ["entropy", "unweighted_entropy"]:
+        assert 0.0 < a.similarity(b, metric=metric) < 1.0
+    assert a.similarity(b) == pytest.approx(b.similarity(a))
+
+
+def test_batch_similarity(spectra):
+    a = spectra[0]
+    assert a.similarities(spectra) == [a.similarity(x) for x in spectra]
+    matrix = timsrust_pyo3.pairwise_similarity(spectra, metric="entropy")
+    assert len(matrix) == 3
+    for i in range(3):
+        assert matrix[i][i] == pytest.approx(1.0)
+        for j in range(3):
+            assert matrix[i][j] == pytest.approx(matrix[j][i])
+            assert matrix[i][j] == pytest.approx(spectra[i].similarity(spectra[j], metric="entropy"))
+    one_vs_many = timsrust_pyo3.pairwise_similarity([a], spectra[1:], tolerance=0.02, unit="da")
+    assert one_vs_many == [a.similarities(spectra[1:], tolerance=0.02, unit="da")]
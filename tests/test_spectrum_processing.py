@@ -0,0 +1,170 @@
+import math
+
+import pytest
+import timsrust_pyo3
+from synthetic import ISOTOPE_SPACING, isotope_events, write_dda_dataset
+
+PROTON_MASS = 1.007276467
+
+
+@pytest.fixture
+def dda_file(shared_datadir, tmp_path):
+    ms1 = isotope_events(800.4, 2, 1.0, 10000, [1.0, 0.8])
+    fragments = isotope_events(300.1, 1, 1.0, 2000, [1.0])
+    fragments += isotope_events(500.25, 1, 1.0, 8000, [1.0, 0.5, 0.2])
+    fragments += isotope_events(700.4, 2, 1.0, 6000, [1.0, 0.8, 0.4])
+    frames = [(1.0, 0, ms1), (1.1, 8, fragments)]
+    return write_dda_dataset(
+        shared_datadir / "dda_test.d",
+        tmp_path / "dda.d",
+        frames,
+        precursors=[(2, 800.4, 2)],
+    )
+
+
+def test_deisotope(dda_file):
+    spectrum = timsrust_pyo3.SpectrumReader(dda_file).get(0)
+    assert spectrum.charges is None
+    assert len(spectrum.mz_values) == 7
+    deisotoped = spectrum.deisotope()
+    assert len(deisotoped.mz_values) == 3
+    assert deisotoped.mz_values == pytest.approx([300.1, 500.25, 700.4], abs=0.005)
+    assert deisotoped.charges == [0, 1, 2]
+    assert sum(deisotoped.intensities) == pytest.approx(sum(spectrum.intensities))
+    assert deisotoped.intensities[1] > spectrum.intensities[1]
+    # The original spectrum is left untouched
+    assert len(spectrum.mz_values) == 7
+
+
+def test_deisotope_mass_modes(dda_file):
+    spectrum = timsrust_pyo3.SpectrumReader(dda_file).get(0)
+    singly = spectrum.deisotope(mass_mode="singly_charged")
+    assert singly.mz_values[2] == pytest.approx(2 * 700.4 - PROTON_MASS, abs=0.01)
+    assert singly.mz_values[:2] == pytest.approx([300.1, 500.25], abs=0.005)
+    neutral = spectrum.deisotope(mass_mode="neutral")
+    assert neutral.mz_values[0] == pytest.approx(300.1 - PROTON_MASS, abs=0.005)
+    assert neutral.mz_values[2] == pytest.approx(2 * (700.4 - PROTON_MASS), abs=0.01)
+    with pytest.raises(ValueError):
+        spectrum.deisotope(mass_mode="mass")
+
+
+def test_deisotope_max_charge(dda_file):
+    spectrum = timsrust_pyo3.SpectrumReader(dda_file).get(0)
+    deisotoped = spectrum.deisotope(max_charge=1)
+    assert 2 not in deisotoped.charges
+    assert len(deisotoped.mz_values) > 3
+
+
+def test_reader_deisotoping(dda_file):
+    reader = timsrust_pyo3.SpectrumReader(dda_file).with_deisotoping(mass_mode="neutral")
+    spectrum = reader.read_all_spectra()[0]
+    assert spectrum.charges == [0, 1, 2]
+    assert spectrum.mz_values[1] == pytest.approx(500.25 - PROTON_MASS, abs=0.005)
+    expected = timsrust_pyo3.SpectrumReader(dda_file).get(0).deisotope(mass_mode="neutral")
+    assert spectrum.mz_values == expected.mz_values
+    with pytest.raises(ValueError):
+        timsrust_pyo3.SpectrumReader(dda_file).with_deisotoping(mass_mode="mass")
+
+
+def test_filter_intensity(dda_file):
+    spectrum = timsrust_pyo3.SpectrumReader(dda_file).get(0)
+    filtered = spectrum.filter_intensity(min_intensity=min(spectrum.intensities) + 1)
+    assert len(filtered.mz_values) == len(spectrum.mz_values) - 1
+    relative = spectrum.filter_intensity(min_relative_intensity=0.5)
+    assert min(relative.intensities) >= 0.5 * max(spectrum.intensities)
+    assert relative.mz_values == sorted(relative.mz_values)
+
+
+def test_top_n(dda_file):
+    spectrum = timsrust_pyo3.SpectrumReader(dda_file).get(0)
+    top = spectrum.top_n(2)
+    assert sorted(top.intensities) == sorted(spectrum.intensities)[-2:]
+    assert top.mz_values == sorted(top.mz_values)
+    windowed = spectrum.top_n(1, mz_window=100.0)
+    assert windowed.mz_values == pytest.approx([300.1, 500.25, 700.4], abs=0.005)
+    with pytest.raises(ValueError):
+        spectrum.top_n(1, mz_window=0.0)
+
+
+def test_remove_precursor_and_crop(dda_file):
+    spectrum = timsrust_pyo3.SpectrumReader(dda_file).get(0)
+    # No peak at the precursor m/z
+    assert spectrum.remove_precursor().mz_values == spectrum.mz_values
+    # A 30% window around 800.4 reaches down to the 700.4 cluster
+    removed = spectrum.remove_precursor(tolerance_ppm=300_000.0)
+    assert max(removed.mz_values) < 600.0
+    cropped = spectrum.crop_mz(400.0, 600.0)
+    assert len(cropped.mz_values) == 3
+    assert all(400.0 <= mz <= 600.0 for mz in cropped.mz_values)
+
+
+def test_normalize(dda_file):
+    spectrum = timsrust_pyo3.SpectrumReader(dda_file).get(0)
+    assert sum(spectrum.normalize("tic").intensities) == pytest.approx(1.0)
+    assert spectrum.normalize("sqrt").intensities == pytest.approx(
+        [x**0.5 for x in spectrum.intensities]
+    )
+    assert spectrum.normalize("log").intensities == pytest.approx(
+        [math.log1p(x) for x in spectrum.intensities]
+    )
+    with pytest.raises(ValueError):
+        spectrum.normalize("max")
+
+
+def test_reader_pipeline(dda_file):
+    reader = (
+        timsrust_pyo3.SpectrumReader(dda_file)
+        .with_mz_range(400.0, 800.0)
+        .with_deisotoping()
+        .with_top_n(1)
+        .with_normalization("tic")
+    )
+    spectrum = reader.get(0)
+    assert spectrum.mz_values == pytest.approx([500.25], abs=0.005)
+    assert spectrum.charges == [1]
+    assert spectrum.intensities == pytest.approx([1.0])
+
+
+def test_binned_spectrum(dda_file):
+    spectrum = timsrust_pyo3.SpectrumReader(dda_file).get(0)
+    binned = spectrum.to_binned(bin_width=1.0, min_mz=0.0, max_mz=1000.0)
+    assert binned.shape == (1, 1000)
+    assert binned.indptr == [0, len(binned.indices)]
+    # Isotopes of the 700.4 z=2 cluster fall in bins 700 and 701
+    assert binned.indices == [300, 500, 501, 502, 700, 701]
+    assert sum(binned.data) == pytest.approx(sum(spectrum.intensities))
+    assert binned.bin_centers()[300] == pytest.approx(300.5)
+    cropped = spectrum.to_binned(bin_width=0.5, min_mz=400.0, max_mz=600.0)
+    assert cropped.num_bins == 400
+    assert len(cropped.indices) == 3
+    with pytest.raises(ValueError):
+        spectrum.to_binned(bin_width=0.0)
+    with pytest.raises(ValueError):
+        spectrum.to_binned(min_mz=100.0, max_mz=100.0)
+
+
+def test_reader_binning(dda_file):
+    reader = timsrust_pyo3.SpectrumReader(dda_file).with_normalization("tic")
+    binned = reader.to_binned(bin_width=0.1, max_mz=1000.0)
+    assert len(binned) == 1
+    assert binned.spectrum_indices == [0]
+    assert sum(binned.data) == pytest.approx(1.0)
+    batches = list(reader.iter_binned(batch_size=1, bin_width=0.1, max_mz=1000.0))
+    assert len(batches) == 1
+    assert batches[0].indices == binned.indices
+    with pytest.raises(ValueError):
+        reader.iter_binned(batch_size=0)
+
+
+def test_binned_to_numpy_and_scipy(dda_file):
+    np = pytest.importorskip("numpy")
+    sparse = pytest.importorskip("scipy.sparse")
+    binned = timsrust_pyo3.SpectrumReader(dda_file).to_binned(max_mz=1000.0)
+    dense = binned.to_numpy()
+    assert dense.shape == (1, 1000)
+    assert dense[0, 300] > 0
+    assert dense.sum() == pytest.approx(sum(binned.data))
+    matrix = binned.to_scipy()
+    assert isinstance(matrix, sparse.csr_matrix)
+    assert matrix.shape == (1, 1000)
+    assert np.allclose(matrix.toarray(), dense)
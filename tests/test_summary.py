@@ -0,0 +1,56 @@
+import json
+import sqlite3
+
+import timsrust_pyo3
+from synthetic import write_dataset
+
+
+def test_summarize_dda(shared_datadir):
+    summary = timsrust_pyo3.summarize(str(shared_datadir / "dda_test.d"))
+    report = summary.to_dict()
+
+    assert report["acquisition_type"] == "DDAPASEF"
+    assert report["num_frames"] == 4
+    assert report["frames_per_ms_level"] == {"MS1": 2, "MS2": 2}
+    assert report["rt_range"] == [0.1, 0.4]
+    assert report["precursors"] == {"count": 3, "charges": {"2": 2, "3": 1}}
+    assert len(report["tic"]["rt"]) == 2
+    assert report["anomalies"] == {"empty_frames": [], "rt_gaps": []}
+    assert json.loads(summary.to_json()) == report
+    assert summary.to_html().startswith("<!DOCTYPE html>")
+
+
+def test_summarize_dia(shared_datadir):
+    datafile = shared_datadir / "230711_idleflow_400-1000mz_25mz_diaPasef_10sec.d"
+    report = timsrust_pyo3.summarize(str(datafile)).to_dict()
+
+    assert report["acquisition_type"] == "DIAPASEF"
+    assert report["frames_per_ms_level"] == {"MS1": 15, "MS2": 116}
+    assert report["precursors"] is None
+    assert [x["window_group"] for x in report["window_groups"]] == list(range(1, 9))
+    for group in report["window_groups"]:
+        assert all(w["lower_im"] < w["upper_im"] for w in group["windows"])
+
+    # With a factor of 0 every frame to frame step is a gap
+    report = timsrust_pyo3.summarize(str(datafile), rt_gap_factor=0.0).to_dict()
+    assert len(report["anomalies"]["rt_gaps"]) == 130
+
+
+def test_summarize_minitdf(shared_datadir):
+    report = timsrust_pyo3.summarize(str(shared_datadir / "test.ms2")).to_dict()
+
+    assert report["num_spectra"] == 3
+    assert report["precursors"]["count"] == 3
+    assert "num_frames" not in report
+
+
+def test_summary_tic_skips_frames_without_intensity(shared_datadir, tmp_path):
+    frames = [(rt, 0, [(50, 1000, 10)]) for rt in [1.0, 2.0, 3.0]]
+    path = write_dataset(shared_datadir / "dda_test.d", tmp_path / "run.d", frames)
+    connection = sqlite3.connect(path + "/analysis.tdf")
+    connection.execute("UPDATE Frames SET SummedIntensities = NULL WHERE Id = 2")
+    connection.commit()
+    connection.close()
+
+    html = timsrust_pyo3.summarize(path).to_html()
+    assert 'points="0.0,0.0 600.0,0.0"' in html